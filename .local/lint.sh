@@ -1,5 +1,4 @@
 #!/bin/sh
-cargo clippy --no-default-features --features left,serial --target thumbv6m-none-eabi
-cargo clippy --no-default-features --features left,hiddev --target thumbv6m-none-eabi
-cargo clippy --no-default-features --features right,serial --target thumbv6m-none-eabi
-cargo clippy --no-default-features --features right --target thumbv6m-none-eabi
+cargo clippy --no-default-features --features serial --target thumbv6m-none-eabi
+cargo clippy --no-default-features --features hiddev --target thumbv6m-none-eabi
+cargo clippy --no-default-features --target thumbv6m-none-eabi
@@ -0,0 +1,541 @@
+//! Line based command parsing for the usb-serial console, shared by both halves.
+//! Bytes are fed into a [`LineBuffer`], completed lines are parsed into a [`Command`],
+//! executing it is up to the side that received it.
+use core::fmt::Write;
+
+pub const LINE_CAPACITY: usize = 64;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum LineError {
+    /// The line didn't fit in the buffer, everything up to the next line break was discarded
+    Overflow,
+    InvalidUtf8,
+}
+
+pub struct LineBuffer<const N: usize> {
+    buf: [u8; N],
+    len: usize,
+    overflowed: bool,
+}
+
+impl<const N: usize> LineBuffer<N> {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            buf: [0u8; N],
+            len: 0,
+            overflowed: false,
+        }
+    }
+
+    /// Feed a single byte, returns the line when a line break is received.
+    /// Empty lines are swallowed, which also takes care of `\r\n`.
+    pub fn push(&mut self, byte: u8) -> Option<Result<&str, LineError>> {
+        match byte {
+            b'\r' | b'\n' => {
+                let len = core::mem::take(&mut self.len);
+                if core::mem::take(&mut self.overflowed) {
+                    return Some(Err(LineError::Overflow));
+                }
+                if len == 0 {
+                    return None;
+                }
+                Some(core::str::from_utf8(&self.buf[..len]).map_err(|_e| LineError::InvalidUtf8))
+            }
+            // Backspace and delete, terminals differ in which one they send
+            0x08 | 0x7f => {
+                self.len = self.len.saturating_sub(1);
+                None
+            }
+            _ => {
+                if self.len == N {
+                    self.overflowed = true;
+                } else if !self.overflowed {
+                    self.buf[self.len] = byte;
+                    self.len += 1;
+                }
+                None
+            }
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ConfigKey {
+    /// Forward debug output over serial
+    Output,
+    /// Power led, pretty useless but nice for checking that the console is alive
+    Led,
+    /// Seconds of inactivity before the oled and led turn off
+    Sleep,
+}
+
+impl ConfigKey {
+    pub const ALL: [Self; 3] = [Self::Output, Self::Led, Self::Sleep];
+
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|k| k.name() == name)
+    }
+
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            ConfigKey::Output => "output",
+            ConfigKey::Led => "led",
+            ConfigKey::Sleep => "sleep",
+        }
+    }
+
+    /// Parse a value for this key
+    /// # Errors
+    /// The value doesn't fit the key
+    pub fn parse_value(self, raw: &str) -> Result<ConfigValue, ParseError<'_>> {
+        match self {
+            ConfigKey::Output | ConfigKey::Led => parse_bool(raw).map(ConfigValue::Bool),
+            ConfigKey::Sleep => raw
+                .parse::<u32>()
+                .ok()
+                .filter(|secs| *secs > 0)
+                .map(ConfigValue::Seconds)
+                .ok_or(ParseError::InvalidValue(raw)),
+        }
+    }
+}
+
+fn parse_bool(raw: &str) -> Result<bool, ParseError<'_>> {
+    match raw {
+        "on" | "1" | "true" => Ok(true),
+        "off" | "0" | "false" => Ok(false),
+        _ => Err(ParseError::InvalidValue(raw)),
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ConfigValue {
+    Bool(bool),
+    Seconds(u32),
+}
+
+impl core::fmt::Display for ConfigValue {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ConfigValue::Bool(true) => f.write_str("on"),
+            ConfigValue::Bool(false) => f.write_str("off"),
+            ConfigValue::Seconds(secs) => write!(f, "{secs}s"),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Command<'a> {
+    Help(Option<&'a str>),
+    Status,
+    Layer,
+    Stats,
+    Chatter,
+    ChatterAll,
+    Trace,
+    Crash,
+    CrashClear,
+    Clock,
+    /// Switch both halves to the clock profile of this many MHz
+    ClockSet(u32),
+    ConfigList,
+    ConfigGet(ConfigKey),
+    ConfigSet(ConfigKey, ConfigValue),
+    Reboot,
+    UsbBoot,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ParseError<'a> {
+    UnknownCommand(&'a str),
+    UnknownConfigKey(&'a str),
+    InvalidValue(&'a str),
+    MissingArgument(&'static CommandSpec),
+    UnexpectedArgument(&'static CommandSpec, &'a str),
+}
+
+impl core::fmt::Display for ParseError<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ParseError::UnknownCommand(cmd) => {
+                write!(f, "unknown command '{cmd}', try 'help'")
+            }
+            ParseError::UnknownConfigKey(key) => {
+                write!(f, "unknown config key '{key}', try 'config'")
+            }
+            ParseError::InvalidValue(val) => write!(f, "invalid value '{val}'"),
+            ParseError::MissingArgument(spec) => {
+                write!(f, "missing argument, usage: {} {}", spec.name, spec.args)
+            }
+            ParseError::UnexpectedArgument(spec, arg) => {
+                write!(
+                    f,
+                    "unexpected argument '{arg}', usage: {} {}",
+                    spec.name, spec.args
+                )
+            }
+        }
+    }
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct CommandSpec {
+    pub name: &'static str,
+    pub args: &'static str,
+    pub help: &'static str,
+}
+
+const HELP: CommandSpec = CommandSpec {
+    name: "help",
+    args: "[command]",
+    help: "List commands, or show help for one",
+};
+
+const STATUS: CommandSpec = CommandSpec {
+    name: "status",
+    args: "",
+    help: "Show side, uptime, clock and sleep state",
+};
+
+const LAYER: CommandSpec = CommandSpec {
+    name: "layer",
+    args: "",
+    help: "Show the default layer, the one momentary layers fall back to",
+};
+
+const STATS: CommandSpec = CommandSpec {
+    name: "stats",
+    args: "",
+    help: "Show min/p50/p99/max of the latencies, and link counters",
+};
+
+const CHATTER: CommandSpec = CommandSpec {
+    name: "chatter",
+    args: "[all]",
+    help: "List this half's switches that bounce suspiciously, or every one that bounced",
+};
+
+const TRACE: CommandSpec = CommandSpec {
+    name: "trace",
+    args: "",
+    help: "Dump this half's recent events, oldest first",
+};
+
+const CRASH: CommandSpec = CommandSpec {
+    name: "crash",
+    args: "[clear]",
+    help: "Show this half's last panic, kept across resets until power is lost, or forget it",
+};
+
+const CLOCK: CommandSpec = CommandSpec {
+    name: "clock",
+    args: "[mhz]",
+    help: "Show the system clock and its profiles, or switch both halves to one",
+};
+
+const CONFIG: CommandSpec = CommandSpec {
+    name: "config",
+    args: "[get <key> | set <key> <value>]",
+    help: "List, read or change runtime configuration",
+};
+
+const REBOOT: CommandSpec = CommandSpec {
+    name: "reboot",
+    args: "[usb]",
+    help: "Reset the keyboard, 'usb' resets into the usb bootloader",
+};
+
+pub static COMMANDS: [CommandSpec; 10] = [
+    HELP, STATUS, LAYER, STATS, CHATTER, TRACE, CRASH, CLOCK, CONFIG, REBOOT,
+];
+
+#[must_use]
+pub fn find_command(name: &str) -> Option<&'static CommandSpec> {
+    COMMANDS.iter().find(|spec| spec.name == name)
+}
+
+/// Parse a line into a command
+/// # Errors
+/// Unknown command or bad arguments
+pub fn parse(line: &str) -> Result<Option<Command<'_>>, ParseError<'_>> {
+    let mut args = line.split_ascii_whitespace();
+    let Some(name) = args.next() else {
+        return Ok(None);
+    };
+    let spec = find_command(name).ok_or(ParseError::UnknownCommand(name))?;
+    let cmd = match spec.name {
+        "help" => Command::Help(args.next()),
+        "status" => Command::Status,
+        "layer" => Command::Layer,
+        "stats" => Command::Stats,
+        "chatter" => match args.next() {
+            None => Command::Chatter,
+            Some("all") => Command::ChatterAll,
+            Some(other) => return Err(ParseError::UnexpectedArgument(&CHATTER, other)),
+        },
+        "trace" => Command::Trace,
+        "crash" => match args.next() {
+            None => Command::Crash,
+            Some("clear") => Command::CrashClear,
+            Some(other) => return Err(ParseError::UnexpectedArgument(&CRASH, other)),
+        },
+        "clock" => match args.next() {
+            None => Command::Clock,
+            Some(raw) => Command::ClockSet(
+                raw.parse::<u32>()
+                    .map_err(|_e| ParseError::InvalidValue(raw))?,
+            ),
+        },
+        "config" => match args.next() {
+            None => Command::ConfigList,
+            Some("get") => Command::ConfigGet(parse_key(args.next())?),
+            Some("set") => {
+                let key = parse_key(args.next())?;
+                let raw = args.next().ok_or(ParseError::MissingArgument(&CONFIG))?;
+                Command::ConfigSet(key, key.parse_value(raw)?)
+            }
+            Some(other) => return Err(ParseError::UnexpectedArgument(&CONFIG, other)),
+        },
+        "reboot" => match args.next() {
+            None => Command::Reboot,
+            Some("usb") => Command::UsbBoot,
+            Some(other) => return Err(ParseError::UnexpectedArgument(&REBOOT, other)),
+        },
+        _ => return Err(ParseError::UnknownCommand(name)),
+    };
+    if let Some(extra) = args.next() {
+        return Err(ParseError::UnexpectedArgument(spec, extra));
+    }
+    Ok(Some(cmd))
+}
+
+fn parse_key(raw: Option<&str>) -> Result<ConfigKey, ParseError<'_>> {
+    let raw = raw.ok_or(ParseError::MissingArgument(&CONFIG))?;
+    ConfigKey::from_name(raw).ok_or(ParseError::UnknownConfigKey(raw))
+}
+
+/// Write help for all commands, or the one specified
+/// # Errors
+/// Writer fails
+pub fn write_help<W: Write>(w: &mut W, command: Option<&str>) -> core::fmt::Result {
+    match command {
+        None => {
+            for spec in &COMMANDS {
+                write_spec(w, spec)?;
+            }
+            Ok(())
+        }
+        Some(name) => match find_command(name) {
+            Some(spec) => write_spec(w, spec),
+            None => write!(w, "{}\r\n", ParseError::UnknownCommand(name)),
+        },
+    }
+}
+
+fn write_spec<W: Write>(w: &mut W, spec: &CommandSpec) -> core::fmt::Result {
+    if spec.args.is_empty() {
+        write!(w, "{} - {}\r\n", spec.name, spec.help)
+    } else {
+        write!(w, "{} {} - {}\r\n", spec.name, spec.args, spec.help)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feed<const N: usize>(
+        buf: &mut LineBuffer<N>,
+        input: &[u8],
+    ) -> Vec<Result<String, LineError>> {
+        let mut out = Vec::new();
+        for b in input {
+            if let Some(res) = buf.push(*b) {
+                out.push(res.map(str::to_string));
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn line_buffer_splits_lines() {
+        let mut buf: LineBuffer<16> = LineBuffer::new();
+        let lines = feed(&mut buf, b"status\r\nconfig get led\rhel");
+        assert_eq!(
+            vec![Ok("status".to_string()), Ok("config get led".to_string())],
+            lines
+        );
+        let lines = feed(&mut buf, b"p\n");
+        assert_eq!(vec![Ok("help".to_string())], lines);
+    }
+
+    #[test]
+    fn line_buffer_backspace() {
+        let mut buf: LineBuffer<16> = LineBuffer::new();
+        let lines = feed(&mut buf, b"statx\x08us\x7f\x7fus\r");
+        assert_eq!(vec![Ok("status".to_string())], lines);
+        // Backspace on an empty line does nothing
+        let lines = feed(&mut buf, b"\x08\x08ok\r");
+        assert_eq!(vec![Ok("ok".to_string())], lines);
+    }
+
+    #[test]
+    fn line_buffer_overflow_recovers() {
+        let mut buf: LineBuffer<4> = LineBuffer::new();
+        let lines = feed(&mut buf, b"abcdefgh\rabcd\r");
+        assert_eq!(
+            vec![Err(LineError::Overflow), Ok("abcd".to_string())],
+            lines
+        );
+    }
+
+    #[test]
+    fn line_buffer_bad_utf8() {
+        let mut buf: LineBuffer<4> = LineBuffer::new();
+        let lines = feed(&mut buf, &[0xff, 0xfe, b'\r', b'a', b'\r']);
+        assert_eq!(
+            vec![Err(LineError::InvalidUtf8), Ok("a".to_string())],
+            lines
+        );
+    }
+
+    #[test]
+    fn parse_simple() {
+        assert_eq!(Ok(None), parse("   "));
+        assert_eq!(Ok(Some(Command::Status)), parse("status"));
+        assert_eq!(Ok(Some(Command::Layer)), parse("  layer "));
+        assert_eq!(Ok(Some(Command::Stats)), parse("stats"));
+        assert_eq!(Ok(Some(Command::Chatter)), parse("chatter"));
+        assert_eq!(Ok(Some(Command::ChatterAll)), parse("chatter all"));
+        assert_eq!(Ok(Some(Command::Trace)), parse("trace"));
+        assert_eq!(Ok(Some(Command::Crash)), parse("crash"));
+        assert_eq!(Ok(Some(Command::CrashClear)), parse("crash clear"));
+        assert_eq!(Ok(Some(Command::Clock)), parse("clock"));
+        assert_eq!(Ok(Some(Command::ClockSet(133))), parse("clock 133"));
+        assert_eq!(Ok(Some(Command::Help(None))), parse("help"));
+        assert_eq!(
+            Ok(Some(Command::Help(Some("config")))),
+            parse("help config")
+        );
+        assert_eq!(Ok(Some(Command::Reboot)), parse("reboot"));
+        assert_eq!(Ok(Some(Command::UsbBoot)), parse("reboot usb"));
+    }
+
+    #[test]
+    fn parse_config() {
+        assert_eq!(Ok(Some(Command::ConfigList)), parse("config"));
+        assert_eq!(
+            Ok(Some(Command::ConfigGet(ConfigKey::Led))),
+            parse("config get led")
+        );
+        assert_eq!(
+            Ok(Some(Command::ConfigSet(
+                ConfigKey::Output,
+                ConfigValue::Bool(true)
+            ))),
+            parse("config set output on")
+        );
+        assert_eq!(
+            Ok(Some(Command::ConfigSet(
+                ConfigKey::Sleep,
+                ConfigValue::Seconds(120)
+            ))),
+            parse("config set sleep 120")
+        );
+        assert_eq!(
+            Err(ParseError::InvalidValue("0")),
+            parse("config set sleep 0")
+        );
+        assert_eq!(
+            Err(ParseError::InvalidValue("maybe")),
+            parse("config set led maybe")
+        );
+        assert_eq!(
+            Err(ParseError::UnknownConfigKey("bogus")),
+            parse("config get bogus")
+        );
+        assert_eq!(
+            Err(ParseError::MissingArgument(&CONFIG)),
+            parse("config get")
+        );
+        assert_eq!(
+            Err(ParseError::MissingArgument(&CONFIG)),
+            parse("config set led")
+        );
+        assert_eq!(
+            Err(ParseError::UnexpectedArgument(&CONFIG, "put")),
+            parse("config put led on")
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(Err(ParseError::UnknownCommand("led")), parse("led"));
+        // The old console matched on suffixes, make sure that doesn't happen anymore
+        assert_eq!(Err(ParseError::UnknownCommand("toggled")), parse("toggled"));
+        assert_eq!(
+            Err(ParseError::UnexpectedArgument(&STATUS, "now")),
+            parse("status now")
+        );
+        assert_eq!(
+            Err(ParseError::UnexpectedArgument(&REBOOT, "bootloader")),
+            parse("reboot bootloader")
+        );
+        assert_eq!(
+            Err(ParseError::UnexpectedArgument(&CHATTER, "some")),
+            parse("chatter some")
+        );
+        assert_eq!(
+            Err(ParseError::UnexpectedArgument(&CRASH, "now")),
+            parse("crash now")
+        );
+        assert_eq!(Err(ParseError::InvalidValue("fast")), parse("clock fast"));
+        assert_eq!(
+            Err(ParseError::UnexpectedArgument(&CLOCK, "48")),
+            parse("clock 133 48")
+        );
+    }
+
+    #[test]
+    fn usage_specs_match_names() {
+        for (spec, name) in [
+            (&CHATTER, "chatter"),
+            (&CRASH, "crash"),
+            (&CONFIG, "config"),
+            (&REBOOT, "reboot"),
+        ] {
+            assert_eq!(Some(spec), find_command(name));
+        }
+    }
+
+    #[test]
+    fn help_lists_all() {
+        let mut out = String::new();
+        write_help(&mut out, None).unwrap();
+        for spec in &COMMANDS {
+            assert!(out.contains(spec.name), "{out}");
+            assert!(out.contains(spec.help), "{out}");
+        }
+        let mut out = String::new();
+        write_help(&mut out, Some("reboot")).unwrap();
+        assert_eq!(
+            "reboot [usb] - Reset the keyboard, 'usb' resets into the usb bootloader\r\n",
+            out
+        );
+        let mut out = String::new();
+        write_help(&mut out, Some("nope")).unwrap();
+        assert_eq!("unknown command 'nope', try 'help'\r\n", out);
+    }
+
+    #[test]
+    fn config_keys_roundtrip() {
+        for key in ConfigKey::ALL {
+            assert_eq!(Some(key), ConfigKey::from_name(key.name()));
+        }
+        assert_eq!("on", ConfigValue::Bool(true).to_string());
+        assert_eq!("60s", ConfigValue::Seconds(60).to_string());
+    }
+}
@@ -0,0 +1,255 @@
+//! What's left of a panic after the reset it ends in. The report is plain bytes, it's kept
+//! in ram that isn't initialized at boot, so anything read back has to pass the magic and
+//! the checksum before it's believed.
+
+/// Bytes kept of the panicking file's path, the end of it if it's longer
+pub const FILE_LEN: usize = 48;
+/// Bytes kept of the panic message, it's cut off after that
+pub const MESSAGE_LEN: usize = 128;
+
+/// A panic that no boot has shown yet
+const MAGIC_NEW: u32 = 0x4352_5348;
+/// Shown on a boot, kept for the console until cleared or overwritten
+const MAGIC_SEEN: u32 = 0x5345_454E;
+
+#[derive(Debug, Copy, Clone)]
+#[repr(C)]
+pub struct CrashReport {
+    magic: u32,
+    checksum: u32,
+    line: u32,
+    column: u32,
+    core: u8,
+    file_len: u8,
+    message_len: u8,
+    /// Non-zero once the message didn't fit, nothing is added after that. Not a `bool`, every
+    /// field has to be fine with whatever bits were left in ram.
+    truncated: u8,
+    file: [u8; FILE_LEN],
+    message: [u8; MESSAGE_LEN],
+}
+
+impl CrashReport {
+    /// Empty, and not valid until [`Self::seal`]ed
+    #[must_use]
+    pub const fn new(core: u8) -> Self {
+        Self {
+            magic: 0,
+            checksum: 0,
+            line: 0,
+            column: 0,
+            core,
+            file_len: 0,
+            message_len: 0,
+            truncated: 0,
+            file: [0; FILE_LEN],
+            message: [0; MESSAGE_LEN],
+        }
+    }
+
+    /// Paths too long to fit keep their end, that's where the file name is
+    pub fn set_location(&mut self, file: &str, line: u32, column: u32) {
+        let mut start = file.len().saturating_sub(FILE_LEN);
+        while !file.is_char_boundary(start) {
+            start += 1;
+        }
+        let kept = &file.as_bytes()[start..];
+        self.file[..kept.len()].copy_from_slice(kept);
+        #[expect(clippy::cast_possible_truncation)]
+        let file_len = kept.len() as u8;
+        self.file_len = file_len;
+        self.line = line;
+        self.column = column;
+    }
+
+    /// Makes the report valid, nothing may change after
+    pub fn seal(&mut self) {
+        self.magic = MAGIC_NEW;
+        self.checksum = self.checksum();
+    }
+
+    /// The magic and the checksum match, and the lengths are in bounds
+    #[must_use]
+    pub fn is_valid(&self) -> bool {
+        (self.magic == MAGIC_NEW || self.magic == MAGIC_SEEN)
+            && usize::from(self.file_len) <= FILE_LEN
+            && usize::from(self.message_len) <= MESSAGE_LEN
+            && self.checksum == self.checksum()
+    }
+
+    /// Valid, and not shown by a boot yet
+    #[must_use]
+    pub fn is_new(&self) -> bool {
+        self.magic == MAGIC_NEW && self.is_valid()
+    }
+
+    /// The checksum doesn't cover the magic, a seen report stays valid
+    pub fn mark_seen(&mut self) {
+        self.magic = MAGIC_SEEN;
+    }
+
+    /// Makes the report invalid
+    pub fn clear(&mut self) {
+        self.magic = 0;
+    }
+
+    /// The core that panicked
+    #[must_use]
+    pub fn core(&self) -> u8 {
+        self.core
+    }
+
+    #[must_use]
+    pub fn file(&self) -> &str {
+        let len = usize::from(self.file_len).min(FILE_LEN);
+        core::str::from_utf8(&self.file[..len]).unwrap_or("?")
+    }
+
+    /// File name without directories or extension, `keymap` for `src/keymap.rs`
+    #[must_use]
+    pub fn file_stem(&self) -> &str {
+        let file = self.file();
+        let name = file.rsplit(['/', '\\']).next().unwrap_or(file);
+        name.split_once('.').map_or(name, |(stem, _ext)| stem)
+    }
+
+    #[must_use]
+    pub fn line(&self) -> u32 {
+        self.line
+    }
+
+    #[must_use]
+    pub fn column(&self) -> u32 {
+        self.column
+    }
+
+    #[must_use]
+    pub fn message(&self) -> &str {
+        let len = usize::from(self.message_len).min(MESSAGE_LEN);
+        core::str::from_utf8(&self.message[..len]).unwrap_or("?")
+    }
+
+    /// FNV-1a over everything but the magic and the checksum itself
+    fn checksum(&self) -> u32 {
+        let header = [self.core, self.file_len, self.message_len, self.truncated];
+        [
+            &self.line.to_le_bytes()[..],
+            &self.column.to_le_bytes(),
+            &header,
+            &self.file,
+            &self.message,
+        ]
+        .into_iter()
+        .flatten()
+        .fold(0x811C_9DC5, |hash: u32, byte| {
+            (hash ^ u32::from(*byte)).wrapping_mul(0x0100_0193)
+        })
+    }
+}
+
+/// The message, silently cut off at [`MESSAGE_LEN`], a panic handler has no use for an error
+impl core::fmt::Write for CrashReport {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        if self.truncated != 0 {
+            return Ok(());
+        }
+        let len = usize::from(self.message_len);
+        let mut take = s.len().min(MESSAGE_LEN - len);
+        while !s.is_char_boundary(take) {
+            take -= 1;
+        }
+        self.truncated = u8::from(take < s.len());
+        self.message[len..len + take].copy_from_slice(&s.as_bytes()[..take]);
+        #[expect(clippy::cast_possible_truncation)]
+        let message_len = (len + take) as u8;
+        self.message_len = message_len;
+        Ok(())
+    }
+}
+
+/// `core 1 panicked at src/keymap.rs:12:5: message`, a cut off message ends in `...`
+impl core::fmt::Display for CrashReport {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "core {} panicked at {}:{}:{}: {}{}",
+            self.core,
+            self.file(),
+            self.line,
+            self.column,
+            self.message(),
+            if self.truncated != 0 { "..." } else { "" }
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::fmt::Write;
+
+    fn report() -> CrashReport {
+        let mut report = CrashReport::new(1);
+        report.set_location("src/runtime/master.rs", 120, 9);
+        write!(report, "index out of bounds: {} >= {}", 7, 6).unwrap();
+        report.seal();
+        report
+    }
+
+    #[test]
+    fn sealed_report_is_valid() {
+        let mut report = report();
+        assert!(report.is_valid());
+        assert!(report.is_new());
+        assert_eq!(
+            "core 1 panicked at src/runtime/master.rs:120:9: index out of bounds: 7 >= 6",
+            report.to_string()
+        );
+        assert_eq!("master", report.file_stem());
+        report.mark_seen();
+        assert!(report.is_valid());
+        assert!(!report.is_new());
+        report.clear();
+        assert!(!report.is_valid());
+    }
+
+    #[test]
+    fn unsealed_or_damaged_is_invalid() {
+        let report = CrashReport::new(0);
+        assert!(!report.is_valid());
+        let mut report = self::report();
+        report.line += 1;
+        assert!(!report.is_valid());
+        let mut report = self::report();
+        report.message[MESSAGE_LEN - 1] ^= 1;
+        assert!(!report.is_valid());
+        let mut report = self::report();
+        report.file_len = u8::MAX;
+        assert!(!report.is_valid());
+    }
+
+    #[test]
+    fn long_message_is_cut_on_a_char_boundary() {
+        let mut report = CrashReport::new(0);
+        for _ in 0..MESSAGE_LEN - 1 {
+            report.write_str("a").unwrap();
+        }
+        report.write_str("åäö").unwrap();
+        report.write_str("more").unwrap();
+        assert_eq!(MESSAGE_LEN - 1, report.message().len());
+        assert!(report.message().chars().all(|c| c == 'a'));
+        assert!(report.to_string().ends_with("aaa..."));
+    }
+
+    #[test]
+    fn long_path_keeps_the_file_name() {
+        let mut report = CrashReport::new(0);
+        let path = "/home/someone/.cargo/git/checkouts/rp-hal-0123456789/rp2040-hal/src/sio.rs";
+        report.set_location(path, 1, 1);
+        assert!(path.ends_with(report.file()));
+        assert_eq!(FILE_LEN, report.file().len());
+        assert_eq!("sio", report.file_stem());
+        report.set_location("lib.rs", 1, 1);
+        assert_eq!("lib", report.file_stem());
+    }
+}
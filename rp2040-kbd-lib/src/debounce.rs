@@ -0,0 +1,516 @@
+//! Debouncing for key switches. A switch bounces for a few millis when it closes or opens,
+//! every algorithm here trades latency against how much of that (and of noise) gets through.
+//! They're fed a raw sample on every scan and keep the debounced state themselves.
+
+/// A point in time with microsecond resolution
+pub trait Timestamp: Copy {
+    /// Micros from `earlier` to `self`, `None` if `earlier` is later
+    fn micros_since(self, earlier: Self) -> Option<u64>;
+}
+
+impl Timestamp for u64 {
+    #[inline]
+    fn micros_since(self, earlier: Self) -> Option<u64> {
+        self.checked_sub(earlier)
+    }
+}
+
+/// A wrapping 32-bit counter, like the low half of the rp2040 timer,
+/// never more than about 71 minutes apart
+impl Timestamp for u32 {
+    #[inline]
+    fn micros_since(self, earlier: Self) -> Option<u64> {
+        Some(u64::from(self.wrapping_sub(earlier)))
+    }
+}
+
+#[inline]
+fn elapsed<T: Timestamp>(now: T, since: Option<T>, micros: u32) -> bool {
+    // A clock going backwards is as good as no earlier timestamp
+    since
+        .and_then(|since| now.micros_since(since))
+        .is_none_or(|diff| diff >= u64::from(micros))
+}
+
+pub trait Debouncer<T: Timestamp> {
+    /// Takes a raw sample, returns the new debounced state if it changed
+    fn update(&mut self, now: T, raw: bool) -> Option<bool>;
+
+    /// The debounced state, true is pressed
+    fn is_pressed(&self) -> bool;
+}
+
+/// Changes once the raw state has held for `window_micros`, both ways.
+/// Noise never gets through, every change is late by the window.
+#[derive(Debug, Copy, Clone)]
+pub struct SymmetricDefer<T> {
+    window_micros: u32,
+    pending_since: Option<T>,
+    pressed: bool,
+}
+
+impl<T> SymmetricDefer<T> {
+    #[must_use]
+    pub const fn new(window_micros: u32) -> Self {
+        Self {
+            window_micros,
+            pending_since: None,
+            pressed: false,
+        }
+    }
+}
+
+impl<T: Timestamp> Debouncer<T> for SymmetricDefer<T> {
+    #[inline]
+    fn update(&mut self, now: T, raw: bool) -> Option<bool> {
+        if raw == self.pressed {
+            self.pending_since = None;
+            return None;
+        }
+        let Some(since) = self.pending_since else {
+            self.pending_since = Some(now);
+            return None;
+        };
+        if !elapsed(now, Some(since), self.window_micros) {
+            return None;
+        }
+        self.pending_since = None;
+        self.pressed = raw;
+        Some(raw)
+    }
+
+    #[inline]
+    fn is_pressed(&self) -> bool {
+        self.pressed
+    }
+}
+
+/// Presses go through on the first sample, releases once the switch has been open
+/// for `window_micros`. Fast presses, but noise on an idle key is a press.
+#[derive(Debug, Copy, Clone)]
+pub struct EagerPressDeferRelease<T> {
+    release: SymmetricDefer<T>,
+}
+
+impl<T> EagerPressDeferRelease<T> {
+    #[must_use]
+    pub const fn new(window_micros: u32) -> Self {
+        Self {
+            release: SymmetricDefer::new(window_micros),
+        }
+    }
+}
+
+impl<T: Timestamp> Debouncer<T> for EagerPressDeferRelease<T> {
+    #[inline]
+    fn update(&mut self, now: T, raw: bool) -> Option<bool> {
+        if raw && !self.release.pressed {
+            self.release.pressed = true;
+            self.release.pending_since = None;
+            return Some(true);
+        }
+        self.release.update(now, raw)
+    }
+
+    #[inline]
+    fn is_pressed(&self) -> bool {
+        self.release.pressed
+    }
+}
+
+/// Every change goes through on the first sample, then the key ignores its switch
+/// for `window_micros`. Whatever state it's in after that is taken as is.
+#[derive(Debug, Copy, Clone)]
+pub struct EagerPerKey<T> {
+    window_micros: u32,
+    last_change: Option<T>,
+    pressed: bool,
+}
+
+impl<T> EagerPerKey<T> {
+    #[must_use]
+    pub const fn new(window_micros: u32) -> Self {
+        Self {
+            window_micros,
+            last_change: None,
+            pressed: false,
+        }
+    }
+}
+
+impl<T: Timestamp> Debouncer<T> for EagerPerKey<T> {
+    #[inline]
+    fn update(&mut self, now: T, raw: bool) -> Option<bool> {
+        if raw == self.pressed || !elapsed(now, self.last_change, self.window_micros) {
+            return None;
+        }
+        self.last_change = Some(now);
+        self.pressed = raw;
+        Some(raw)
+    }
+
+    #[inline]
+    fn is_pressed(&self) -> bool {
+        self.pressed
+    }
+}
+
+/// Samples the switch at most once per `sample_micros` and changes after `samples`
+/// samples in a row that differ from the debounced state. Doesn't depend on how often
+/// it's fed, as long as it's fed more often than it samples.
+#[derive(Debug, Copy, Clone)]
+pub struct SampleCounter<T> {
+    sample_micros: u32,
+    samples: u8,
+    count: u8,
+    last_sample: Option<T>,
+    pressed: bool,
+}
+
+impl<T> SampleCounter<T> {
+    #[must_use]
+    pub const fn new(sample_micros: u32, samples: u8) -> Self {
+        Self {
+            sample_micros,
+            samples,
+            count: 0,
+            last_sample: None,
+            pressed: false,
+        }
+    }
+}
+
+impl<T: Timestamp> Debouncer<T> for SampleCounter<T> {
+    #[inline]
+    fn update(&mut self, now: T, raw: bool) -> Option<bool> {
+        if !elapsed(now, self.last_sample, self.sample_micros) {
+            return None;
+        }
+        self.last_sample = Some(now);
+        if raw == self.pressed {
+            self.count = 0;
+            return None;
+        }
+        self.count = self.count.saturating_add(1);
+        if self.count < self.samples {
+            return None;
+        }
+        self.count = 0;
+        self.pressed = raw;
+        Some(raw)
+    }
+
+    #[inline]
+    fn is_pressed(&self) -> bool {
+        self.pressed
+    }
+}
+
+/// What a switch did before debouncing, for finding the ones that need replacing
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct ChatterStats {
+    /// Raw transitions seen
+    pub edges: u32,
+    /// Transitions that made it through the debouncer
+    pub changes: u32,
+    /// Shortest time between two raw transitions
+    pub min_interval_micros: Option<u32>,
+}
+
+impl ChatterStats {
+    /// Below this many rejected transitions a switch is never suspicious
+    pub const SUSPICIOUS_REJECTED: u32 = 8;
+
+    /// Raw transitions the debouncer threw away
+    #[inline]
+    #[must_use]
+    pub const fn rejected(&self) -> u32 {
+        self.edges.saturating_sub(self.changes)
+    }
+
+    /// Every switch bounces a little, this one throws away more transitions than it
+    /// lets through
+    #[inline]
+    #[must_use]
+    pub const fn is_suspicious(&self) -> bool {
+        let rejected = self.rejected();
+        rejected >= Self::SUSPICIOUS_REJECTED && rejected > self.changes
+    }
+}
+
+/// Wraps any debouncer and keeps [`ChatterStats`] on what it's fed
+#[derive(Debug, Copy, Clone)]
+pub struct ChatterTracker<D, T> {
+    inner: D,
+    raw: bool,
+    last_edge: Option<T>,
+    stats: ChatterStats,
+}
+
+impl<D, T> ChatterTracker<D, T> {
+    #[must_use]
+    pub const fn new(inner: D) -> Self {
+        Self {
+            inner,
+            raw: false,
+            last_edge: None,
+            stats: ChatterStats {
+                edges: 0,
+                changes: 0,
+                min_interval_micros: None,
+            },
+        }
+    }
+
+    /// The last raw sample
+    #[inline]
+    #[must_use]
+    pub const fn raw(&self) -> bool {
+        self.raw
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn stats(&self) -> ChatterStats {
+        self.stats
+    }
+}
+
+impl<D: Debouncer<T>, T: Timestamp> Debouncer<T> for ChatterTracker<D, T> {
+    #[inline]
+    fn update(&mut self, now: T, raw: bool) -> Option<bool> {
+        if raw != self.raw {
+            self.raw = raw;
+            self.stats.edges = self.stats.edges.wrapping_add(1);
+            if let Some(interval) = self
+                .last_edge
+                .and_then(|last| now.micros_since(last))
+                .map(|diff| u32::try_from(diff).unwrap_or(u32::MAX))
+            {
+                self.stats.min_interval_micros = Some(
+                    self.stats
+                        .min_interval_micros
+                        .map_or(interval, |min| min.min(interval)),
+                );
+            }
+            self.last_edge = Some(now);
+        }
+        let change = self.inner.update(now, raw);
+        if change.is_some() {
+            self.stats.changes = self.stats.changes.wrapping_add(1);
+        }
+        change
+    }
+
+    #[inline]
+    fn is_pressed(&self) -> bool {
+        self.inner.is_pressed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Scan period of the simulated matrix
+    const SCAN_MICROS: u64 = 100;
+    const WINDOW_MICROS: u32 = 5_000;
+
+    const NO_CHANGES: Vec<(u64, bool)> = Vec::new();
+
+    /// A raw signal as `(from_micros, state)` edges, sampled every [`SCAN_MICROS`] until `end`,
+    /// returns the debounced changes and when they happened
+    fn run(
+        debouncer: &mut impl Debouncer<u64>,
+        edges: &[(u64, bool)],
+        end: u64,
+    ) -> Vec<(u64, bool)> {
+        let mut out = Vec::new();
+        for now in (0..end).step_by(usize::try_from(SCAN_MICROS).unwrap()) {
+            let raw = edges
+                .iter()
+                .take_while(|(at, _)| *at <= now)
+                .last()
+                .is_some_and(|(_, state)| *state);
+            if let Some(state) = debouncer.update(now, raw) {
+                out.push((now, state));
+            }
+        }
+        out
+    }
+
+    /// Pressed at 1ms, released at 20ms, bouncing for 2ms on both
+    fn bouncy_press() -> Vec<(u64, bool)> {
+        vec![
+            (1_000, true),
+            (1_300, false),
+            (1_600, true),
+            (2_000, false),
+            (2_500, true),
+            (20_000, false),
+            (20_400, true),
+            (20_900, false),
+            (21_500, true),
+            (22_000, false),
+        ]
+    }
+
+    /// A single 300µs spike on an idle key
+    fn spike() -> Vec<(u64, bool)> {
+        vec![(1_000, true), (1_300, false)]
+    }
+
+    fn assert_single_press(out: &[(u64, bool)]) {
+        let states: Vec<bool> = out.iter().map(|(_, state)| *state).collect();
+        assert_eq!(vec![true, false], states, "{out:?}");
+    }
+
+    #[test]
+    fn symmetric_defer_waits_out_bounces() {
+        let out = run(
+            &mut SymmetricDefer::new(WINDOW_MICROS),
+            &bouncy_press(),
+            40_000,
+        );
+        assert_single_press(&out);
+        // Stable from the last bounce
+        assert_eq!(2_500 + u64::from(WINDOW_MICROS), out[0].0);
+        assert_eq!(22_000 + u64::from(WINDOW_MICROS), out[1].0);
+        let out = run(&mut SymmetricDefer::new(WINDOW_MICROS), &spike(), 40_000);
+        assert_eq!(NO_CHANGES, out);
+    }
+
+    #[test]
+    fn eager_press_defer_release_presses_on_first_contact() {
+        let mut debouncer = EagerPressDeferRelease::new(WINDOW_MICROS);
+        let out = run(&mut debouncer, &bouncy_press(), 40_000);
+        assert_single_press(&out);
+        assert_eq!(1_000, out[0].0);
+        assert_eq!(22_000 + u64::from(WINDOW_MICROS), out[1].0);
+        assert!(!debouncer.is_pressed());
+        // Noise is a press, it's released once it's gone
+        let out = run(
+            &mut EagerPressDeferRelease::new(WINDOW_MICROS),
+            &spike(),
+            40_000,
+        );
+        assert_eq!(
+            vec![(1_000, true), (1_300 + u64::from(WINDOW_MICROS), false)],
+            out
+        );
+    }
+
+    #[test]
+    fn eager_per_key_changes_on_first_contact() {
+        let out = run(
+            &mut EagerPerKey::new(WINDOW_MICROS),
+            &bouncy_press(),
+            40_000,
+        );
+        assert_eq!(vec![(1_000, true), (20_000, false)], out);
+        // Still open when the window ends, the release comes right after
+        let out = run(&mut EagerPerKey::new(WINDOW_MICROS), &spike(), 40_000);
+        assert_eq!(
+            vec![(1_000, true), (1_000 + u64::from(WINDOW_MICROS), false)],
+            out
+        );
+    }
+
+    #[test]
+    fn sample_counter_needs_consecutive_samples() {
+        // 5 samples, 1ms apart, bounces shorter than a sample can't add up
+        let mut debouncer = SampleCounter::new(1_000, 5);
+        let out = run(&mut debouncer, &bouncy_press(), 40_000);
+        assert_single_press(&out);
+        assert!(out[0].0 >= 2_500 + 4_000, "{out:?}");
+        assert!(out[1].0 > 22_000, "{out:?}");
+        let out = run(&mut SampleCounter::new(1_000, 5), &spike(), 40_000);
+        assert_eq!(NO_CHANGES, out);
+    }
+
+    #[test]
+    fn sample_counter_ignores_feed_rate() {
+        let mut debouncer: SampleCounter<u64> = SampleCounter::new(1_000, 3);
+        // Fed many times within one sample, counts once
+        for now in 0..1_000 {
+            assert_eq!(None, debouncer.update(now, now > 0));
+        }
+        assert_eq!(None, debouncer.update(1_000, true));
+        assert_eq!(None, debouncer.update(2_000, true));
+        assert_eq!(Some(true), debouncer.update(3_000, true));
+    }
+
+    #[test]
+    fn steady_input_never_changes() {
+        fn check(debouncer: &mut impl Debouncer<u64>) {
+            for now in 0..10_000 {
+                assert_eq!(None, debouncer.update(now * SCAN_MICROS, false));
+            }
+            assert!(!debouncer.is_pressed());
+        }
+        check(&mut SymmetricDefer::new(WINDOW_MICROS));
+        check(&mut EagerPressDeferRelease::new(WINDOW_MICROS));
+        check(&mut EagerPerKey::new(WINDOW_MICROS));
+        check(&mut SampleCounter::new(1_000, 5));
+    }
+
+    #[test]
+    fn wrapping_timestamps() {
+        let start = u32::MAX - 1_000;
+        let mut debouncer: SymmetricDefer<u32> = SymmetricDefer::new(WINDOW_MICROS);
+        assert_eq!(None, debouncer.update(start, true));
+        assert_eq!(
+            None,
+            debouncer.update(start.wrapping_add(WINDOW_MICROS - 1), true)
+        );
+        assert_eq!(
+            Some(true),
+            debouncer.update(start.wrapping_add(WINDOW_MICROS), true)
+        );
+        // A u64 clock going backwards doesn't stall a key
+        let mut debouncer: EagerPerKey<u64> = EagerPerKey::new(WINDOW_MICROS);
+        assert_eq!(Some(true), debouncer.update(10_000, true));
+        assert_eq!(Some(false), debouncer.update(5_000, false));
+    }
+
+    #[test]
+    fn chatter_tracker_counts_rejected() {
+        let mut debouncer = ChatterTracker::new(EagerPerKey::new(WINDOW_MICROS));
+        let out = run(&mut debouncer, &bouncy_press(), 40_000);
+        assert_eq!(vec![(1_000, true), (20_000, false)], out);
+        let stats = debouncer.stats();
+        assert_eq!(10, stats.edges);
+        assert_eq!(2, stats.changes);
+        assert_eq!(8, stats.rejected());
+        assert_eq!(Some(300), stats.min_interval_micros);
+        assert!(stats.is_suspicious());
+        assert!(!debouncer.is_pressed());
+    }
+
+    #[test]
+    fn chatter_tracker_clean_presses() {
+        let mut debouncer = ChatterTracker::new(SymmetricDefer::new(WINDOW_MICROS));
+        let edges: Vec<(u64, bool)> = (0..20)
+            .flat_map(|press| {
+                [
+                    (press * 100_000 + 1_000, true),
+                    (press * 100_000 + 50_000, false),
+                ]
+            })
+            .collect();
+        let out = run(&mut debouncer, &edges, 2_000_000);
+        assert_eq!(40, out.len());
+        let stats = debouncer.stats();
+        assert_eq!(0, stats.rejected());
+        assert!(!stats.is_suspicious());
+        // One bounce per press is normal too
+        let mut stats = ChatterStats {
+            edges: 80,
+            changes: 40,
+            min_interval_micros: Some(200),
+        };
+        assert!(!stats.is_suspicious());
+        stats.edges += 1;
+        assert!(stats.is_suspicious());
+    }
+}
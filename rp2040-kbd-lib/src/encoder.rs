@@ -0,0 +1,419 @@
+//! Quadrature decoding for rotary encoders.
+//! The two pins walk a Gray code, `00 -> 10 -> 11 -> 01 -> 00` is clockwise, every valid
+//! transition flips exactly one pin. Steps are summed up and only turned into a detent when
+//! the encoder comes back to rest, so a pin bouncing back and forth cancels itself out.
+//! A transition flipping both pins means a state was missed, the direction of that one is
+//! unknown, it's counted as a glitch and contributes nothing.
+
+/// How many Gray code steps the encoder moves per mechanical detent
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum StepsPerDetent {
+    /// Every state is a detent
+    One,
+    /// Detents at the starting state and its opposite
+    Two,
+    /// Detents only at the starting state
+    Four,
+}
+
+impl StepsPerDetent {
+    #[inline]
+    const fn steps(self) -> i8 {
+        match self {
+            StepsPerDetent::One => 1,
+            StepsPerDetent::Two => 2,
+            StepsPerDetent::Four => 4,
+        }
+    }
+}
+
+/// Position in the Gray code sequence by state, state is `a << 1 | b`
+const SEQUENCE_POSITION: [u8; 4] = [0, 3, 1, 2];
+
+#[derive(Debug, Copy, Clone)]
+pub struct QuadratureDecoder {
+    steps_per_detent: StepsPerDetent,
+    inverted: bool,
+    /// The state the encoder was in at the first sample, assumed to be a detent
+    rest: Option<u8>,
+    last: u8,
+    steps: i8,
+    glitches: u32,
+}
+
+impl QuadratureDecoder {
+    #[must_use]
+    pub const fn new(steps_per_detent: StepsPerDetent, inverted: bool) -> Self {
+        Self {
+            steps_per_detent,
+            inverted,
+            rest: None,
+            last: 0,
+            steps: 0,
+            glitches: 0,
+        }
+    }
+
+    /// Feed a sample of both pins, returns `Some(clockwise)` when the encoder has
+    /// settled in a new detent
+    pub fn update(&mut self, a: bool, b: bool) -> Option<bool> {
+        let state = (u8::from(a) << 1) | u8::from(b);
+        let Some(rest) = self.rest else {
+            self.rest = Some(state);
+            self.last = state;
+            return None;
+        };
+        if state == self.last {
+            return None;
+        }
+        let moved = SEQUENCE_POSITION[usize::from(state)]
+            .wrapping_sub(SEQUENCE_POSITION[usize::from(self.last)])
+            & 0b11;
+        self.last = state;
+        match moved {
+            1 => self.steps = self.steps.saturating_add(1),
+            3 => self.steps = self.steps.saturating_sub(1),
+            _ => self.glitches = self.glitches.wrapping_add(1),
+        }
+        if !self.is_detent(rest, state) {
+            return None;
+        }
+        let steps = core::mem::take(&mut self.steps);
+        // A missed state on the way can leave us short, half way there is enough
+        if steps.unsigned_abs() * 2 < self.steps_per_detent.steps().unsigned_abs() {
+            return None;
+        }
+        Some((steps > 0) != self.inverted)
+    }
+
+    /// Number of transitions that skipped a state since boot
+    #[inline]
+    #[must_use]
+    pub const fn glitches(&self) -> u32 {
+        self.glitches
+    }
+
+    #[inline]
+    fn is_detent(&self, rest: u8, state: u8) -> bool {
+        match self.steps_per_detent {
+            StepsPerDetent::One => true,
+            StepsPerDetent::Two => state == rest || state == rest ^ 0b11,
+            StepsPerDetent::Four => state == rest,
+        }
+    }
+}
+
+/// How many steps a detent is worth, by how soon it came after the one before it
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct AccelerationCurve {
+    /// Detents further apart than this are a single step
+    pub slow_micros: u64,
+    /// Detents this close together or closer are `max_steps`, linear in between
+    pub fast_micros: u64,
+    pub max_steps: u8,
+}
+
+impl AccelerationCurve {
+    pub const NONE: Self = Self {
+        slow_micros: 0,
+        fast_micros: 0,
+        max_steps: 1,
+    };
+
+    pub const DEFAULT: Self = Self {
+        slow_micros: 100_000,
+        fast_micros: 20_000,
+        max_steps: 6,
+    };
+
+    #[must_use]
+    pub fn steps(&self, interval_micros: u64) -> u8 {
+        if self.max_steps <= 1 || interval_micros >= self.slow_micros {
+            return 1;
+        }
+        if interval_micros <= self.fast_micros {
+            return self.max_steps;
+        }
+        let extra = u64::from(self.max_steps - 1) * (self.slow_micros - interval_micros)
+            / (self.slow_micros - self.fast_micros);
+        1 + u8::try_from(extra).unwrap_or(self.max_steps - 1)
+    }
+}
+
+/// Times detents to scale them with an [`AccelerationCurve`],
+/// turning around starts over from a single step
+#[derive(Debug, Copy, Clone, Default)]
+pub struct EncoderVelocity {
+    last: Option<(u64, bool)>,
+}
+
+impl EncoderVelocity {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self { last: None }
+    }
+
+    /// Signed steps for a detent at `now_micros`, positive is clockwise
+    pub fn detent(&mut self, now_micros: u64, clockwise: bool, curve: &AccelerationCurve) -> i8 {
+        let interval = match self.last {
+            Some((at, last_clockwise)) if last_clockwise == clockwise => {
+                now_micros.saturating_sub(at)
+            }
+            _ => u64::MAX,
+        };
+        self.last = Some((now_micros, clockwise));
+        let steps = i8::try_from(curve.steps(interval)).unwrap_or(i8::MAX);
+        if clockwise {
+            steps
+        } else {
+            -steps
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL_STEPS: [StepsPerDetent; 3] = [
+        StepsPerDetent::One,
+        StepsPerDetent::Two,
+        StepsPerDetent::Four,
+    ];
+
+    const NONE: Vec<bool> = Vec::new();
+
+    /// States in clockwise order
+    const CLOCKWISE: [(bool, bool); 4] =
+        [(false, false), (true, false), (true, true), (false, true)];
+
+    /// Walks `moves` Gray code steps from `start`, each move is +1 or -1 (or +2 for a skip),
+    /// returns the emitted detents
+    fn walk(decoder: &mut QuadratureDecoder, start: u8, moves: &[i8]) -> Vec<bool> {
+        let mut pos = i64::from(start);
+        let (a, b) = CLOCKWISE[usize::from(start)];
+        assert_eq!(None, decoder.update(a, b));
+        let mut out = Vec::new();
+        for m in moves {
+            pos += i64::from(*m);
+            let (a, b) = CLOCKWISE[usize::try_from(pos.rem_euclid(4)).unwrap()];
+            if let Some(cw) = decoder.update(a, b) {
+                out.push(cw);
+            }
+        }
+        out
+    }
+
+    fn net(detents: &[bool]) -> i64 {
+        detents.iter().map(|cw| if *cw { 1 } else { -1 }).sum()
+    }
+
+    #[test]
+    fn first_sample_is_rest() {
+        for steps in ALL_STEPS {
+            for (a, b) in CLOCKWISE {
+                let mut decoder = QuadratureDecoder::new(steps, false);
+                assert_eq!(None, decoder.update(a, b));
+                assert_eq!(None, decoder.update(a, b));
+            }
+        }
+    }
+
+    #[test]
+    fn full_turns_both_directions() {
+        for steps in ALL_STEPS {
+            let n = usize::from(steps.steps().unsigned_abs());
+            for inverted in [false, true] {
+                for start in 0..4 {
+                    for cw in [true, false] {
+                        let mut decoder = QuadratureDecoder::new(steps, inverted);
+                        let step = if cw { 1 } else { -1 };
+                        let moves = vec![step; n * 5];
+                        let detents = walk(&mut decoder, start, &moves);
+                        assert_eq!(vec![cw != inverted; 5], detents, "{steps:?} {start}");
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn bounce_on_every_step_is_ignored() {
+        for steps in ALL_STEPS {
+            let n = usize::from(steps.steps().unsigned_abs());
+            for start in 0..4 {
+                // Forward, back, forward for every step
+                let mut moves = Vec::new();
+                for _ in 0..n * 3 {
+                    moves.extend_from_slice(&[1, -1, 1]);
+                }
+                let mut decoder = QuadratureDecoder::new(steps, false);
+                let detents = walk(&mut decoder, start, &moves);
+                if steps == StepsPerDetent::One {
+                    // Every state is a detent, bounce can't be told apart from turning
+                    assert_eq!(3, net(&detents));
+                } else {
+                    assert_eq!(vec![true; 3], detents, "{steps:?} {start}");
+                }
+                assert_eq!(0, decoder.glitches());
+            }
+        }
+    }
+
+    #[test]
+    fn wiggle_without_reaching_a_detent() {
+        for steps in [StepsPerDetent::Two, StepsPerDetent::Four] {
+            let n = usize::from(steps.steps().unsigned_abs());
+            for start in 0..4 {
+                let mut decoder = QuadratureDecoder::new(steps, false);
+                // Up to one short of the next detent and back, a few times over
+                let mut moves = Vec::new();
+                for _ in 0..3 {
+                    moves.extend(core::iter::repeat_n(1, n - 1));
+                    moves.extend(core::iter::repeat_n(-1, n - 1));
+                    moves.extend(core::iter::repeat_n(-1, n - 1));
+                    moves.extend(core::iter::repeat_n(1, n - 1));
+                }
+                assert_eq!(NONE, walk(&mut decoder, start, &moves));
+            }
+        }
+    }
+
+    #[test]
+    fn skipped_state_is_a_glitch() {
+        // One state missed in the middle of a detent still gets there
+        let mut decoder = QuadratureDecoder::new(StepsPerDetent::Four, false);
+        assert_eq!(vec![true], walk(&mut decoder, 0, &[1, 2, 1]));
+        assert_eq!(1, decoder.glitches());
+        let mut decoder = QuadratureDecoder::new(StepsPerDetent::Four, false);
+        assert_eq!(vec![false], walk(&mut decoder, 2, &[-1, -2, -1]));
+        assert_eq!(1, decoder.glitches());
+        // Both pins flipping straight out of rest and back is nothing
+        let mut decoder = QuadratureDecoder::new(StepsPerDetent::Four, false);
+        assert_eq!(NONE, walk(&mut decoder, 1, &[2, 2]));
+        assert_eq!(2, decoder.glitches());
+        let mut decoder = QuadratureDecoder::new(StepsPerDetent::Two, false);
+        assert_eq!(NONE, walk(&mut decoder, 3, &[2]));
+        assert_eq!(1, decoder.glitches());
+        // Without a known direction every state is a guess, nothing is emitted
+        let mut decoder = QuadratureDecoder::new(StepsPerDetent::One, false);
+        assert_eq!(NONE, walk(&mut decoder, 0, &[2, 2, 2]));
+        assert_eq!(3, decoder.glitches());
+    }
+
+    #[test]
+    fn skipped_state_after_bounce_is_not_a_second_detent() {
+        let mut decoder = QuadratureDecoder::new(StepsPerDetent::Four, false);
+        // Detent, bounce out of it and skip straight back over
+        assert_eq!(
+            vec![true],
+            walk(&mut decoder, 0, &[1, 1, 1, 1, 1, -1, 2, -2])
+        );
+        assert_eq!(2, decoder.glitches());
+    }
+
+    /// Every walk of valid single steps ending at a detent emits exactly the detents
+    /// it moved, no matter how it got there
+    #[test]
+    fn exhaustive_valid_walks() {
+        const LEN: u32 = 12;
+        for steps in ALL_STEPS {
+            let n = i64::from(steps.steps());
+            for inverted in [false, true] {
+                for start in 0..4 {
+                    for len in 0..=LEN {
+                        for bits in 0..(1u32 << len) {
+                            let moves: Vec<i8> = (0..len)
+                                .map(|i| if bits & (1 << i) == 0 { 1 } else { -1 })
+                                .collect();
+                            let position: i64 = moves.iter().map(|m| i64::from(*m)).sum();
+                            let mut decoder = QuadratureDecoder::new(steps, inverted);
+                            let detents = walk(&mut decoder, start, &moves);
+                            assert_eq!(0, decoder.glitches());
+                            // Consecutive detents are always one apart, never more
+                            if position.rem_euclid(n) == 0 {
+                                let expect = if inverted { -position } else { position };
+                                assert_eq!(expect, net(&detents) * n, "{steps:?} {moves:?}");
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// With a skip anywhere in the walk, no more detents than positions passed are emitted
+    #[test]
+    fn exhaustive_walks_with_a_skip_never_overshoot() {
+        const LEN: u32 = 10;
+        for steps in ALL_STEPS {
+            let n = i64::from(steps.steps());
+            for start in 0..4 {
+                for len in 1..=LEN {
+                    for skip_at in 0..len {
+                        for bits in 0..(1u32 << len) {
+                            let moves: Vec<i8> = (0..len)
+                                .map(|i| {
+                                    let m = if bits & (1 << i) == 0 { 1 } else { -1 };
+                                    if i == skip_at {
+                                        m * 2
+                                    } else {
+                                        m
+                                    }
+                                })
+                                .collect();
+                            let mut decoder = QuadratureDecoder::new(steps, false);
+                            let detents = walk(&mut decoder, start, &moves);
+                            assert_eq!(1, decoder.glitches());
+                            let travelled: i64 = moves.iter().map(|m| i64::from(m.abs())).sum();
+                            let emitted = i64::try_from(detents.len()).unwrap();
+                            assert!(emitted * n <= travelled, "{steps:?} {moves:?}");
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn acceleration_curve_is_bounded_and_monotonic() {
+        for curve in [AccelerationCurve::NONE, AccelerationCurve::DEFAULT] {
+            let mut last = curve.max_steps;
+            for interval in (0..200_000).step_by(500) {
+                let steps = curve.steps(interval);
+                assert!(
+                    (1..=curve.max_steps).contains(&steps),
+                    "{curve:?} {interval}"
+                );
+                assert!(steps <= last, "{curve:?} {interval}");
+                last = steps;
+            }
+            assert_eq!(1, curve.steps(u64::MAX));
+        }
+        let curve = AccelerationCurve::DEFAULT;
+        assert_eq!(curve.max_steps, curve.steps(curve.fast_micros));
+        assert_eq!(1, curve.steps(curve.slow_micros));
+        assert_eq!(1, AccelerationCurve::NONE.steps(0));
+    }
+
+    #[test]
+    fn velocity_speeds_up_and_resets_on_turnaround() {
+        let curve = AccelerationCurve::DEFAULT;
+        let max = i8::try_from(curve.max_steps).unwrap();
+        let mut velocity = EncoderVelocity::new();
+        // First one has nothing to compare with
+        assert_eq!(1, velocity.detent(1_000_000, true, &curve));
+        assert_eq!(max, velocity.detent(1_010_000, true, &curve));
+        assert_eq!(1, velocity.detent(1_500_000, true, &curve));
+        // Spinning fast the other way starts slow
+        assert_eq!(-1, velocity.detent(1_505_000, false, &curve));
+        assert_eq!(-max, velocity.detent(1_510_000, false, &curve));
+        // A clock that went backwards isn't a huge speed
+        let mut velocity = EncoderVelocity::new();
+        velocity.detent(1_000, true, &curve);
+        assert_eq!(max, velocity.detent(0, true, &curve));
+        let mut velocity = EncoderVelocity::new();
+        velocity.detent(0, true, &AccelerationCurve::NONE);
+        assert_eq!(1, velocity.detent(1, true, &AccelerationCurve::NONE));
+    }
+}
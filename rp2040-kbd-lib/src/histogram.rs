@@ -0,0 +1,254 @@
+//! Fixed-bucket latency histograms. An average hides the slow outliers, which are the ones that
+//! get felt, these keep enough of the distribution for percentiles without storing samples.
+//! Buckets are log-linear: exact up to [`EXACT`], above that every power of two is split into
+//! [`SUB_BUCKETS`] buckets, so a percentile is off by at most an eighth of its value.
+
+/// Sub-buckets per power of two, as bits
+const SUB_BUCKET_BITS: u32 = 3;
+pub const SUB_BUCKETS: u32 = 1 << SUB_BUCKET_BITS;
+/// Values below this get a bucket each
+pub const EXACT: u32 = SUB_BUCKETS * 2;
+/// Enough to cover every `u32`
+pub const BUCKETS: usize = (EXACT + (u32::BITS - SUB_BUCKET_BITS - 1) * SUB_BUCKETS) as usize;
+
+#[inline]
+fn bucket_index(value: u32) -> usize {
+    if value < EXACT {
+        return value as usize;
+    }
+    let exp = u32::BITS - 1 - value.leading_zeros();
+    let sub = (value >> (exp - SUB_BUCKET_BITS)) & (SUB_BUCKETS - 1);
+    (EXACT + (exp - SUB_BUCKET_BITS - 1) * SUB_BUCKETS + sub) as usize
+}
+
+/// The largest value that lands in bucket `index`
+fn bucket_upper_bound(index: usize) -> u32 {
+    #[expect(clippy::cast_possible_truncation)]
+    let index = index as u32;
+    if index < EXACT {
+        return index;
+    }
+    let exp = (index - EXACT) / SUB_BUCKETS + SUB_BUCKET_BITS + 1;
+    let sub = (index - EXACT) % SUB_BUCKETS;
+    let width = 1 << (exp - SUB_BUCKET_BITS);
+    ((SUB_BUCKETS + sub) << (exp - SUB_BUCKET_BITS)) + (width - 1)
+}
+
+#[derive(Debug, Clone)]
+pub struct Histogram {
+    buckets: [u32; BUCKETS],
+    count: u32,
+    min: u32,
+    max: u32,
+}
+
+impl Histogram {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            buckets: [0; BUCKETS],
+            count: 0,
+            min: u32::MAX,
+            max: 0,
+        }
+    }
+
+    /// Stops counting at `u32::MAX` samples, instead of wrapping
+    #[inline]
+    pub fn record(&mut self, value: u32) {
+        if self.count == u32::MAX {
+            return;
+        }
+        self.count += 1;
+        self.buckets[bucket_index(value)] += 1;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    #[must_use]
+    pub fn min(&self) -> Option<u32> {
+        (self.count > 0).then_some(self.min)
+    }
+
+    #[must_use]
+    pub fn max(&self) -> Option<u32> {
+        (self.count > 0).then_some(self.max)
+    }
+
+    /// The value `percent` of the samples are at or below, rounded up to its bucket's bound.
+    /// Never outside of the recorded min and max, `None` without samples.
+    #[must_use]
+    pub fn percentile(&self, percent: u8) -> Option<u32> {
+        if self.count == 0 {
+            return None;
+        }
+        let percent = u64::from(percent.min(100));
+        let rank = (u64::from(self.count) * percent).div_ceil(100).max(1);
+        let mut seen = 0;
+        for (index, &count) in self.buckets.iter().enumerate() {
+            seen += u64::from(count);
+            if seen >= rank {
+                return Some(bucket_upper_bound(index).clamp(self.min, self.max));
+            }
+        }
+        Some(self.max)
+    }
+
+    #[must_use]
+    pub fn summary(&self) -> LatencySummary {
+        LatencySummary {
+            count: self.count,
+            min: self.min().unwrap_or(0),
+            p50: self.percentile(50).unwrap_or(0),
+            p99: self.percentile(99).unwrap_or(0),
+            max: self.max().unwrap_or(0),
+        }
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// What's shown of a [`Histogram`], small enough to send between cores
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct LatencySummary {
+    pub count: u32,
+    pub min: u32,
+    pub p50: u32,
+    pub p99: u32,
+    pub max: u32,
+}
+
+/// `min/p50/p99/max` in micros and the sample count, `-` without samples
+impl core::fmt::Display for LatencySummary {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        if self.count == 0 {
+            return f.write_str("-");
+        }
+        write!(
+            f,
+            "{}/{}/{}/{}us ({})",
+            self.min, self.p50, self.p99, self.max, self.count
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buckets_cover_every_value() {
+        assert_eq!(0, bucket_index(0));
+        assert_eq!(EXACT as usize - 1, bucket_index(EXACT - 1));
+        assert_eq!(EXACT as usize, bucket_index(EXACT));
+        assert_eq!(BUCKETS - 1, bucket_index(u32::MAX));
+        assert_eq!(u32::MAX, bucket_upper_bound(BUCKETS - 1));
+        // Every bucket starts right after the one before it ends
+        for index in 1..BUCKETS {
+            let lower = bucket_upper_bound(index - 1) + 1;
+            assert_eq!(index, bucket_index(lower), "bucket {index}");
+            assert_eq!(
+                index,
+                bucket_index(bucket_upper_bound(index)),
+                "bucket {index}"
+            );
+        }
+    }
+
+    #[test]
+    fn bucket_error_is_bounded() {
+        for value in (0..100_000).chain([u32::MAX / 3, u32::MAX - 1]) {
+            let bound = bucket_upper_bound(bucket_index(value));
+            assert!(bound >= value);
+            assert!(bound - value <= value / SUB_BUCKETS, "{value} -> {bound}");
+        }
+    }
+
+    #[test]
+    fn empty_has_no_percentiles() {
+        let hist = Histogram::new();
+        assert_eq!(None, hist.percentile(50));
+        assert_eq!(None, hist.min());
+        assert_eq!(None, hist.max());
+        assert_eq!(LatencySummary::default(), hist.summary());
+        assert_eq!("-", hist.summary().to_string());
+    }
+
+    #[test]
+    fn small_values_are_exact() {
+        let mut hist = Histogram::new();
+        for value in 1..=10 {
+            hist.record(value);
+        }
+        assert_eq!(Some(5), hist.percentile(50));
+        assert_eq!(Some(10), hist.percentile(99));
+        assert_eq!(Some(1), hist.percentile(0));
+        assert_eq!(Some(10), hist.percentile(100));
+    }
+
+    #[test]
+    fn outliers_show_in_p99_not_p50() {
+        let mut hist = Histogram::new();
+        for _ in 0..990 {
+            hist.record(10);
+        }
+        for _ in 0..10 {
+            hist.record(5_000);
+        }
+        let summary = hist.summary();
+        assert_eq!(1_000, summary.count);
+        assert_eq!(10, summary.min);
+        assert_eq!(10, summary.p50);
+        assert_eq!(10, summary.p99);
+        assert_eq!(5_000, summary.max);
+        hist.record(5_000);
+        // Rounded up to the bucket's bound, then clamped to what was actually seen
+        assert_eq!(Some(5_000), hist.percentile(99));
+    }
+
+    #[test]
+    fn percentile_within_bucket_error() {
+        let mut hist = Histogram::new();
+        for value in 1..=1_000 {
+            hist.record(value);
+        }
+        let p50 = hist.percentile(50).unwrap();
+        assert!((500..=500 + 500 / SUB_BUCKETS).contains(&p50), "{p50}");
+        let p99 = hist.percentile(99).unwrap();
+        assert!((990..=1_000).contains(&p99), "{p99}");
+    }
+
+    #[test]
+    fn reset_forgets_samples() {
+        let mut hist = Histogram::new();
+        hist.record(u32::MAX);
+        assert_eq!(Some(u32::MAX), hist.percentile(50));
+        hist.reset();
+        assert_eq!(0, hist.count());
+        hist.record(3);
+        assert_eq!(Some(3), hist.min());
+        assert_eq!(Some(3), hist.max());
+    }
+
+    #[test]
+    fn summary_display() {
+        let mut hist = Histogram::new();
+        hist.record(12);
+        hist.record(14);
+        assert_eq!("12/12/14/14us (2)", hist.summary().to_string());
+    }
+}
@@ -1,4 +1,14 @@
 #![cfg_attr(not(test), no_std)]
+pub mod cli;
+pub mod crash;
+pub mod debounce;
+pub mod encoder;
+pub mod histogram;
 pub mod keycodes;
 pub mod matrix;
 pub mod queue;
+pub mod remap;
+pub mod report;
+pub mod reset;
+pub mod split;
+pub mod trace;
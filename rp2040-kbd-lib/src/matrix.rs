@@ -1,3 +1,6 @@
+use core::marker::PhantomData;
+
+/// The largest half any supported board has, smaller boards use the top left of it
 pub const NUM_ROWS: u8 = 5;
 pub const NUM_COLS: u8 = 6;
 
@@ -50,6 +53,12 @@ impl MatrixIndex {
         Self(row_index.0 * NUM_COLS + col_index.0)
     }
 
+    /// Callers make sure it's below `NUM_ROWS * NUM_COLS`
+    #[inline]
+    pub(crate) const fn from_byte_unchecked(byte: u8) -> Self {
+        Self(byte)
+    }
+
     #[must_use]
     #[inline(always)]
     pub const fn byte(&self) -> u8 {
@@ -63,28 +72,48 @@ impl MatrixIndex {
     }
 }
 
-#[repr(transparent)]
-#[derive(Debug, Copy, Clone)]
-pub struct MatrixUpdate(u8);
+/// A change on one half. Keys fit in a single byte, encoders are identified by their index
+/// on the half and carry a second byte of signed steps.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct MatrixUpdate {
+    byte: u8,
+    steps: i8,
+}
 
 #[derive(Debug, Copy, Clone)]
 pub enum MatrixChange {
     KeyUpdate(MatrixIndex, bool),
-    EncoderUpdate(bool),
+    /// Steps are positive clockwise
+    EncoderUpdate {
+        index: u8,
+        steps: i8,
+    },
 }
 
 impl MatrixUpdate {
     const KEY_STATE_BIT: u8 = 0b0010_0000;
 
     const KEY_INDEX_MASK: u8 = 0b0001_1111;
-    const ENCODER_STATE_BIT: u8 = 0b1000_0000;
-    const ENCODER_ON: Self = Self(0b1100_0000);
-    const ENCODER_OFF: Self = Self(0b0100_0000);
+    /// Two bits, so that a single flipped bit can't turn a key into an encoder or back,
+    /// the frame length depends on it and the CRC can't catch that alone
+    const ENCODER_BITS: u8 = 0b1100_0000;
+    const ENCODER_INDEX_MASK: u8 = 0b0000_0111;
+
+    /// Most encoders a single half can have
+    pub const MAX_ENCODERS: u8 = Self::ENCODER_INDEX_MASK + 1;
+
+    /// Key updates only, an encoder update needs the byte after it, see [`Self::from_bytes`]
     #[must_use]
     pub fn from_byte(byte: u8) -> Option<Self> {
         let ind = byte & Self::KEY_INDEX_MASK;
-        // There are 2 illegal bit-patterns
-        (ind <= 30).then_some(Self(byte))
+        // There are 2 illegal bit-patterns, and the top bits are never set
+        (ind <= 30 && byte & !(Self::KEY_STATE_BIT | Self::KEY_INDEX_MASK) == 0)
+            .then_some(Self { byte, steps: 0 })
+    }
+
+    #[inline]
+    const fn is_encoder(first: u8) -> bool {
+        first & !Self::ENCODER_INDEX_MASK == Self::ENCODER_BITS
     }
 
     #[inline]
@@ -94,36 +123,303 @@ impl MatrixUpdate {
         if state {
             val |= Self::KEY_STATE_BIT;
         }
-        Self(val)
+        Self {
+            byte: val,
+            steps: 0,
+        }
     }
 
+    /// Steps are positive clockwise, zero steps or an index past [`Self::MAX_ENCODERS`]
+    /// is no update
     #[inline]
     #[must_use]
-    pub const fn from_rotary_change(clockwise: bool) -> Self {
-        if clockwise {
-            Self::ENCODER_ON
-        } else {
-            Self::ENCODER_OFF
+    pub const fn from_encoder_steps(index: u8, steps: i8) -> Option<Self> {
+        if steps == 0 || index >= Self::MAX_ENCODERS {
+            return None;
         }
+        Some(Self {
+            byte: Self::ENCODER_BITS | index,
+            steps,
+        })
     }
 
     #[inline]
     #[must_use]
     pub const fn interpret_byte(&self) -> MatrixChange {
-        let encoder = self.0 & Self::ENCODER_ON.0;
-        if encoder == 0 {
-            let idx = self.0 & Self::KEY_INDEX_MASK;
-            let state = self.0 & Self::KEY_STATE_BIT;
-            MatrixChange::KeyUpdate(MatrixIndex(idx), state != 0)
+        if Self::is_encoder(self.byte) {
+            MatrixChange::EncoderUpdate {
+                index: self.byte & Self::ENCODER_INDEX_MASK,
+                steps: self.steps,
+            }
         } else {
-            MatrixChange::EncoderUpdate(encoder & Self::ENCODER_STATE_BIT != 0)
+            let idx = self.byte & Self::KEY_INDEX_MASK;
+            let state = self.byte & Self::KEY_STATE_BIT;
+            MatrixChange::KeyUpdate(MatrixIndex(idx), state != 0)
         }
     }
 
+    /// The first byte on the wire, identifies a key update completely
     #[inline]
     #[must_use]
     pub const fn byte(self) -> u8 {
-        self.0
+        self.byte
+    }
+}
+
+/// Marks the start of a frame sent from the slave.
+pub const FRAME_START: u8 = 0xA5;
+/// Start marker, sequence number, payload, CRC.
+pub const FRAME_LEN: usize = 4;
+/// Most bytes a payload can carry after its first one.
+pub const MAX_EXTRA_LEN: usize = 4;
+pub const MAX_FRAME_LEN: usize = FRAME_LEN + MAX_EXTRA_LEN;
+
+/// A message sent in a frame, most fit in a single payload byte, the first byte tells
+/// if there's more.
+pub trait FramePayload: Sized {
+    /// Differs per direction, a half never syncs on frames it sent itself.
+    const START: u8;
+
+    fn to_byte(&self) -> u8;
+
+    fn from_byte(byte: u8) -> Option<Self>;
+
+    /// Bytes that follow a first payload byte of `first`, at most [`MAX_EXTRA_LEN`]
+    #[inline]
+    #[must_use]
+    fn extra_len(_first: u8) -> usize {
+        0
+    }
+
+    /// Fills the [`Self::extra_len`] bytes after the first one
+    #[inline]
+    fn write_extra(&self, _extra: &mut [u8]) {}
+
+    #[inline]
+    #[must_use]
+    fn from_bytes(first: u8, _extra: &[u8]) -> Option<Self> {
+        Self::from_byte(first)
+    }
+}
+
+impl FramePayload for MatrixUpdate {
+    const START: u8 = FRAME_START;
+
+    #[inline]
+    fn to_byte(&self) -> u8 {
+        self.byte
+    }
+
+    #[inline]
+    fn from_byte(byte: u8) -> Option<Self> {
+        MatrixUpdate::from_byte(byte)
+    }
+
+    #[inline]
+    fn extra_len(first: u8) -> usize {
+        usize::from(Self::is_encoder(first))
+    }
+
+    #[inline]
+    fn write_extra(&self, extra: &mut [u8]) {
+        // Empty for keys
+        if let [steps] = extra {
+            *steps = self.steps.to_le_bytes()[0];
+        }
+    }
+
+    #[inline]
+    fn from_bytes(first: u8, extra: &[u8]) -> Option<Self> {
+        if !Self::is_encoder(first) {
+            return Self::from_byte(first);
+        }
+        let steps = i8::from_le_bytes(extra.try_into().ok()?);
+        Self::from_encoder_steps(first & Self::ENCODER_INDEX_MASK, steps)
+    }
+}
+
+/// CRC-8 with polynomial 0x07, starting at 0xFF so that a run of zeroes isn't a valid frame.
+#[must_use]
+pub const fn crc8(bytes: &[u8]) -> u8 {
+    let mut crc = 0xFFu8;
+    let mut i = 0;
+    while i < bytes.len() {
+        crc ^= bytes[i];
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x07
+            } else {
+                crc << 1
+            };
+            bit += 1;
+        }
+        i += 1;
+    }
+    crc
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Frame {
+    bytes: [u8; MAX_FRAME_LEN],
+    len: usize,
+}
+
+impl core::ops::Deref for Frame {
+    type Target = [u8];
+
+    #[inline]
+    fn deref(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+}
+
+impl core::ops::DerefMut for Frame {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.bytes[..self.len]
+    }
+}
+
+impl IntoIterator for Frame {
+    type Item = u8;
+    type IntoIter = core::iter::Take<core::array::IntoIter<u8, MAX_FRAME_LEN>>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.bytes.into_iter().take(self.len)
+    }
+}
+
+pub struct FrameEncoder<P> {
+    seq: u8,
+    _payload: PhantomData<P>,
+}
+
+impl<P: FramePayload> FrameEncoder<P> {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            seq: 0,
+            _payload: PhantomData,
+        }
+    }
+
+    #[must_use]
+    pub fn encode(&mut self, payload: &P) -> Frame {
+        let seq = self.seq;
+        self.seq = self.seq.wrapping_add(1);
+        let first = payload.to_byte();
+        let len = FRAME_LEN + P::extra_len(first);
+        let mut bytes = [0u8; MAX_FRAME_LEN];
+        bytes[0] = P::START;
+        bytes[1] = seq;
+        bytes[2] = first;
+        payload.write_extra(&mut bytes[3..len - 1]);
+        bytes[len - 1] = crc8(&bytes[1..len - 1]);
+        Frame { bytes, len }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct FrameStats {
+    /// Frames that passed the checks
+    pub received: u32,
+    /// Frames that failed the CRC or carried an invalid payload
+    pub corrupt: u32,
+    /// Frames missing according to the sequence number
+    pub missed: u32,
+}
+
+/// Decodes frames one byte at a time, resyncing on the next start marker after a bad frame.
+pub struct FrameDecoder<P> {
+    // Bytes after the start marker
+    buf: [u8; MAX_FRAME_LEN - 1],
+    // 0 when waiting for a start marker, otherwise the number of frame bytes consumed
+    len: usize,
+    // Length of the current frame, known after its first payload byte
+    frame_len: usize,
+    expected_seq: Option<u8>,
+    stats: FrameStats,
+    _payload: PhantomData<P>,
+}
+
+impl<P: FramePayload> FrameDecoder<P> {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; MAX_FRAME_LEN - 1],
+            len: 0,
+            frame_len: FRAME_LEN,
+            expected_seq: None,
+            stats: FrameStats {
+                received: 0,
+                corrupt: 0,
+                missed: 0,
+            },
+            _payload: PhantomData,
+        }
+    }
+
+    pub fn push(&mut self, byte: u8) -> Option<P> {
+        if self.len == 0 {
+            if byte == P::START {
+                self.len = 1;
+            }
+            return None;
+        }
+        self.buf[self.len - 1] = byte;
+        self.len += 1;
+        if self.len == FRAME_LEN - 1 {
+            // A corrupt first byte can claim any length, the CRC catches it after
+            self.frame_len = FRAME_LEN + P::extra_len(byte).min(MAX_EXTRA_LEN);
+        }
+        if self.len < self.frame_len {
+            return None;
+        }
+        let body_len = self.frame_len - 1;
+        self.len = 0;
+        self.frame_len = FRAME_LEN;
+        let (body, crc) = self.buf[..body_len].split_at(body_len - 1);
+        let seq = body[0];
+        if let Some(msg) = (crc8(body) == crc[0])
+            .then(|| P::from_bytes(body[1], &body[2..]))
+            .flatten()
+        {
+            if let Some(expected) = self.expected_seq {
+                self.stats.missed = self
+                    .stats
+                    .missed
+                    .wrapping_add(u32::from(seq.wrapping_sub(expected)));
+            }
+            self.expected_seq = Some(seq.wrapping_add(1));
+            self.stats.received = self.stats.received.wrapping_add(1);
+            return Some(msg);
+        }
+        self.stats.corrupt = self.stats.corrupt.wrapping_add(1);
+        // The start marker may have been noise, and the real one among the bytes after it.
+        // Those can hold at most one short frame, whatever follows it is dropped and shows
+        // up as missed.
+        let rest = self.buf;
+        for b in &rest[..body_len] {
+            if let Some(msg) = self.push(*b) {
+                return Some(msg);
+            }
+        }
+        None
+    }
+
+    /// The next frame's sequence number is taken as is, instead of counting the gap to it as
+    /// missed. For after the link timed out, the other half may have restarted from 0.
+    #[inline]
+    pub fn resync(&mut self) {
+        self.expected_seq = None;
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn stats(&self) -> FrameStats {
+        self.stats
     }
 }
 
@@ -137,7 +433,7 @@ mod tests {
         const C1: ColIndex = ColIndex::from_value(4);
         const M1: MatrixIndex = MatrixIndex::from_row_col(R1, C1);
         const MU1: MatrixUpdate = MatrixUpdate::from_key_update(M1, true);
-        assert_eq!(0b00101010, MU1.0, "{:b}", MU1.0);
+        assert_eq!(0b00101010, MU1.byte, "{:b}", MU1.byte);
         const EXPECT_IND: u8 = R1.0 * NUM_COLS + C1.0;
         assert!(matches!(
             MU1.interpret_byte(),
@@ -148,6 +444,208 @@ mod tests {
             MU2.interpret_byte(),
             MatrixChange::KeyUpdate(MatrixIndex(EXPECT_IND), false)
         ));
-        assert_eq!(0b00001010, MU2.0, "{:b}", MU2.0);
+        assert_eq!(0b00001010, MU2.byte, "{:b}", MU2.byte);
+    }
+
+    #[test]
+    fn valid_updates_roundtrip() {
+        let mut keys = 0;
+        let mut encoders = 0;
+        for update in all_updates() {
+            let mut extra = [0u8; MAX_EXTRA_LEN];
+            let extra = &mut extra[..MatrixUpdate::extra_len(update.to_byte())];
+            update.write_extra(extra);
+            assert_eq!(
+                Some(update),
+                MatrixUpdate::from_bytes(update.to_byte(), extra)
+            );
+            match update.interpret_byte() {
+                MatrixChange::KeyUpdate(ind, state) => {
+                    assert_eq!(update, MatrixUpdate::from_key_update(ind, state));
+                    keys += 1;
+                }
+                MatrixChange::EncoderUpdate { index, steps } => {
+                    let back = MatrixUpdate::from_encoder_steps(index, steps).unwrap();
+                    assert_eq!(update, back);
+                    encoders += 1;
+                }
+            }
+        }
+        // 31 indices pressed and released, every encoder any number of steps but 0
+        assert_eq!(31 * 2, keys);
+        assert_eq!(usize::from(MatrixUpdate::MAX_ENCODERS) * 255, encoders);
+    }
+
+    #[test]
+    fn encoder_updates_are_never_keys() {
+        for index in 0..MatrixUpdate::MAX_ENCODERS {
+            let update = MatrixUpdate::from_encoder_steps(index, 1).unwrap();
+            assert!(MatrixUpdate::from_byte(update.byte()).is_none());
+        }
+        assert!(MatrixUpdate::from_encoder_steps(0, 0).is_none());
+        assert!(MatrixUpdate::from_encoder_steps(MatrixUpdate::MAX_ENCODERS, 1).is_none());
+    }
+
+    fn all_updates() -> impl Iterator<Item = MatrixUpdate> {
+        (0..=u8::MAX).filter_map(MatrixUpdate::from_byte).chain(
+            (0..MatrixUpdate::MAX_ENCODERS).flat_map(|index| {
+                (i8::MIN..=i8::MAX)
+                    .filter_map(move |steps| MatrixUpdate::from_encoder_steps(index, steps))
+            }),
+        )
+    }
+
+    // Xorshift, keeps the fuzzing deterministic without pulling in a dependency
+    struct Rng(u32);
+
+    impl Rng {
+        fn next(&mut self) -> u32 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 17;
+            self.0 ^= self.0 << 5;
+            self.0
+        }
+
+        fn byte(&mut self) -> u8 {
+            self.next().to_le_bytes()[0]
+        }
+    }
+
+    #[test]
+    fn frame_roundtrip() {
+        let mut enc: FrameEncoder<MatrixUpdate> = FrameEncoder::new();
+        let mut dec: FrameDecoder<MatrixUpdate> = FrameDecoder::new();
+        for _ in 0..3 {
+            for update in all_updates() {
+                let frame = enc.encode(&update);
+                let (last, head) = frame.split_last().unwrap();
+                for b in head {
+                    assert!(dec.push(*b).is_none());
+                }
+                assert_eq!(Some(update), dec.push(*last));
+            }
+        }
+        let stats = dec.stats();
+        assert_eq!(0, stats.corrupt);
+        assert_eq!(0, stats.missed);
+        assert_eq!(
+            u32::try_from(all_updates().count()).unwrap() * 3,
+            stats.received
+        );
+    }
+
+    #[test]
+    fn frame_rejects_bit_flips() {
+        // Steps that look like a start marker are resynced on after a bad frame, whatever
+        // follows them passes the CRC by chance now and then
+        for update in all_updates().filter(|u| u.steps.to_le_bytes()[0] != FRAME_START) {
+            let frame = FrameEncoder::new().encode(&update);
+            for bit in 0..frame.len() * 8 {
+                let mut corrupted = frame;
+                corrupted[bit / 8] ^= 1 << (bit % 8);
+                let mut dec: FrameDecoder<MatrixUpdate> = FrameDecoder::new();
+                // A flipped first byte can claim a longer frame, pad until it ends
+                for b in corrupted.into_iter().chain([0; MAX_EXTRA_LEN]) {
+                    assert!(dec.push(b).is_none(), "{update:?} bit {bit}");
+                }
+                // A flipped start marker is never seen as a frame, anything else is corrupt,
+                // the CRC can look like the next start marker
+                let corrupt = dec.stats().corrupt;
+                let expect = if bit < 8 { 0..=1 } else { 1..=2 };
+                assert!(expect.contains(&corrupt), "{update:?} bit {bit} {corrupt}");
+            }
+        }
+    }
+
+    #[test]
+    fn frame_counts_missed() {
+        let mut enc: FrameEncoder<MatrixUpdate> = FrameEncoder::new();
+        let mut dec: FrameDecoder<MatrixUpdate> = FrameDecoder::new();
+        let update = MatrixUpdate::from_encoder_steps(0, 1).unwrap();
+        for i in 0..10 {
+            let frame = enc.encode(&update);
+            if i % 3 == 1 {
+                continue;
+            }
+            for b in frame {
+                let _ = dec.push(b);
+            }
+        }
+        assert_eq!(3, dec.stats().missed);
+        assert_eq!(7, dec.stats().received);
+    }
+
+    #[test]
+    fn resync_forgets_the_gap_to_a_restarted_sender() {
+        let mut enc: FrameEncoder<MatrixUpdate> = FrameEncoder::new();
+        let mut dec: FrameDecoder<MatrixUpdate> = FrameDecoder::new();
+        let update = MatrixUpdate::from_encoder_steps(0, 1).unwrap();
+        for _ in 0..200 {
+            for b in enc.encode(&update) {
+                let _ = dec.push(b);
+            }
+        }
+        // The sender rebooted
+        let mut enc: FrameEncoder<MatrixUpdate> = FrameEncoder::new();
+        dec.resync();
+        for _ in 0..3 {
+            for b in enc.encode(&update) {
+                let _ = dec.push(b);
+            }
+        }
+        assert_eq!(0, dec.stats().missed);
+        assert_eq!(203, dec.stats().received);
+    }
+
+    #[test]
+    fn frame_fuzz_noise_then_resync() {
+        let mut rng = Rng(0x1234_5678);
+        let mut enc: FrameEncoder<MatrixUpdate> = FrameEncoder::new();
+        let mut dec: FrameDecoder<MatrixUpdate> = FrameDecoder::new();
+        let updates: Vec<MatrixUpdate> = all_updates().collect();
+        let mut phantoms = 0;
+        for _ in 0..20_000 {
+            let noise = rng.next() % 16;
+            for _ in 0..noise {
+                if dec.push(rng.byte()).is_some() {
+                    phantoms += 1;
+                }
+            }
+            let update = updates[rng.next() as usize % updates.len()];
+            // Two frames, the first may be swallowed by a partial frame of noise,
+            // the second has to come through intact
+            for b in enc.encode(&update) {
+                if let Some(u) = dec.push(b) {
+                    if phantoms == 0 {
+                        assert_eq!(update, u);
+                    }
+                }
+            }
+            let mut decoded = None;
+            for b in enc.encode(&update) {
+                if let Some(u) = dec.push(b) {
+                    decoded = Some(u);
+                }
+            }
+            assert_eq!(Some(update), decoded);
+            assert_eq!(0, dec.len, "decoder left mid-frame after a valid frame");
+        }
+        // Noise has to hit the start marker and a 1/256 CRC and a valid payload
+        assert!(phantoms < 20, "{phantoms}");
+    }
+
+    #[test]
+    fn frame_fuzz_random_bytes() {
+        let mut rng = Rng(0xdead_beef);
+        let mut dec: FrameDecoder<MatrixUpdate> = FrameDecoder::new();
+        let mut decoded = 0u32;
+        for _ in 0..1_000_000 {
+            if dec.push(rng.byte()).is_some() {
+                decoded += 1;
+            }
+        }
+        // About 1M / 256 starts, 1/256 of those pass the CRC
+        assert!(decoded < 50, "{decoded}");
+        assert_eq!(decoded, dec.stats().received);
     }
 }
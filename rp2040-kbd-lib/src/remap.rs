@@ -0,0 +1,226 @@
+//! The keymap is written for the lily58's matrix: letters on rows 0 to 2, mods, layer keys and
+//! space on row 3, and whatever's left over on row 4. Each board has a table per half that
+//! puts its switches on those positions, so a key does the same thing wherever a board
+//! wires it.
+use crate::matrix::{ColIndex, MatrixIndex, RowIndex, NUM_COLS, NUM_ROWS};
+
+const NUM_KEYS: usize = NUM_ROWS as usize * NUM_COLS as usize;
+
+/// What a board has at one of its matrix positions
+#[derive(Debug, Copy, Clone)]
+pub enum Slot {
+    NoSwitch,
+    /// The keymap's position for the switch
+    Key(MatrixIndex),
+    /// A switch the keymap has no spare position for, pressing it does nothing
+    Keyless,
+}
+
+/// Board index to what's there
+pub type Positions = [Slot; NUM_KEYS];
+
+type Row = [Slot; NUM_COLS as usize];
+
+const X: Slot = Slot::NoSwitch;
+
+/// The left half's reboot key, no other board's switch may land on it
+pub const LEFT_REBOOT_POSITION: MatrixIndex =
+    MatrixIndex::from_row_col(RowIndex::from_value(4), ColIndex::from_value(1));
+
+pub struct KeymapRemap {
+    pub left: Positions,
+    pub right: Positions,
+}
+
+impl KeymapRemap {
+    /// Where the keymap has the switch at `index` on the board, `None` if there's no key
+    #[inline]
+    #[must_use]
+    pub const fn keymap_position(&self, left: bool, index: u8) -> Option<MatrixIndex> {
+        let positions = if left { &self.left } else { &self.right };
+        if (index as usize) < NUM_KEYS {
+            if let Slot::Key(position) = positions[index as usize] {
+                return Some(position);
+            }
+        }
+        None
+    }
+
+    /// Bits, at the board's index, of the positions the table has a switch at
+    #[must_use]
+    pub const fn present(positions: &Positions) -> u32 {
+        let mut bits = 0;
+        let mut ind = 0;
+        while ind < NUM_KEYS {
+            if !matches!(positions[ind], Slot::NoSwitch) {
+                bits |= 1 << ind;
+            }
+            ind += 1;
+        }
+        bits
+    }
+}
+
+/// A switch at the keymap's `(row, col)`
+const fn k(row: u8, col: u8) -> Slot {
+    Slot::Key(MatrixIndex::from_row_col(
+        RowIndex::from_value(row),
+        ColIndex::from_value(col),
+    ))
+}
+
+/// The board's row is the keymap's row
+const fn same(row: u8) -> Row {
+    [
+        k(row, 0),
+        k(row, 1),
+        k(row, 2),
+        k(row, 3),
+        k(row, 4),
+        k(row, 5),
+    ]
+}
+
+const fn from_rows(rows: [Row; NUM_ROWS as usize]) -> Positions {
+    let mut positions = [X; NUM_KEYS];
+    let mut row = 0;
+    while row < NUM_ROWS as usize {
+        let mut col = 0;
+        while col < NUM_COLS as usize {
+            positions[row * NUM_COLS as usize + col] = rows[row][col];
+            col += 1;
+        }
+        row += 1;
+    }
+    positions
+}
+
+const LILY58_HALF: Positions = from_rows([
+    same(0),
+    same(1),
+    same(2),
+    same(3),
+    [X, k(4, 1), k(4, 2), k(4, 3), k(4, 4), k(4, 5)],
+]);
+
+/// The keymap's own matrix
+pub const LILY58: KeymapRemap = KeymapRemap {
+    left: LILY58_HALF,
+    right: LILY58_HALF,
+};
+
+/// Three thumb keys per half, outer to inner on columns 3 to 5. The letters fit as they are,
+/// the thumbs get ctrl, the layer key and space. Gui, alt, settings and row 4 have no switch.
+pub const CORNE: KeymapRemap = KeymapRemap {
+    left: from_rows([
+        same(0),
+        same(1),
+        same(2),
+        [X, X, X, k(3, 0), k(3, 4), k(3, 5)],
+        [X; NUM_COLS as usize],
+    ]),
+    right: from_rows([
+        same(0),
+        same(1),
+        same(2),
+        [X, X, X, k(3, 3), k(3, 4), k(3, 5)],
+        [X; NUM_COLS as usize],
+    ]),
+};
+
+/// A number row on top, the letters below it, then five thumb keys outer to inner and the
+/// encoder's push switch. The number row takes row 4 and settings, the reboot key isn't
+/// given to any of it, so the left's outer number key is left without a key.
+pub const SOFLE: KeymapRemap = KeymapRemap {
+    left: from_rows([
+        [Slot::Keyless, k(4, 0), k(4, 2), k(4, 3), k(4, 4), k(4, 5)],
+        same(0),
+        same(1),
+        same(2),
+        [k(3, 0), k(3, 1), k(3, 2), k(3, 4), k(3, 5), k(3, 3)],
+    ]),
+    right: from_rows([
+        [k(4, 0), k(4, 1), k(4, 2), k(4, 3), k(4, 4), k(3, 1)],
+        same(0),
+        same(1),
+        same(2),
+        [k(3, 0), k(3, 2), k(3, 3), k(3, 4), k(3, 5), k(4, 5)],
+    ]),
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(row: u8, col: u8) -> u8 {
+        row * NUM_COLS + col
+    }
+
+    fn keymap_byte(remap: &KeymapRemap, left: bool, row: u8, col: u8) -> Option<u8> {
+        remap
+            .keymap_position(left, at(row, col))
+            .map(|position| position.byte())
+    }
+
+    #[test]
+    fn switches_get_distinct_keymap_keys() {
+        for (positions, keys, keyless) in [
+            (&LILY58.left, 29, 0),
+            (&LILY58.right, 29, 0),
+            (&CORNE.left, 21, 0),
+            (&CORNE.right, 21, 0),
+            (&SOFLE.left, 29, 1),
+            (&SOFLE.right, 30, 0),
+        ] {
+            let mut seen = 0u32;
+            for slot in positions {
+                if let Slot::Key(position) = slot {
+                    // The keymap has a key at every position of the matrix
+                    assert!(position.index() < NUM_KEYS);
+                    assert_eq!(0, seen & (1 << position.byte()), "{position:?} twice");
+                    seen |= 1 << position.byte();
+                }
+            }
+            assert_eq!(keys, seen.count_ones());
+            assert_eq!(keys + keyless, KeymapRemap::present(positions).count_ones());
+        }
+    }
+
+    #[test]
+    fn only_the_lily58_has_a_switch_on_the_reboot_key() {
+        for remap in [&CORNE, &SOFLE] {
+            for ind in 0..NUM_ROWS * NUM_COLS {
+                assert_ne!(
+                    Some(LEFT_REBOOT_POSITION.byte()),
+                    remap.keymap_position(true, ind).map(|p| p.byte())
+                );
+            }
+        }
+        assert_eq!(
+            Some(LEFT_REBOOT_POSITION.byte()),
+            keymap_byte(&LILY58, true, 4, 1)
+        );
+    }
+
+    #[test]
+    fn missing_switches_map_to_nothing() {
+        assert_eq!(None, keymap_byte(&LILY58, true, 4, 0));
+        assert_eq!(None, keymap_byte(&LILY58, false, 4, 0));
+        assert_eq!(None, keymap_byte(&CORNE, true, 3, 2));
+        assert_eq!(None, keymap_byte(&CORNE, false, 4, 5));
+        assert_eq!(None, keymap_byte(&SOFLE, true, 0, 0));
+        assert!(LILY58.keymap_position(true, at(4, 5) + 1).is_none());
+    }
+
+    #[test]
+    fn moved_keys_keep_their_meaning() {
+        // Corne thumbs, ctrl on the left's outer one, the layer keys stay put
+        assert_eq!(Some(at(3, 0)), keymap_byte(&CORNE, true, 3, 3));
+        assert_eq!(Some(at(3, 4)), keymap_byte(&CORNE, true, 3, 4));
+        assert_eq!(Some(at(3, 3)), keymap_byte(&CORNE, false, 3, 3));
+        // Sofle letters are a row down
+        assert_eq!(Some(at(0, 0)), keymap_byte(&SOFLE, true, 1, 0));
+        assert_eq!(Some(at(2, 5)), keymap_byte(&SOFLE, false, 3, 5));
+        assert_eq!(Some(at(3, 5)), keymap_byte(&SOFLE, false, 4, 4));
+    }
+}
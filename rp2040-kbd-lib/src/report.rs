@@ -0,0 +1,386 @@
+//! Outbound keyboard reports. Reports are full snapshots of what's pressed, so the newest
+//! report is always correct, the queue exists to not lose transitions the host needs to see.
+//!
+//! A new report is merged into the last queued one when the host can't tell the difference:
+//! - A press that's only visible in the queued report can't be merged away, it'd never be seen.
+//! - A release followed by a press of the same key needs both reports, or the tap is lost.
+//! - Modifier before key: a key pressed in the queued report goes out with that report's
+//!   modifiers, a later modifier change can't be merged into it.
+//! - A modifier that's toggled in the queued report, then toggled back, needs both reports.
+//!
+//! When the queue is full the last report is replaced by the new one, that can lose a quick
+//! tap, but never leaves a key pressed on the host.
+
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct Report {
+    pub modifier: u8,
+    pub keycodes: [u8; 6],
+}
+
+impl Report {
+    pub const EMPTY: Self = Self {
+        modifier: 0,
+        keycodes: [0u8; 6],
+    };
+
+    /// Keys pressed, modifiers not counted
+    #[inline]
+    #[must_use]
+    pub fn key_count(self) -> u8 {
+        #[expect(clippy::cast_possible_truncation)]
+        let count = self.keycodes.iter().filter(|k| **k != 0).count() as u8;
+        count
+    }
+
+    #[inline]
+    fn contains(self, key: u8) -> bool {
+        key != 0 && self.keycodes.contains(&key)
+    }
+
+    /// Keys that are pressed in `self` but not in `prev`
+    #[inline]
+    fn pressed_since(self, prev: Report) -> impl Iterator<Item = u8> {
+        self.keycodes
+            .into_iter()
+            .filter(move |k| *k != 0 && !prev.contains(*k))
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct ReportStats {
+    /// Reports that were merged into an already queued report
+    pub coalesced: u32,
+    /// Reports that replaced a queued report that couldn't be merged, because the queue was full
+    pub dropped: u32,
+}
+
+pub struct ReportQueue<const N: usize> {
+    buf: [Report; N],
+    head: usize,
+    len: usize,
+    last_sent: Report,
+    stats: ReportStats,
+}
+
+impl<const N: usize> ReportQueue<N> {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            buf: [Report::EMPTY; N],
+            head: 0,
+            len: 0,
+            last_sent: Report::EMPTY,
+            stats: ReportStats {
+                coalesced: 0,
+                dropped: 0,
+            },
+        }
+    }
+
+    #[inline]
+    fn slot(&self, offset: usize) -> usize {
+        (self.head + offset) % N
+    }
+
+    /// Queue a report, never fails, see module docs for what happens to it
+    pub fn push(&mut self, report: Report) {
+        if self.len == 0 {
+            if report != self.last_sent {
+                self.buf[self.head] = report;
+                self.len = 1;
+            }
+            return;
+        }
+        let tail = self.slot(self.len - 1);
+        let pending = self.buf[tail];
+        if pending == report {
+            return;
+        }
+        let prev = if self.len > 1 {
+            self.buf[self.slot(self.len - 2)]
+        } else {
+            self.last_sent
+        };
+        if can_merge(prev, pending, report) {
+            self.buf[tail] = report;
+            self.stats.coalesced = self.stats.coalesced.wrapping_add(1);
+        } else if self.len == N {
+            if report == prev {
+                // The queued report is undone entirely
+                self.len -= 1;
+            } else {
+                self.buf[tail] = report;
+            }
+            self.stats.dropped = self.stats.dropped.wrapping_add(1);
+        } else {
+            let next = self.slot(self.len);
+            self.buf[next] = report;
+            self.len += 1;
+        }
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn peek(&self) -> Option<&Report> {
+        (self.len > 0).then(|| &self.buf[self.head])
+    }
+
+    /// The report from [`Self::peek`] was handed to the host
+    #[inline]
+    pub fn accept(&mut self) {
+        if self.len > 0 {
+            self.last_sent = self.buf[self.head];
+            self.head = self.slot(1);
+            self.len -= 1;
+        }
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn stats(&self) -> ReportStats {
+        self.stats
+    }
+}
+
+/// Times a half's key events from detection to the host, timestamps are the low half of a
+/// micros timer and may wrap. Every report only carries the earliest event that went into it,
+/// so a burst of keys counts as its first one.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct DeliveryTimer {
+    /// Detected, not in a report that went out yet
+    pending: Option<u32>,
+    /// In a report that was accepted, the host hasn't picked it up yet
+    in_flight: Option<u32>,
+}
+
+impl DeliveryTimer {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            pending: None,
+            in_flight: None,
+        }
+    }
+
+    /// Keeps the earliest event that's still pending
+    #[inline]
+    pub fn detected(&mut self, at: u32) {
+        if self.pending.is_none() {
+            self.pending = Some(at);
+        }
+    }
+
+    /// Nothing was queued for what's pending, it won't go out
+    #[inline]
+    pub fn discard_pending(&mut self) {
+        self.pending = None;
+    }
+
+    /// A report was accepted for the host. Replaces whatever was in flight, the host
+    /// picked that up before another report could be accepted.
+    #[inline]
+    pub fn accepted(&mut self) {
+        if let Some(at) = self.pending.take() {
+            self.in_flight = Some(at);
+        }
+    }
+
+    /// Micros from detection to the host picking up the report, once per report
+    #[inline]
+    pub fn polled(&mut self, at: u32) -> Option<u32> {
+        self.in_flight
+            .take()
+            .map(|detected| at.wrapping_sub(detected))
+    }
+}
+
+fn can_merge(prev: Report, pending: Report, next: Report) -> bool {
+    if pending.pressed_since(prev).any(|k| !next.contains(k)) {
+        return false;
+    }
+    if prev.pressed_since(pending).any(|k| next.contains(k)) {
+        return false;
+    }
+    let pending_mods = prev.modifier ^ pending.modifier;
+    let next_mods = pending.modifier ^ next.modifier;
+    if pending_mods & next_mods != 0 {
+        return false;
+    }
+    !(next_mods != 0 && pending.pressed_since(prev).next().is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHIFT: u8 = 0b0000_0010;
+    const ALT: u8 = 0b0000_0100;
+    const A: u8 = 4;
+    const B: u8 = 5;
+
+    fn report(modifier: u8, keys: &[u8]) -> Report {
+        let mut keycodes = [0u8; 6];
+        keycodes[..keys.len()].copy_from_slice(keys);
+        Report { modifier, keycodes }
+    }
+
+    fn drain<const N: usize>(queue: &mut ReportQueue<N>) -> Vec<Report> {
+        let mut out = Vec::new();
+        while let Some(r) = queue.peek() {
+            out.push(*r);
+            queue.accept();
+        }
+        out
+    }
+
+    #[test]
+    fn delivery_times_earliest_event() {
+        let mut timer = DeliveryTimer::new();
+        assert_eq!(None, timer.polled(5));
+        timer.detected(100);
+        timer.detected(120);
+        // Not accepted yet
+        assert_eq!(None, timer.polled(150));
+        timer.accepted();
+        timer.detected(200);
+        assert_eq!(Some(900), timer.polled(1_000));
+        assert_eq!(None, timer.polled(1_100));
+        timer.accepted();
+        assert_eq!(Some(1_000), timer.polled(1_200));
+    }
+
+    #[test]
+    fn delivery_discard_and_wrap() {
+        let mut timer = DeliveryTimer::new();
+        timer.detected(10);
+        timer.discard_pending();
+        timer.accepted();
+        assert_eq!(None, timer.polled(20));
+        timer.detected(u32::MAX - 9);
+        timer.accepted();
+        assert_eq!(Some(30), timer.polled(20));
+    }
+
+    #[test]
+    fn no_change_is_not_queued() {
+        let mut queue: ReportQueue<4> = ReportQueue::new();
+        queue.push(Report::EMPTY);
+        assert!(queue.is_empty());
+        queue.push(report(0, &[A]));
+        queue.push(report(0, &[A]));
+        assert_eq!(1, queue.len());
+        assert_eq!(ReportStats::default(), queue.stats());
+    }
+
+    #[test]
+    fn modifier_and_key_share_report() {
+        let mut queue: ReportQueue<4> = ReportQueue::new();
+        // temp_modify, add shift, then press the key
+        queue.push(report(SHIFT, &[]));
+        queue.push(report(SHIFT, &[A]));
+        assert_eq!(vec![report(SHIFT, &[A])], drain(&mut queue));
+        assert_eq!(1, queue.stats().coalesced);
+    }
+
+    #[test]
+    fn key_keeps_its_modifier() {
+        let mut queue: ReportQueue<4> = ReportQueue::new();
+        queue.push(report(SHIFT, &[A]));
+        // Restoring user mods can't be merged into the report that pressed the key
+        queue.push(report(0, &[A]));
+        assert_eq!(
+            vec![report(SHIFT, &[A]), report(0, &[A])],
+            drain(&mut queue)
+        );
+    }
+
+    #[test]
+    fn multiple_presses_share_report() {
+        let mut queue: ReportQueue<4> = ReportQueue::new();
+        queue.push(report(0, &[A]));
+        queue.push(report(0, &[A, B]));
+        assert_eq!(vec![report(0, &[A, B])], drain(&mut queue));
+    }
+
+    #[test]
+    fn tap_is_not_lost() {
+        let mut queue: ReportQueue<4> = ReportQueue::new();
+        queue.push(report(0, &[A]));
+        queue.push(report(0, &[]));
+        assert_eq!(vec![report(0, &[A]), report(0, &[])], drain(&mut queue));
+        assert_eq!(0, queue.stats().coalesced);
+    }
+
+    #[test]
+    fn retap_is_not_lost() {
+        let mut queue: ReportQueue<4> = ReportQueue::new();
+        queue.push(report(0, &[A]));
+        queue.accept();
+        queue.push(report(0, &[]));
+        queue.push(report(0, &[A]));
+        assert_eq!(vec![report(0, &[]), report(0, &[A])], drain(&mut queue));
+    }
+
+    #[test]
+    fn modifier_tap_is_not_lost() {
+        let mut queue: ReportQueue<4> = ReportQueue::new();
+        queue.push(report(SHIFT, &[]));
+        queue.push(report(0, &[]));
+        assert_eq!(vec![report(SHIFT, &[]), report(0, &[])], drain(&mut queue));
+    }
+
+    #[test]
+    fn releases_share_report() {
+        let mut queue: ReportQueue<4> = ReportQueue::new();
+        queue.push(report(SHIFT | ALT, &[A, B]));
+        queue.accept();
+        queue.push(report(SHIFT | ALT, &[B]));
+        queue.push(report(ALT, &[B]));
+        queue.push(report(ALT, &[]));
+        queue.push(report(0, &[]));
+        assert_eq!(vec![report(0, &[])], drain(&mut queue));
+        assert_eq!(3, queue.stats().coalesced);
+    }
+
+    #[test]
+    fn full_queue_keeps_newest_state() {
+        let mut queue: ReportQueue<2> = ReportQueue::new();
+        for _ in 0..8 {
+            queue.push(report(0, &[A]));
+            queue.push(report(0, &[]));
+        }
+        assert_eq!(2, queue.len());
+        let sent = drain(&mut queue);
+        // The last thing the host sees is everything released
+        assert_eq!(Some(&report(0, &[])), sent.last());
+        assert_eq!(7, queue.stats().dropped);
+    }
+
+    #[test]
+    fn wraps_around() {
+        let mut queue: ReportQueue<3> = ReportQueue::new();
+        for i in 0..32u8 {
+            let key = A + (i % 2);
+            queue.push(report(0, &[key]));
+            queue.push(report(0, &[]));
+            assert_eq!(
+                vec![report(0, &[key]), report(0, &[])],
+                drain(&mut queue),
+                "{i}"
+            );
+        }
+        assert_eq!(ReportStats::default(), queue.stats());
+    }
+}
@@ -0,0 +1,146 @@
+//! Why the chip last reset, pieced together at boot from the watchdog's reason register and
+//! a code written to one of its scratch registers before a reset is forced. The scratch
+//! registers keep their value through every reset but power-on.
+
+/// Marks a scratch value as one of ours, the rest is the code
+const SCRATCH_MAGIC: u32 = 0x5253_0000;
+const SCRATCH_MAGIC_MASK: u32 = 0xFFFF_0000;
+const KEY_CORE_STALLED: u32 = 1;
+/// Plus the core
+const PANIC: u32 = 0x10;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ResetReason {
+    /// Power-on, the run pin, or a reset that didn't go through the watchdog
+    PowerOn,
+    /// The watchdog wasn't fed in time, the admin core stopped
+    WatchdogTimeout,
+    /// The key core stopped looping, the admin core reset the chip
+    KeyCoreStalled,
+    /// A panic on this core, see [`crate::crash`]
+    Panic(u8),
+    /// Forced through the watchdog without saying why, the usb bootloader does that
+    Forced,
+}
+
+impl ResetReason {
+    /// What the watchdog's reason register and scratch value say after a reset
+    #[must_use]
+    pub fn from_watchdog(timed_out: bool, forced: bool, scratch: u32) -> Self {
+        if timed_out {
+            return ResetReason::WatchdogTimeout;
+        }
+        if !forced {
+            return ResetReason::PowerOn;
+        }
+        if scratch & SCRATCH_MAGIC_MASK != SCRATCH_MAGIC {
+            return ResetReason::Forced;
+        }
+        match scratch & !SCRATCH_MAGIC_MASK {
+            KEY_CORE_STALLED => ResetReason::KeyCoreStalled,
+            code if code & !1 == PANIC => ResetReason::Panic(u8::from(code & 1 != 0)),
+            _ => ResetReason::Forced,
+        }
+    }
+
+    /// For the scratch register before forcing a reset, `None` for the ones that aren't forced
+    /// with a reason
+    #[must_use]
+    pub fn scratch(self) -> Option<u32> {
+        let code = match self {
+            ResetReason::KeyCoreStalled => KEY_CORE_STALLED,
+            ResetReason::Panic(core) => PANIC | u32::from(core & 1),
+            ResetReason::PowerOn | ResetReason::WatchdogTimeout | ResetReason::Forced => {
+                return None
+            }
+        };
+        Some(SCRATCH_MAGIC | code)
+    }
+
+    /// Something went wrong before the reset
+    #[must_use]
+    pub fn is_fault(self) -> bool {
+        matches!(
+            self,
+            ResetReason::WatchdogTimeout | ResetReason::KeyCoreStalled | ResetReason::Panic(_)
+        )
+    }
+
+    /// Fits an oled line
+    #[must_use]
+    pub const fn short_name(self) -> &'static str {
+        match self {
+            ResetReason::PowerOn => "POWER",
+            ResetReason::WatchdogTimeout => "WDOG",
+            ResetReason::KeyCoreStalled => "STALL",
+            ResetReason::Panic(_) => "PANIC",
+            ResetReason::Forced => "FORCED",
+        }
+    }
+}
+
+impl core::fmt::Display for ResetReason {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ResetReason::PowerOn => f.write_str("power-on"),
+            ResetReason::WatchdogTimeout => f.write_str("watchdog timeout"),
+            ResetReason::KeyCoreStalled => f.write_str("key core stalled"),
+            ResetReason::Panic(core) => write!(f, "panic on core {core}"),
+            ResetReason::Forced => f.write_str("forced"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forced_reasons_roundtrip() {
+        for reason in [
+            ResetReason::KeyCoreStalled,
+            ResetReason::Panic(0),
+            ResetReason::Panic(1),
+        ] {
+            let scratch = reason.scratch().unwrap();
+            assert_eq!(reason, ResetReason::from_watchdog(false, true, scratch));
+            assert!(reason.is_fault());
+        }
+        for reason in [
+            ResetReason::PowerOn,
+            ResetReason::WatchdogTimeout,
+            ResetReason::Forced,
+        ] {
+            assert_eq!(None, reason.scratch());
+        }
+    }
+
+    #[test]
+    fn reason_register_comes_first() {
+        let stalled = ResetReason::KeyCoreStalled.scratch().unwrap();
+        assert_eq!(
+            ResetReason::WatchdogTimeout,
+            ResetReason::from_watchdog(true, false, stalled)
+        );
+        // A scratch value left from an earlier forced reset means nothing without the force bit
+        assert_eq!(
+            ResetReason::PowerOn,
+            ResetReason::from_watchdog(false, false, stalled)
+        );
+        assert_eq!(
+            ResetReason::Forced,
+            ResetReason::from_watchdog(false, true, 0)
+        );
+        assert_eq!(
+            ResetReason::Forced,
+            ResetReason::from_watchdog(false, true, SCRATCH_MAGIC | 0xFF)
+        );
+        assert!(!ResetReason::Forced.is_fault());
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!("panic on core 1", ResetReason::Panic(1).to_string());
+        assert_eq!("key core stalled", ResetReason::KeyCoreStalled.to_string());
+    }
+}
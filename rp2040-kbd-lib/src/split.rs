@@ -0,0 +1,667 @@
+//! Messages between the halves. The master is the half that's connected to the host and runs
+//! the keymap, the slave forwards its matrix to it.
+//! The link is a single half-duplex wire, the slave owns it and hands it over by sending
+//! [`SlaveToMaster::Poll`], the master answers every poll with exactly one frame, then the
+//! line goes back to the slave.
+use crate::matrix::{FramePayload, MatrixIndex, MatrixUpdate, FRAME_START, NUM_COLS, NUM_ROWS};
+
+/// Marks the start of a frame sent from the master.
+pub const MASTER_FRAME_START: u8 = 0x5A;
+
+/// The slave polls at least this often, that's its heartbeat
+pub const HEARTBEAT_INTERVAL_MICROS: u64 = 10_000;
+
+/// The slave sends a [`SlaveToMaster::Snapshot`] at least this often
+pub const SNAPSHOT_INTERVAL_MICROS: u64 = 1_000_000;
+
+/// No valid frame from the other half for this long and the link is considered lost
+pub const LINK_TIMEOUT_MICROS: u64 = 100_000;
+
+/// The slave doesn't send for this long after a clock switch, the master's regulator settling
+/// and pll relocking with a margin, frames sent meanwhile would go out at the wrong baud
+pub const CLOCK_SWITCH_QUIET_MICROS: u64 = 2_000;
+
+/// Host LED bits, as they come in the HID output report
+pub const NUM_LOCK: u8 = 0b0000_0001;
+pub const CAPS_LOCK: u8 = 0b0000_0010;
+pub const SCROLL_LOCK: u8 = 0b0000_0100;
+
+/// Pressed keys of one half, bit `n` is matrix index `n`
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct KeyBitmap(u32);
+
+impl KeyBitmap {
+    pub const EMPTY: Self = Self(0);
+    const VALID: u32 = (1 << (NUM_ROWS * NUM_COLS)) - 1;
+
+    #[inline]
+    #[must_use]
+    pub const fn from_bits(bits: u32) -> Option<Self> {
+        if bits & !Self::VALID == 0 {
+            Some(Self(bits))
+        } else {
+            None
+        }
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn bits(self) -> u32 {
+        self.0
+    }
+
+    #[inline]
+    pub fn set(&mut self, index: u8, pressed: bool) {
+        let bit = 1u32 << index;
+        if pressed {
+            self.0 |= bit;
+        } else {
+            self.0 &= !bit;
+        }
+        self.0 &= Self::VALID;
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn is_pressed(self, index: u8) -> bool {
+        index < NUM_ROWS * NUM_COLS && self.0 & (1 << index) != 0
+    }
+
+    /// The key updates that take `self` to `newer`, lowest index first
+    #[inline]
+    #[must_use]
+    pub const fn changes_to(self, newer: Self) -> KeyChanges {
+        KeyChanges {
+            newer: newer.0,
+            changed: self.0 ^ newer.0,
+        }
+    }
+}
+
+/// See [`KeyBitmap::changes_to`]
+#[derive(Debug, Clone)]
+pub struct KeyChanges {
+    newer: u32,
+    changed: u32,
+}
+
+impl Iterator for KeyChanges {
+    type Item = MatrixUpdate;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.changed == 0 {
+            return None;
+        }
+        let bit = self.changed & self.changed.wrapping_neg();
+        self.changed &= !bit;
+        // Both bitmaps only hold valid indices, so this is below 30
+        #[expect(clippy::cast_possible_truncation)]
+        let index = MatrixIndex::from_byte_unchecked(bit.trailing_zeros() as u8);
+        Some(MatrixUpdate::from_key_update(index, self.newer & bit != 0))
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub enum SlaveToMaster {
+    /// A change, and the micros from its detection to the frame going out, saturated.
+    /// The halves' timers aren't synced, this is what lets the master tell when it happened.
+    Matrix(MatrixUpdate, u8),
+    /// The master may answer now
+    Poll,
+    /// Every key that's currently pressed on the slave
+    Snapshot(KeyBitmap),
+}
+
+impl SlaveToMaster {
+    // Key index 31 doesn't exist, so these never collide with a matrix update
+    const POLL: u8 = 0xFF;
+    const SNAPSHOT: u8 = 0xDF;
+}
+
+impl FramePayload for SlaveToMaster {
+    const START: u8 = FRAME_START;
+
+    #[inline]
+    fn to_byte(&self) -> u8 {
+        match self {
+            SlaveToMaster::Matrix(update, _) => update.byte(),
+            SlaveToMaster::Poll => Self::POLL,
+            SlaveToMaster::Snapshot(_) => Self::SNAPSHOT,
+        }
+    }
+
+    #[inline]
+    fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            Self::POLL => Some(SlaveToMaster::Poll),
+            // Need the bytes after them
+            _ => None,
+        }
+    }
+
+    #[inline]
+    fn extra_len(first: u8) -> usize {
+        match first {
+            Self::SNAPSHOT => 4,
+            Self::POLL => 0,
+            // The age goes last
+            _ => MatrixUpdate::extra_len(first) + 1,
+        }
+    }
+
+    #[inline]
+    fn write_extra(&self, extra: &mut [u8]) {
+        match self {
+            SlaveToMaster::Snapshot(keys) => extra.copy_from_slice(&keys.bits().to_le_bytes()),
+            SlaveToMaster::Matrix(update, age) => {
+                if let Some((last, update_extra)) = extra.split_last_mut() {
+                    update.write_extra(update_extra);
+                    *last = *age;
+                }
+            }
+            SlaveToMaster::Poll => {}
+        }
+    }
+
+    #[inline]
+    fn from_bytes(first: u8, extra: &[u8]) -> Option<Self> {
+        match first {
+            Self::SNAPSHOT => {
+                let bits = u32::from_le_bytes(extra.try_into().ok()?);
+                KeyBitmap::from_bits(bits).map(SlaveToMaster::Snapshot)
+            }
+            Self::POLL => Some(SlaveToMaster::Poll),
+            _ => {
+                let (age, update_extra) = extra.split_last()?;
+                MatrixUpdate::from_bytes(first, update_extra)
+                    .map(|update| SlaveToMaster::Matrix(update, *age))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum MasterToSlave {
+    /// Index of the active layer
+    Layer(u8),
+    /// See [`NUM_LOCK`], [`CAPS_LOCK`], [`SCROLL_LOCK`]
+    HostLeds(u8),
+    Awake(bool),
+    /// Ask for a [`SlaveToMaster::Snapshot`]
+    RequestSnapshot,
+    /// Index of the clock profile to run at, both halves switch as soon as it's on the wire
+    Clock(u8),
+}
+
+impl MasterToSlave {
+    const KIND_MASK: u8 = 0b1100_0000;
+    const LAYER: u8 = 0b0000_0000;
+    const LAYER_MASK: u8 = 0b0011_1111;
+    const HOST_LEDS: u8 = 0b0100_0000;
+    const HOST_LEDS_MASK: u8 = 0b0001_1111;
+    const AWAKE: u8 = 0b1000_0000;
+    const REQUEST_SNAPSHOT: u8 = 0b1100_0000;
+    // Shares its kind with snapshot requests, which are always 0 below it
+    const CLOCK: u8 = 0b1110_0000;
+    const CLOCK_MASK: u8 = 0b0001_1111;
+}
+
+impl FramePayload for MasterToSlave {
+    const START: u8 = MASTER_FRAME_START;
+
+    #[inline]
+    fn to_byte(&self) -> u8 {
+        match self {
+            MasterToSlave::Layer(layer) => Self::LAYER | (layer & Self::LAYER_MASK),
+            MasterToSlave::HostLeds(leds) => Self::HOST_LEDS | (leds & Self::HOST_LEDS_MASK),
+            MasterToSlave::Awake(awake) => Self::AWAKE | u8::from(*awake),
+            MasterToSlave::RequestSnapshot => Self::REQUEST_SNAPSHOT,
+            MasterToSlave::Clock(profile) => Self::CLOCK | (profile & Self::CLOCK_MASK),
+        }
+    }
+
+    #[inline]
+    fn from_byte(byte: u8) -> Option<Self> {
+        let value = byte & !Self::KIND_MASK;
+        match byte & Self::KIND_MASK {
+            Self::LAYER => Some(MasterToSlave::Layer(value)),
+            Self::HOST_LEDS if value <= Self::HOST_LEDS_MASK => {
+                Some(MasterToSlave::HostLeds(value))
+            }
+            Self::AWAKE if value <= 1 => Some(MasterToSlave::Awake(value == 1)),
+            Self::REQUEST_SNAPSHOT if value == 0 => Some(MasterToSlave::RequestSnapshot),
+            Self::REQUEST_SNAPSHOT if byte & !Self::CLOCK_MASK == Self::CLOCK => {
+                Some(MasterToSlave::Clock(byte & Self::CLOCK_MASK))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// What the master wants the slave to know, picks what to answer a poll with.
+/// Snapshot requests go first, then changes, otherwise the state is repeated round-robin,
+/// so that a lost frame is corrected by a later poll.
+pub struct MasterState {
+    current: [MasterToSlave; 4],
+    sent: [Option<MasterToSlave>; 4],
+    next: usize,
+    want_snapshot: bool,
+}
+
+impl MasterState {
+    const LAYER: usize = 0;
+    const HOST_LEDS: usize = 1;
+    const AWAKE: usize = 2;
+    const CLOCK: usize = 3;
+
+    #[must_use]
+    pub const fn new(layer: u8, clock: u8) -> Self {
+        Self {
+            current: [
+                MasterToSlave::Layer(layer),
+                MasterToSlave::HostLeds(0),
+                MasterToSlave::Awake(true),
+                MasterToSlave::Clock(clock),
+            ],
+            sent: [None; 4],
+            next: 0,
+            want_snapshot: false,
+        }
+    }
+
+    #[inline]
+    pub fn set_layer(&mut self, layer: u8) {
+        self.current[Self::LAYER] = MasterToSlave::Layer(layer);
+    }
+
+    #[inline]
+    pub fn set_host_leds(&mut self, leds: u8) {
+        self.current[Self::HOST_LEDS] = MasterToSlave::HostLeds(leds);
+    }
+
+    #[inline]
+    pub fn set_awake(&mut self, awake: bool) {
+        self.current[Self::AWAKE] = MasterToSlave::Awake(awake);
+    }
+
+    #[inline]
+    pub fn set_clock(&mut self, profile: u8) {
+        self.current[Self::CLOCK] = MasterToSlave::Clock(profile);
+    }
+
+    #[inline]
+    pub fn request_snapshot(&mut self) {
+        self.want_snapshot = true;
+    }
+
+    pub fn next_message(&mut self) -> MasterToSlave {
+        if core::mem::take(&mut self.want_snapshot) {
+            return MasterToSlave::RequestSnapshot;
+        }
+        let ind = (0..self.current.len())
+            .find(|i| self.sent[*i] != Some(self.current[*i]))
+            .unwrap_or_else(|| {
+                let ind = self.next;
+                self.next = (self.next + 1) % self.current.len();
+                ind
+            });
+        self.sent[ind] = Some(self.current[ind]);
+        self.current[ind]
+    }
+}
+
+/// The slave's copy of the master's state, `None` until heard from the master.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct SlaveState {
+    pub layer: Option<u8>,
+    pub host_leds: Option<u8>,
+    pub awake: Option<bool>,
+}
+
+impl SlaveState {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            layer: None,
+            host_leds: None,
+            awake: None,
+        }
+    }
+
+    /// Returns true if the message changed anything, requests don't.
+    /// Clock switches aren't kept, the slave acts on them right away.
+    pub fn apply(&mut self, msg: MasterToSlave) -> bool {
+        fn replace<T: PartialEq>(slot: &mut Option<T>, value: T) -> bool {
+            let changed = slot.as_ref() != Some(&value);
+            *slot = Some(value);
+            changed
+        }
+        match msg {
+            MasterToSlave::Layer(layer) => replace(&mut self.layer, layer),
+            MasterToSlave::HostLeds(leds) => replace(&mut self.host_leds, leds),
+            MasterToSlave::Awake(awake) => replace(&mut self.awake, awake),
+            MasterToSlave::RequestSnapshot | MasterToSlave::Clock(_) => false,
+        }
+    }
+}
+
+/// Tracks whether the other half is still there, any valid frame counts as a heartbeat.
+pub struct LinkMonitor {
+    timeout_micros: u64,
+    last_heard: u64,
+    up: bool,
+}
+
+impl LinkMonitor {
+    #[must_use]
+    pub const fn new(timeout_micros: u64) -> Self {
+        Self {
+            timeout_micros,
+            last_heard: 0,
+            up: false,
+        }
+    }
+
+    /// Returns true if the link just came up
+    #[inline]
+    pub fn heard(&mut self, now_micros: u64) -> bool {
+        self.last_heard = now_micros;
+        !core::mem::replace(&mut self.up, true)
+    }
+
+    /// Returns true if the link was just lost
+    #[inline]
+    pub fn check(&mut self, now_micros: u64) -> bool {
+        if self.up && now_micros.saturating_sub(self.last_heard) > self.timeout_micros {
+            self.up = false;
+            return true;
+        }
+        false
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn is_up(&self) -> bool {
+        self.up
+    }
+}
+
+/// Keeps the slave off the link while the master switches clocks
+#[derive(Debug, Copy, Clone)]
+pub struct QuietWindow {
+    until: u64,
+}
+
+impl QuietWindow {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self { until: 0 }
+    }
+
+    /// The master is switching as of `now_micros`
+    #[inline]
+    pub fn start(&mut self, now_micros: u64) {
+        self.until = now_micros.saturating_add(CLOCK_SWITCH_QUIET_MICROS);
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn is_quiet(&self, now_micros: u64) -> bool {
+        now_micros < self.until
+    }
+}
+
+impl Default for QuietWindow {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::{FrameDecoder, FrameEncoder, MatrixChange};
+
+    #[test]
+    fn master_to_slave_bytes_roundtrip() {
+        let mut valid = 0;
+        for byte in 0..=u8::MAX {
+            if let Some(msg) = MasterToSlave::from_byte(byte) {
+                assert_eq!(byte, msg.to_byte(), "{msg:?}");
+                valid += 1;
+            }
+        }
+        // 64 layers, 32 led states, awake and asleep, snapshot request, 32 clock profiles
+        assert_eq!(64 + 32 + 2 + 1 + 32, valid);
+    }
+
+    #[test]
+    fn slave_to_master_bytes_roundtrip() {
+        for byte in 0..=u8::MAX {
+            if let Some(msg) = SlaveToMaster::from_byte(byte) {
+                assert_eq!(byte, msg.to_byte(), "{msg:?}");
+            }
+        }
+        assert!(matches!(
+            SlaveToMaster::from_byte(SlaveToMaster::Poll.to_byte()),
+            Some(SlaveToMaster::Poll)
+        ));
+    }
+
+    #[test]
+    fn master_frames_roundtrip() {
+        let mut enc = FrameEncoder::new();
+        let mut dec: FrameDecoder<MasterToSlave> = FrameDecoder::new();
+        for msg in [
+            MasterToSlave::Layer(9),
+            MasterToSlave::HostLeds(CAPS_LOCK | NUM_LOCK),
+            MasterToSlave::Awake(false),
+            MasterToSlave::RequestSnapshot,
+            MasterToSlave::Clock(0),
+            MasterToSlave::Clock(2),
+        ] {
+            let mut decoded = None;
+            for b in enc.encode(&msg) {
+                decoded = dec.push(b);
+            }
+            assert_eq!(Some(msg), decoded);
+        }
+    }
+
+    #[test]
+    fn master_state_sends_changes_first() {
+        let mut state = MasterState::new(0, 2);
+        let first: Vec<MasterToSlave> = (0..4).map(|_| state.next_message()).collect();
+        assert_eq!(
+            vec![
+                MasterToSlave::Layer(0),
+                MasterToSlave::HostLeds(0),
+                MasterToSlave::Awake(true),
+                MasterToSlave::Clock(2)
+            ],
+            first
+        );
+        state.set_awake(false);
+        state.set_host_leds(CAPS_LOCK);
+        assert_eq!(MasterToSlave::HostLeds(CAPS_LOCK), state.next_message());
+        assert_eq!(MasterToSlave::Awake(false), state.next_message());
+        // Nothing changed, repeat everything
+        let repeated: Vec<MasterToSlave> = (0..4).map(|_| state.next_message()).collect();
+        assert_eq!(
+            vec![
+                MasterToSlave::Layer(0),
+                MasterToSlave::HostLeds(CAPS_LOCK),
+                MasterToSlave::Awake(false),
+                MasterToSlave::Clock(2)
+            ],
+            repeated
+        );
+        state.set_clock(0);
+        state.set_layer(4);
+        state.request_snapshot();
+        assert_eq!(MasterToSlave::RequestSnapshot, state.next_message());
+        assert_eq!(MasterToSlave::Layer(4), state.next_message());
+        assert_eq!(MasterToSlave::Clock(0), state.next_message());
+    }
+
+    #[test]
+    fn slave_state_reports_changes() {
+        let mut state = SlaveState::new();
+        assert!(state.apply(MasterToSlave::Layer(1)));
+        assert!(!state.apply(MasterToSlave::Layer(1)));
+        assert!(state.apply(MasterToSlave::Awake(true)));
+        assert!(state.apply(MasterToSlave::Awake(false)));
+        assert!(!state.apply(MasterToSlave::Clock(1)));
+        assert_eq!(Some(1), state.layer);
+        assert_eq!(None, state.host_leds);
+    }
+
+    #[test]
+    fn link_lost_once_after_timeout() {
+        let mut link = LinkMonitor::new(LINK_TIMEOUT_MICROS);
+        // Never heard from isn't lost, it was never up
+        assert!(!link.check(LINK_TIMEOUT_MICROS * 2));
+        assert!(!link.is_up());
+        let mut now = 1_000_000;
+        assert!(link.heard(now));
+        for _ in 0..20 {
+            assert!(!link.heard(now));
+            now += HEARTBEAT_INTERVAL_MICROS;
+            assert!(!link.check(now));
+        }
+        assert!(link.is_up());
+        assert!(!link.check(now + LINK_TIMEOUT_MICROS - HEARTBEAT_INTERVAL_MICROS));
+        assert!(link.check(now + LINK_TIMEOUT_MICROS));
+        assert!(!link.check(now + LINK_TIMEOUT_MICROS * 2));
+        assert!(!link.is_up());
+        assert!(link.heard(now + LINK_TIMEOUT_MICROS * 3));
+        assert!(link.is_up());
+    }
+
+    #[test]
+    fn bitmap_changes_take_one_to_the_other() {
+        let older = KeyBitmap::from_bits(0b0010_0000_0000_0000_0000_0000_0001_0110).unwrap();
+        let newer = KeyBitmap::from_bits(0b0000_0000_0000_0000_0000_0000_0100_0011).unwrap();
+        let changes: Vec<_> = older.changes_to(newer).collect();
+        let mut applied = older;
+        for update in &changes {
+            let MatrixChange::KeyUpdate(index, pressed) = update.interpret_byte() else {
+                panic!("not a key {update:?}");
+            };
+            assert_ne!(pressed, applied.is_pressed(index.byte()));
+            applied.set(index.byte(), pressed);
+        }
+        assert_eq!(newer, applied);
+        // 0, 2, 4, 6, 29
+        assert_eq!(5, changes.len());
+        assert_eq!(
+            MatrixUpdate::from_key_update(MatrixIndex::from_byte_unchecked(0), true),
+            changes[0]
+        );
+        assert_eq!(0, newer.changes_to(newer).count());
+    }
+
+    #[test]
+    fn matrix_frames_carry_age() {
+        let mut enc = FrameEncoder::new();
+        let mut dec: FrameDecoder<SlaveToMaster> = FrameDecoder::new();
+        let key = MatrixUpdate::from_key_update(MatrixIndex::from_byte_unchecked(29), true);
+        let encoder = MatrixUpdate::from_encoder_steps(1, -3).unwrap();
+        for (update, age) in [(key, 0), (encoder, 17), (key, u8::MAX), (encoder, 0)] {
+            let mut decoded = None;
+            for b in enc.encode(&SlaveToMaster::Matrix(update, age)) {
+                assert!(decoded.is_none());
+                decoded = dec.push(b);
+            }
+            assert!(
+                matches!(decoded, Some(SlaveToMaster::Matrix(u, a)) if u == update && a == age),
+                "{decoded:?}"
+            );
+        }
+        assert_eq!(0, dec.stats().corrupt);
+    }
+
+    #[test]
+    fn snapshot_frames_roundtrip() {
+        let mut enc = FrameEncoder::new();
+        let mut dec: FrameDecoder<SlaveToMaster> = FrameDecoder::new();
+        let mut keys = KeyBitmap::EMPTY;
+        for ind in [0, 7, 29] {
+            keys.set(ind, true);
+        }
+        // Short frames on either side of the long one
+        let msgs = [
+            SlaveToMaster::Poll,
+            SlaveToMaster::Snapshot(keys),
+            SlaveToMaster::Snapshot(KeyBitmap::EMPTY),
+            SlaveToMaster::Poll,
+        ];
+        for msg in msgs {
+            let frame = enc.encode(&msg);
+            let mut decoded = None;
+            for b in frame {
+                assert!(decoded.is_none());
+                decoded = dec.push(b);
+            }
+            match (msg, decoded) {
+                (SlaveToMaster::Poll, Some(SlaveToMaster::Poll)) => {}
+                (SlaveToMaster::Snapshot(sent), Some(SlaveToMaster::Snapshot(got))) => {
+                    assert_eq!(sent, got);
+                }
+                other => panic!("{other:?}"),
+            }
+        }
+        assert_eq!(0, dec.stats().corrupt);
+        assert_eq!(0, dec.stats().missed);
+    }
+
+    #[test]
+    fn corrupt_snapshot_resyncs() {
+        let mut enc = FrameEncoder::new();
+        let mut dec: FrameDecoder<SlaveToMaster> = FrameDecoder::new();
+        let mut keys = KeyBitmap::EMPTY;
+        keys.set(3, true);
+        for bit in 8..crate::matrix::MAX_FRAME_LEN * 8 {
+            let mut frame = enc.encode(&SlaveToMaster::Snapshot(keys));
+            frame[bit / 8] ^= 1 << (bit % 8);
+            for b in frame {
+                assert!(dec.push(b).is_none(), "bit {bit}");
+            }
+            let mut decoded = None;
+            for b in enc.encode(&SlaveToMaster::Snapshot(keys)) {
+                decoded = decoded.or(dec.push(b));
+            }
+            assert!(
+                matches!(decoded, Some(SlaveToMaster::Snapshot(got)) if got == keys),
+                "bit {bit}"
+            );
+        }
+    }
+
+    #[test]
+    fn key_bitmap_bounds() {
+        let mut keys = KeyBitmap::EMPTY;
+        keys.set(29, true);
+        assert!(keys.is_pressed(29));
+        assert!(!keys.is_pressed(30));
+        assert!(KeyBitmap::from_bits(1 << 30).is_none());
+        keys.set(29, false);
+        assert_eq!(KeyBitmap::EMPTY, keys);
+    }
+
+    #[test]
+    fn quiet_window_covers_the_switch() {
+        let mut window = QuietWindow::new();
+        assert!(!window.is_quiet(0));
+        assert!(!window.is_quiet(5_000));
+        window.start(5_000);
+        assert!(window.is_quiet(5_000));
+        assert!(window.is_quiet(5_000 + CLOCK_SWITCH_QUIET_MICROS - 1));
+        assert!(!window.is_quiet(5_000 + CLOCK_SWITCH_QUIET_MICROS));
+        // Another switch before the window is over pushes it out
+        window.start(6_000);
+        assert!(window.is_quiet(5_000 + CLOCK_SWITCH_QUIET_MICROS));
+        assert!(!window.is_quiet(6_000 + CLOCK_SWITCH_QUIET_MICROS));
+    }
+}
@@ -0,0 +1,317 @@
+//! A ring of timestamped events, for finding out what happened around a glitch after the fact.
+//! Recording is a couple of atomic stores, cheap enough for the hot paths where a debug print
+//! would wreck the very timing it's trying to show. There's a single writer, readers on the
+//! other core skip entries that were overwritten while they were read.
+use crate::matrix::{FramePayload, MatrixUpdate};
+use core::sync::atomic::{AtomicU32, Ordering};
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum TraceEvent {
+    /// A debounced key or encoder change, from either half
+    Matrix { left: bool, update: MatrixUpdate },
+    /// A frame from the other half passed the checks, its first payload byte.
+    /// Heartbeats are left out.
+    LinkReceived(u8),
+    /// Frames from the other half that were thrown away, saturating
+    LinkRejected(u8),
+    /// The active layer changed, by its index
+    Layer(u8),
+    /// A report was queued for the host, its modifiers and the number of keys in it
+    ReportQueued { modifier: u8, keys: u8 },
+    /// The usb stack took a report
+    ReportSent { modifier: u8, keys: u8 },
+    /// A usb interrupt, `picked_up` if it was the host fetching the last report sent
+    UsbPoll { picked_up: bool },
+    /// A message to the admin core didn't fit in the queue, once until one fits again
+    QueueFull,
+    /// A seventh key pushed the oldest out of the report, the key that got in
+    KeyOverflow(u8),
+}
+
+impl TraceEvent {
+    const MATRIX: u8 = 0;
+    const LINK_RECEIVED: u8 = 1;
+    const LINK_REJECTED: u8 = 2;
+    const LAYER: u8 = 3;
+    const REPORT_QUEUED: u8 = 4;
+    const REPORT_SENT: u8 = 5;
+    const USB_POLL: u8 = 6;
+    const QUEUE_FULL: u8 = 7;
+    const KEY_OVERFLOW: u8 = 8;
+
+    /// Kind in the top byte, the rest is payload
+    #[must_use]
+    pub fn encode(self) -> u32 {
+        let (kind, payload) = match self {
+            TraceEvent::Matrix { left, update } => {
+                let mut steps = [0];
+                update.write_extra(&mut steps[..MatrixUpdate::extra_len(update.byte())]);
+                (Self::MATRIX, [u8::from(left), update.byte(), steps[0]])
+            }
+            TraceEvent::LinkReceived(byte) => (Self::LINK_RECEIVED, [0, 0, byte]),
+            TraceEvent::LinkRejected(count) => (Self::LINK_REJECTED, [0, 0, count]),
+            TraceEvent::Layer(index) => (Self::LAYER, [0, 0, index]),
+            TraceEvent::ReportQueued { modifier, keys } => {
+                (Self::REPORT_QUEUED, [0, modifier, keys])
+            }
+            TraceEvent::ReportSent { modifier, keys } => (Self::REPORT_SENT, [0, modifier, keys]),
+            TraceEvent::UsbPoll { picked_up } => (Self::USB_POLL, [0, 0, u8::from(picked_up)]),
+            TraceEvent::QueueFull => (Self::QUEUE_FULL, [0; 3]),
+            TraceEvent::KeyOverflow(key) => (Self::KEY_OVERFLOW, [0, 0, key]),
+        };
+        u32::from_be_bytes([kind, payload[0], payload[1], payload[2]])
+    }
+
+    #[must_use]
+    pub fn decode(raw: u32) -> Option<Self> {
+        let [kind, a, b, c] = raw.to_be_bytes();
+        Some(match kind {
+            Self::MATRIX => TraceEvent::Matrix {
+                left: a != 0,
+                update: MatrixUpdate::from_bytes(b, &[c])?,
+            },
+            Self::LINK_RECEIVED => TraceEvent::LinkReceived(c),
+            Self::LINK_REJECTED => TraceEvent::LinkRejected(c),
+            Self::LAYER => TraceEvent::Layer(c),
+            Self::REPORT_QUEUED => TraceEvent::ReportQueued {
+                modifier: b,
+                keys: c,
+            },
+            Self::REPORT_SENT => TraceEvent::ReportSent {
+                modifier: b,
+                keys: c,
+            },
+            Self::USB_POLL => TraceEvent::UsbPoll { picked_up: c != 0 },
+            Self::QUEUE_FULL => TraceEvent::QueueFull,
+            Self::KEY_OVERFLOW => TraceEvent::KeyOverflow(c),
+            _ => return None,
+        })
+    }
+}
+
+impl core::fmt::Display for TraceEvent {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            TraceEvent::Matrix { left, update } => {
+                let side = if *left { "left" } else { "right" };
+                match update.interpret_byte() {
+                    crate::matrix::MatrixChange::KeyUpdate(ind, pressed) => write!(
+                        f,
+                        "{side} key {} {}",
+                        ind.byte(),
+                        if pressed { "down" } else { "up" }
+                    ),
+                    crate::matrix::MatrixChange::EncoderUpdate { index, steps } => {
+                        write!(f, "{side} encoder {index} {steps:+}")
+                    }
+                }
+            }
+            TraceEvent::LinkReceived(byte) => write!(f, "link rx {byte:#04x}"),
+            TraceEvent::LinkRejected(count) => write!(f, "link rejected {count}"),
+            TraceEvent::Layer(index) => write!(f, "layer {index}"),
+            TraceEvent::ReportQueued { modifier, keys } => {
+                write!(f, "report queued mod={modifier:#04x} keys={keys}")
+            }
+            TraceEvent::ReportSent { modifier, keys } => {
+                write!(f, "report sent mod={modifier:#04x} keys={keys}")
+            }
+            TraceEvent::UsbPoll { picked_up: true } => f.write_str("usb poll, report picked up"),
+            TraceEvent::UsbPoll { picked_up: false } => f.write_str("usb poll"),
+            TraceEvent::QueueFull => f.write_str("admin queue full"),
+            TraceEvent::KeyOverflow(key) => write!(f, "key overflow {key:#04x}"),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct TraceEntry {
+    /// Counts every event recorded, gaps are events overwritten while reading
+    pub seq: u32,
+    /// Low half of the timer's micros
+    pub at: u32,
+    pub event: TraceEvent,
+}
+
+/// `seq at event`, one line's worth
+impl core::fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{} {} {}", self.seq, self.at, self.event)
+    }
+}
+
+struct TraceSlot {
+    at: AtomicU32,
+    event: AtomicU32,
+}
+
+impl TraceSlot {
+    const fn new() -> Self {
+        Self {
+            at: AtomicU32::new(0),
+            event: AtomicU32::new(0),
+        }
+    }
+}
+
+pub struct TraceRing<const N: usize> {
+    slots: [TraceSlot; N],
+    written: AtomicU32,
+}
+
+impl<const N: usize> TraceRing<N> {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            slots: [const { TraceSlot::new() }; N],
+            written: AtomicU32::new(0),
+        }
+    }
+
+    /// Only one writer at a time, two interleaving writes garble both entries
+    #[inline]
+    pub fn record(&self, at: u32, event: TraceEvent) {
+        let seq = self.written.load(Ordering::Relaxed);
+        let slot = &self.slots[seq as usize % N];
+        slot.at.store(at, Ordering::Relaxed);
+        slot.event.store(event.encode(), Ordering::Relaxed);
+        self.written.store(seq.wrapping_add(1), Ordering::Release);
+    }
+
+    /// Events recorded since boot, the ring only holds the last `N`
+    #[inline]
+    #[must_use]
+    pub fn recorded(&self) -> u32 {
+        self.written.load(Ordering::Acquire)
+    }
+
+    /// What's in the ring, oldest first
+    pub fn entries(&self) -> impl Iterator<Item = TraceEntry> + '_ {
+        let written = self.recorded();
+        #[expect(clippy::cast_possible_truncation)]
+        let start = written.saturating_sub(N as u32);
+        (start..written).filter_map(move |seq| self.read(seq))
+    }
+
+    fn read(&self, seq: u32) -> Option<TraceEntry> {
+        let slot = &self.slots[seq as usize % N];
+        let at = slot.at.load(Ordering::Relaxed);
+        let event = slot.event.load(Ordering::Relaxed);
+        // The writer lapped us while reading
+        #[expect(clippy::cast_possible_truncation)]
+        if self.recorded().wrapping_sub(seq) > N as u32 {
+            return None;
+        }
+        Some(TraceEntry {
+            seq,
+            at,
+            event: TraceEvent::decode(event)?,
+        })
+    }
+}
+
+impl<const N: usize> Default for TraceRing<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::{ColIndex, MatrixIndex, RowIndex};
+
+    fn key(row: u8, col: u8, pressed: bool) -> MatrixUpdate {
+        MatrixUpdate::from_key_update(
+            MatrixIndex::from_row_col(RowIndex::from_value(row), ColIndex::from_value(col)),
+            pressed,
+        )
+    }
+
+    #[test]
+    fn events_roundtrip() {
+        let events = [
+            TraceEvent::Matrix {
+                left: true,
+                update: key(2, 3, true),
+            },
+            TraceEvent::Matrix {
+                left: false,
+                update: MatrixUpdate::from_encoder_steps(1, -3).unwrap(),
+            },
+            TraceEvent::LinkReceived(0xA5),
+            TraceEvent::LinkRejected(u8::MAX),
+            TraceEvent::Layer(4),
+            TraceEvent::ReportQueued {
+                modifier: 0x22,
+                keys: 6,
+            },
+            TraceEvent::ReportSent {
+                modifier: 0x01,
+                keys: 0,
+            },
+            TraceEvent::UsbPoll { picked_up: true },
+            TraceEvent::UsbPoll { picked_up: false },
+            TraceEvent::QueueFull,
+            TraceEvent::KeyOverflow(0x04),
+        ];
+        for event in events {
+            assert_eq!(Some(event), TraceEvent::decode(event.encode()), "{event}");
+        }
+        assert_eq!(None, TraceEvent::decode(u32::MAX));
+    }
+
+    #[test]
+    fn ring_keeps_the_last_entries_oldest_first() {
+        let ring: TraceRing<4> = TraceRing::new();
+        assert_eq!(0, ring.entries().count());
+        for at in 0..6 {
+            ring.record(at * 10, TraceEvent::Layer(u8::try_from(at).unwrap()));
+        }
+        assert_eq!(6, ring.recorded());
+        let entries: Vec<_> = ring.entries().collect();
+        assert_eq!(4, entries.len());
+        assert_eq!(
+            TraceEntry {
+                seq: 2,
+                at: 20,
+                event: TraceEvent::Layer(2)
+            },
+            entries[0]
+        );
+        assert_eq!(5, entries[3].seq);
+        assert_eq!(50, entries[3].at);
+    }
+
+    #[test]
+    fn lapped_entries_are_skipped() {
+        let ring: TraceRing<2> = TraceRing::new();
+        ring.record(1, TraceEvent::QueueFull);
+        ring.record(2, TraceEvent::QueueFull);
+        let mut entries = ring.entries();
+        // Written over while the reader is between entries
+        ring.record(3, TraceEvent::QueueFull);
+        ring.record(4, TraceEvent::QueueFull);
+        assert_eq!(None, entries.next());
+    }
+
+    #[test]
+    fn entry_display() {
+        let entry = TraceEntry {
+            seq: 7,
+            at: 1234,
+            event: TraceEvent::Matrix {
+                left: false,
+                update: MatrixUpdate::from_encoder_steps(0, 2).unwrap(),
+            },
+        };
+        assert_eq!("7 1234 right encoder 0 +2", entry.to_string());
+        assert_eq!(
+            "report queued mod=0x02 keys=1",
+            TraceEvent::ReportQueued {
+                modifier: 2,
+                keys: 1
+            }
+            .to_string()
+        );
+    }
+}
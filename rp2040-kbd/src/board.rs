@@ -0,0 +1,104 @@
+//! The keyboard the firmware is built for, picked by cargo feature, the lily58 if none is given.
+//! Every board sits on a Liatris, or anything with the same pro micro footprint, so the split
+//! link (`gpio1`), the oled's i2c (`gpio2`, `gpio3`) and the power led are the same on all of them.
+//! The oled is drawn as a 32 pixel wide portrait screen.
+//! The keymap is written for the lily58's matrix positions, each board's `KEYMAP` puts its
+//! switches on them.
+#[cfg(feature = "corne")]
+mod corne;
+#[cfg(not(any(feature = "corne", feature = "sofle")))]
+mod lily58;
+#[cfg(feature = "sofle")]
+mod sofle;
+
+#[cfg(feature = "corne")]
+pub use corne::*;
+#[cfg(not(any(feature = "corne", feature = "sofle")))]
+pub use lily58::*;
+#[cfg(feature = "sofle")]
+pub use sofle::*;
+
+#[cfg(all(feature = "corne", feature = "sofle"))]
+const _ILLEGAL_BOARDS: () = assert!(false, "Can't compile for more than one board");
+
+const _KEYMAP_COVERS_LAYOUT: () = assert!(
+    KeymapRemap::present(&KEYMAP.left) == LEFT.present()
+        && KeymapRemap::present(&KEYMAP.right) == RIGHT.present(),
+    "The board's keymap remap has to have exactly the switches its layout has"
+);
+
+use crate::keyboard::encoder::{Encoders, RotaryEncoder};
+use crate::keyboard::scanner::MatrixLayout;
+use crate::keyboard::ButtonPin;
+use rp2040_hal::gpio::{DynBankId, DynPinId, FunctionSioInput, PullUp};
+use rp2040_kbd_lib::encoder::StepsPerDetent;
+use rp2040_kbd_lib::matrix::MatrixIndex;
+use rp2040_kbd_lib::remap::KeymapRemap;
+
+/// Pins on the Liatris that every board uses the same way, never handed out by a [`PinBank`]
+const FIXED_PINS: u32 = 1 << 1 | 1 << 2 | 1 << 3 | 1 << 24;
+const BANK0_PINS: u32 = (1 << 30) - 1;
+
+pub struct EncoderPins {
+    pub a: u8,
+    pub b: u8,
+    pub steps_per_detent: StepsPerDetent,
+    /// A push switch on its own pin, and the free matrix slot it's reported at
+    pub switch: Option<(u8, MatrixIndex)>,
+}
+
+/// Hands out the board's pins by number, each at most once
+pub struct PinBank {
+    free: u32,
+}
+
+impl PinBank {
+    /// # Safety
+    /// Only the pins in [`FIXED_PINS`] may be used outside of this bank, the rest of the bank 0
+    /// pins have to be unused, like right after `Pins::new` with the other pins dropped.
+    pub unsafe fn new() -> Self {
+        Self {
+            free: BANK0_PINS & !FIXED_PINS,
+        }
+    }
+
+    /// Panics if the pin was already taken, that's a broken board definition
+    pub fn button(&mut self, num: u8) -> ButtonPin<DynPinId> {
+        let bit = 1 << num;
+        assert!(self.free & bit != 0, "board pin used twice");
+        self.free &= !bit;
+        // Safety: The pin wasn't free in the bank otherwise, and nothing outside uses it
+        let pin = unsafe {
+            rp2040_hal::gpio::new_pin(DynPinId {
+                bank: DynBankId::Bank0,
+                num,
+            })
+        };
+        let Ok(pin) = pin.try_into_function::<FunctionSioInput>() else {
+            unreachable!("every bank 0 pin can be an sio input");
+        };
+        pin.into_pull_type::<PullUp>()
+    }
+
+    /// Row and column pins in `layout` order
+    pub fn matrix<const ROWS: usize, const COLS: usize>(
+        &mut self,
+        layout: &MatrixLayout<ROWS, COLS>,
+    ) -> ([ButtonPin<DynPinId>; ROWS], [ButtonPin<DynPinId>; COLS]) {
+        let rows = layout.rows.map(|num| self.button(num));
+        let cols = layout.cols.map(|num| self.button(num));
+        (rows, cols)
+    }
+
+    pub fn encoders<const N: usize>(&mut self, pins: &[EncoderPins; N]) -> Encoders<N> {
+        Encoders::new(core::array::from_fn(|i| {
+            let def = &pins[i];
+            let encoder =
+                RotaryEncoder::new(self.button(def.a), self.button(def.b), def.steps_per_detent);
+            match def.switch {
+                Some((pin, key)) => encoder.with_switch(self.button(pin), key),
+                None => encoder,
+            }
+        }))
+    }
+}
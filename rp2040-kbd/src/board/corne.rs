@@ -0,0 +1,36 @@
+//! The crkbd rev1 pins from qmk, through the Liatris' pro micro pinout
+use crate::board::EncoderPins;
+use crate::keyboard::scanner::MatrixLayout;
+use rp2040_kbd_lib::matrix::MatrixIndex;
+use rp2040_kbd_lib::remap::{self, KeymapRemap};
+use ssd1306::prelude::DisplayRotation;
+use ssd1306::size::DisplaySize128x32;
+
+/// Not wired on the pcb, bridge the `B4` pad to ground on the right half
+pub const SIDE_PIN: u8 = 8;
+
+pub const ROWS: usize = 4;
+pub const COLS: usize = 6;
+
+const LAYOUT: MatrixLayout<ROWS, COLS> = MatrixLayout {
+    rows: [4, 5, 6, 7],
+    cols: [29, 28, 27, 26, 22, 20],
+    // Only the three thumb keys on row 3
+    missing: &[(3, 0), (3, 1), (3, 2)],
+};
+// Both halves are wired the same, col 0 is the outer column
+pub const LEFT: MatrixLayout<ROWS, COLS> = LAYOUT;
+pub const RIGHT: MatrixLayout<ROWS, COLS> = LAYOUT;
+
+pub const KEYMAP: &KeymapRemap = &remap::CORNE;
+
+/// Every switch has a key in the keymap, `reboot usb` on the serial console instead
+pub const LEFT_REBOOT_KEY: Option<MatrixIndex> = None;
+pub const RIGHT_REBOOT_KEY: Option<MatrixIndex> = None;
+
+pub const LEFT_ENCODERS: [EncoderPins; 0] = [];
+pub const RIGHT_ENCODERS: [EncoderPins; 0] = [];
+
+pub type OledSize = DisplaySize128x32;
+pub const OLED_SIZE: OledSize = DisplaySize128x32;
+pub const OLED_ROTATION: DisplayRotation = DisplayRotation::Rotate90;
@@ -0,0 +1,51 @@
+use crate::board::EncoderPins;
+use crate::keyboard::scanner::MatrixLayout;
+use rp2040_kbd_lib::encoder::StepsPerDetent;
+use rp2040_kbd_lib::matrix::{ColIndex, MatrixIndex, RowIndex};
+use rp2040_kbd_lib::remap::{self, KeymapRemap};
+use ssd1306::prelude::DisplayRotation;
+use ssd1306::size::DisplaySize128x32;
+
+/// High on the left half, grounded on the right
+pub const SIDE_PIN: u8 = 28;
+
+pub const ROWS: usize = 5;
+pub const COLS: usize = 6;
+
+pub const LEFT: MatrixLayout<ROWS, COLS> = MatrixLayout {
+    rows: [29, 27, 6, 7, 8],
+    cols: [9, 26, 22, 20, 23, 21],
+    // Col 0 doesn't exist on row 4
+    missing: &[(4, 0)],
+};
+
+pub const RIGHT: MatrixLayout<ROWS, COLS> = MatrixLayout {
+    rows: [29, 4, 20, 23, 21],
+    cols: [22, 5, 6, 7, 8, 9],
+    // Col 0 doesn't exist on row 4, row 4 col 5 is the rotary encoder's push switch
+    missing: &[(4, 0)],
+};
+
+pub const KEYMAP: &KeymapRemap = &remap::LILY58;
+
+pub const LEFT_REBOOT_KEY: Option<MatrixIndex> = Some(MatrixIndex::from_row_col(
+    RowIndex::from_value(4),
+    ColIndex::from_value(1),
+));
+pub const RIGHT_REBOOT_KEY: Option<MatrixIndex> = Some(MatrixIndex::from_row_col(
+    RowIndex::from_value(4),
+    ColIndex::from_value(2),
+));
+
+pub const LEFT_ENCODERS: [EncoderPins; 0] = [];
+/// Clicks once per full Gray code cycle, its push switch is in the matrix
+pub const RIGHT_ENCODERS: [EncoderPins; 1] = [EncoderPins {
+    a: 26,
+    b: 27,
+    steps_per_detent: StepsPerDetent::Four,
+    switch: None,
+}];
+
+pub type OledSize = DisplaySize128x32;
+pub const OLED_SIZE: OledSize = DisplaySize128x32;
+pub const OLED_ROTATION: DisplayRotation = DisplayRotation::Rotate90;
@@ -0,0 +1,49 @@
+//! The sofle rev1 pins from qmk, through the Liatris' pro micro pinout
+use crate::board::EncoderPins;
+use crate::keyboard::scanner::MatrixLayout;
+use rp2040_kbd_lib::encoder::StepsPerDetent;
+use rp2040_kbd_lib::matrix::MatrixIndex;
+use rp2040_kbd_lib::remap::{self, KeymapRemap};
+use ssd1306::prelude::DisplayRotation;
+use ssd1306::size::DisplaySize128x32;
+
+/// Not wired on the pcb, bridge the `D4` pad to ground on the right half
+pub const SIDE_PIN: u8 = 4;
+
+pub const ROWS: usize = 5;
+pub const COLS: usize = 6;
+
+const LAYOUT: MatrixLayout<ROWS, COLS> = MatrixLayout {
+    rows: [5, 6, 7, 29, 28],
+    cols: [27, 26, 22, 20, 23, 21],
+    // Every position has a switch, row 0 is the number row, row 4 the thumb keys outer to
+    // inner with the encoder's push switch last
+    missing: &[],
+};
+// Both halves are wired the same, col 0 is the outer column
+pub const LEFT: MatrixLayout<ROWS, COLS> = LAYOUT;
+pub const RIGHT: MatrixLayout<ROWS, COLS> = LAYOUT;
+
+pub const KEYMAP: &KeymapRemap = &remap::SOFLE;
+
+/// No switch is on the keymap's reboot key, `reboot usb` on the serial console instead
+pub const LEFT_REBOOT_KEY: Option<MatrixIndex> = None;
+pub const RIGHT_REBOOT_KEY: Option<MatrixIndex> = None;
+
+/// The right half's encoder is mounted mirrored, swapping its pins keeps clockwise the same
+pub const LEFT_ENCODERS: [EncoderPins; 1] = [EncoderPins {
+    a: 9,
+    b: 8,
+    steps_per_detent: StepsPerDetent::Four,
+    switch: None,
+}];
+pub const RIGHT_ENCODERS: [EncoderPins; 1] = [EncoderPins {
+    a: 8,
+    b: 9,
+    steps_per_detent: StepsPerDetent::Four,
+    switch: None,
+}];
+
+pub type OledSize = DisplaySize128x32;
+pub const OLED_SIZE: OledSize = DisplaySize128x32;
+pub const OLED_ROTATION: DisplayRotation = DisplayRotation::Rotate90;
@@ -0,0 +1,234 @@
+//! System clock profiles, switchable at runtime from the console or the Settings layer.
+//! The link's baud follows the system clock, so both halves switch in lockstep: the master's
+//! key core right after it answered a poll with [`MasterToSlave::Clock`], the slave's as soon
+//! as it got that answer, then the slave keeps off the link until the master is done. Asleep
+//! both halves drop to [`IDLE_PROFILE`] and go back to where they were on waking, see
+//! [`IdleClock`]. After losing the link both fall back to [`DEFAULT_PROFILE`] on their own.
+//! Only `clk_sys` changes, `clk_peri` runs off the usb pll so the oled's i2c keeps its baud,
+//! and the timer ticks off the crystal.
+//!
+//! [`MasterToSlave::Clock`]: rp2040_kbd_lib::split::MasterToSlave::Clock
+use core::sync::atomic::{AtomicU8, Ordering};
+use liatris::pac::vreg_and_chip_reset::vreg::VSEL_A;
+use rp2040_hal::fugit::HertzU32;
+use rp2040_hal::pac;
+use rp2040_hal::pll::PLLConfig;
+use rp2040_hal::Timer;
+use rp2040_kbd_lib::split::CLOCK_SWITCH_QUIET_MICROS;
+
+/// FREF is the xosc crystal freq (12Mhz)
+/// POSTDIV(both) 1 -7
+/// If postdiv has different values, POSTDIV1 should be higher
+/// for energy efficiency
+/// Refdiv recommended to be 1
+/// MAX VCO = 1600Mhz
+/// Docs says FBDIV which is, but that can be replaced with VCO since it's a part of it
+/// Calculate by (FREF / REFDIV) * FBDIV / (POSTDIV1 * POSTDIV2)
+/// Where VCO = FREF * FBDIV => FBDIV = VCO / FREF
+/// Ex: (12 / 1) * 133 / (6 * 2) => VCO = 12 * 133 = 1596Mhz
+/// There's a script for finding your optimal clock frequency here:
+/// <https://github.com/raspberrypi/pico-sdk/blob/master/src/rp2_common/hardware_clocks/scripts/vcocalc.py>
+/// Higher VCO-freq decreases jitter but increases power consumption,
+/// The only way to increase VCO is to compromize on output frequency
+pub struct ClockProfile {
+    pub freq: HertzU32,
+    vco_freq: HertzU32,
+    refdiv: u8,
+    post_div1: u8,
+    post_div2: u8,
+    /// Raised before going faster, lowered after going slower
+    voltage: VSEL_A,
+}
+
+impl ClockProfile {
+    pub const fn pll(&self) -> PLLConfig {
+        PLLConfig {
+            vco_freq: self.vco_freq,
+            refdiv: self.refdiv,
+            post_div1: self.post_div1,
+            post_div2: self.post_div2,
+        }
+    }
+
+    pub const fn voltage(&self) -> VSEL_A {
+        self.voltage
+    }
+
+    /// Every profile is in the pll's range of 16 to 320
+    fn fbdiv(&self) -> u16 {
+        let fbdiv = self.vco_freq.to_Hz() / (liatris::XOSC_CRYSTAL_FREQ / u32::from(self.refdiv));
+        #[expect(clippy::cast_possible_truncation)]
+        let fbdiv = fbdiv as u16;
+        fbdiv
+    }
+}
+
+/// Slowest first, indexed by [`rp2040_kbd_lib::split::MasterToSlave::Clock`].
+/// The chip's default 1.10V is rated for up to 133Mhz.
+pub static PROFILES: [ClockProfile; 3] = [
+    ClockProfile {
+        freq: HertzU32::MHz(48),
+        vco_freq: HertzU32::MHz(960),
+        refdiv: 1,
+        post_div1: 5,
+        post_div2: 4,
+        voltage: VSEL_A::VOLTAGE1_10,
+    },
+    ClockProfile {
+        freq: HertzU32::MHz(133),
+        vco_freq: HertzU32::MHz(1596),
+        refdiv: 1,
+        post_div1: 6,
+        post_div2: 2,
+        voltage: VSEL_A::VOLTAGE1_10,
+    },
+    ClockProfile {
+        freq: HertzU32::kHz(199_500),
+        vco_freq: HertzU32::MHz(1596),
+        refdiv: 1,
+        post_div1: 4,
+        post_div2: 2,
+        voltage: VSEL_A::VOLTAGE1_15,
+    },
+];
+
+/// What both halves boot into, 199.5Mhz
+pub const DEFAULT_PROFILE: u8 = 2;
+
+/// Asleep both halves drop to this, 48Mhz at the chip's default voltage
+pub const IDLE_PROFILE: u8 = 0;
+
+/// Generous, the regulator gets there well before
+const VREG_SETTLE_NANOS: u64 = 1_000_000;
+
+// The slave's quiet window has to cover the settling and the pll relocking
+const _: () = assert!(VREG_SETTLE_NANOS / 1_000 * 3 / 2 <= CLOCK_SWITCH_QUIET_MICROS);
+
+/// The profile the system clock runs at, only written by the key core when it switches
+static RUNNING: AtomicU8 = AtomicU8::new(DEFAULT_PROFILE);
+
+/// Asked for from the console or the keymap, the master's key core takes both halves there
+static REQUESTED: AtomicU8 = AtomicU8::new(DEFAULT_PROFILE);
+
+#[inline]
+pub fn running() -> u8 {
+    RUNNING.load(Ordering::Relaxed)
+}
+
+#[inline]
+pub fn system_freq() -> HertzU32 {
+    PROFILES[usize::from(running())].freq
+}
+
+#[cfg(feature = "serial")]
+/// Index of the profile at `mhz`, rounded down like 199.5Mhz to 199
+pub fn find_mhz(mhz: u32) -> Option<u8> {
+    (0u8..)
+        .zip(&PROFILES)
+        .find_map(|(index, profile)| (profile.freq.to_MHz() == mhz).then_some(index))
+}
+
+/// Unknown profiles are ignored
+#[inline]
+pub fn request(profile: u8) {
+    if usize::from(profile) < PROFILES.len() {
+        REQUESTED.store(profile, Ordering::Relaxed);
+    }
+}
+
+#[inline]
+pub fn requested() -> u8 {
+    REQUESTED.load(Ordering::Relaxed)
+}
+
+/// Where a half goes back to once it's awake again. Both halves go idle on the same
+/// `Awake(false)` from the same profile, so they come back to the same one too.
+pub struct IdleClock {
+    resume: Option<u8>,
+}
+
+impl IdleClock {
+    pub const fn new() -> Self {
+        Self { resume: None }
+    }
+
+    /// The profile to switch to for an `Awake` the master sent, `None` if it's no change,
+    /// the master repeats it
+    pub fn awake(&mut self, awake: bool) -> Option<u8> {
+        if awake {
+            self.resume.take()
+        } else if self.resume.is_none() {
+            self.resume = Some(running());
+            Some(IDLE_PROFILE)
+        } else {
+            None
+        }
+    }
+
+    /// Both halves are back on the default after the link went down
+    pub fn forget(&mut self) {
+        self.resume = None;
+    }
+}
+
+/// Reprograms the system pll for `profile`, has to be called from the key core.
+/// Returns false if it's already running or doesn't exist.
+/// The other core keeps going through this, off `clk_ref` while the pll relocks.
+pub fn switch_to(profile: u8, timer: Timer) -> bool {
+    let current = &PROFILES[usize::from(running())];
+    let Some(next) = PROFILES.get(usize::from(profile)) else {
+        return false;
+    };
+    if core::ptr::eq(current, next) {
+        return false;
+    }
+    let faster = next.freq > current.freq;
+    if faster {
+        set_voltage(current.voltage, next.voltage, timer);
+    }
+    // Safety: Only the key core switches, nothing else touches `clk_sys` or the system pll
+    // after boot. The glitchless mux is moved off the pll before it's reprogrammed.
+    unsafe {
+        let clocks = &*pac::CLOCKS::ptr();
+        clocks.clk_sys_ctrl().modify(|_, w| w.src().clk_ref());
+        while clocks.clk_sys_selected().read().bits() != 1 {}
+        let pll = &*pac::PLL_SYS::ptr();
+        pll.pwr()
+            .modify(|_, w| w.pd().set_bit().vcopd().set_bit().postdivpd().set_bit());
+        pll.cs().write(|w| w.refdiv().bits(next.refdiv));
+        pll.fbdiv_int().write(|w| w.fbdiv_int().bits(next.fbdiv()));
+        pll.pwr()
+            .modify(|_, w| w.pd().clear_bit().vcopd().clear_bit());
+        while pll.cs().read().lock().bit_is_clear() {}
+        pll.prim().write(|w| {
+            w.postdiv1()
+                .bits(next.post_div1)
+                .postdiv2()
+                .bits(next.post_div2)
+        });
+        pll.pwr().modify(|_, w| w.postdivpd().clear_bit());
+        // The aux mux was set to the system pll at boot and never changes
+        clocks
+            .clk_sys_ctrl()
+            .modify(|_, w| w.src().clksrc_clk_sys_aux());
+        while clocks.clk_sys_selected().read().bits() != 1 << 1 {}
+    }
+    if !faster {
+        set_voltage(current.voltage, next.voltage, timer);
+    }
+    RUNNING.store(profile, Ordering::Relaxed);
+    true
+}
+
+fn set_voltage(current: VSEL_A, next: VSEL_A, timer: Timer) {
+    if current == next {
+        return;
+    }
+    // Safety: Only written at boot and by `switch_to`
+    unsafe {
+        (*pac::VREG_AND_CHIP_RESET::ptr())
+            .vreg()
+            .modify(|_, w| w.vsel().variant(next));
+    }
+    crate::timer::wait_nanos(timer, VREG_SETTLE_NANOS);
+}
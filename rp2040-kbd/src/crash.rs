@@ -0,0 +1,87 @@
+//! Panics end in a watchdog reset, the report survives it in ram that isn't initialized at
+//! boot. The next boot shows it on the oled once, the console has it until power is lost.
+use crate::runtime::locks::CrashLock;
+use crate::watchdog;
+use core::fmt::Write;
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicBool, Ordering};
+use rp2040_hal::Sio;
+use rp2040_kbd_lib::crash::CrashReport;
+use rp2040_kbd_lib::reset::ResetReason;
+
+/// Garbage after a power cycle, only read through [`read`]
+#[link_section = ".uninit.CRASH_REPORT"]
+static mut CRASH_REPORT: MaybeUninit<CrashReport> = MaybeUninit::uninit();
+
+/// By core, a panic while writing the report goes straight to the reset
+static RECORDING: [AtomicBool; 2] = [const { AtomicBool::new(false) }; 2];
+
+/// Called from the panic handler, on either core
+pub fn record_and_reset(info: &core::panic::PanicInfo) -> ! {
+    let core = Sio::core() as u8;
+    let recording = &RECORDING[usize::from(core)];
+    if recording.load(Ordering::Relaxed) {
+        watchdog::reset(ResetReason::Panic(core));
+    }
+    recording.store(true, Ordering::Relaxed);
+    // Both cores panicking at once, the first one writes its report and resets the chip
+    let Some(_lock) = CrashLock::try_claim() else {
+        loop {
+            core::sync::atomic::compiler_fence(Ordering::SeqCst);
+        }
+    };
+    let mut report = CrashReport::new(core);
+    if let Some(location) = info.location() {
+        report.set_location(location.file(), location.line(), location.column());
+    }
+    let _ = write!(report, "{}", info.message());
+    report.seal();
+    write(report);
+    watchdog::reset(ResetReason::Panic(core))
+}
+
+/// A report that no boot has shown yet, it's marked as shown
+pub fn take_new() -> Option<CrashReport> {
+    let mut report = read();
+    if !report.is_new() {
+        return None;
+    }
+    let shown = report;
+    report.mark_seen();
+    write(report);
+    Some(shown)
+}
+
+/// The last panic, since power was lost or the report was cleared
+#[cfg(feature = "serial")]
+pub fn last() -> Option<CrashReport> {
+    let report = read();
+    report.is_valid().then_some(report)
+}
+
+#[cfg(feature = "serial")]
+pub fn clear() {
+    let mut report = read();
+    report.clear();
+    write(report);
+}
+
+/// Every field takes any bits, whatever was left in ram reads as some report, which then
+/// has to pass [`CrashReport::is_valid`]
+fn read() -> CrashReport {
+    // Safety: Aligned and in bounds, panics and the admin core are the only writers
+    unsafe {
+        core::ptr::addr_of!(CRASH_REPORT)
+            .cast::<CrashReport>()
+            .read_volatile()
+    }
+}
+
+fn write(report: CrashReport) {
+    // Safety: See `read`
+    unsafe {
+        core::ptr::addr_of_mut!(CRASH_REPORT)
+            .cast::<CrashReport>()
+            .write_volatile(report);
+    }
+}
@@ -1,13 +1,20 @@
+use rp2040_hal::pac;
 use rp2040_hal::usb::UsbBus;
 use usb_device::bus::UsbBusAllocator;
-use usb_device::device::{StringDescriptors, UsbDevice};
+use usb_device::device::{StringDescriptors, UsbDevice, UsbDeviceState};
 use usbd_hid::descriptor::{KeyboardReport, SerializedDescriptor};
-use usbd_hid::hid_class::HIDClass;
+use usbd_hid::hid_class::{HIDClass, ReportType};
+
+/// The hid class's interrupt in endpoint, the first one allocated after the control endpoint
+const REPORT_EP: u32 = 1;
+/// Buffer status has an in and an out bit per endpoint, in first
+const REPORT_SENT_BIT: u32 = 1 << (REPORT_EP * 2);
 
 pub struct UsbHiddev<'a> {
     hid: HIDClass<'a, UsbBus>,
     dev: UsbDevice<'a, UsbBus>,
     ready: bool,
+    leds: u8,
 }
 
 impl<'a> UsbHiddev<'a> {
@@ -28,17 +35,20 @@ impl<'a> UsbHiddev<'a> {
             .serial_number("1")])
         .unwrap()
         .device_class(0)
+        .supports_remote_wakeup(true)
         .build()
         .unwrap();
         Self {
             hid,
             dev,
             ready: true,
+            leds: 0,
         }
     }
 
     pub fn try_submit_report(&mut self, keyboard_report: &KeyboardReport) -> bool {
-        if self.ready {
+        // Hold reports while the host is asleep, they go out after resume
+        if self.ready && !self.is_suspended() {
             let res = self.hid.push_input(keyboard_report).is_ok();
             self.ready = false;
             res
@@ -51,8 +61,45 @@ impl<'a> UsbHiddev<'a> {
     // from the OS-negotiated interrupt scheduling.
     // Could cache a value and immediately submit, but the producer
     // outpaces the os significantly so there's no need at the moment (42micros vs 1000 micros poll latency at time of writing)
-    pub fn poll(&mut self) {
+    /// Returns true if the last submitted report's in transfer completed, the host has it
+    #[must_use]
+    pub fn poll(&mut self) -> bool {
+        // Safety: Read only, the bus clears the bit when it handles it in the poll below
+        let buff_status = unsafe { (*pac::USBCTRL_REGS::ptr()).buff_status().read().bits() };
         self.dev.poll(&mut [&mut self.hid]);
         self.ready = true;
+        // The host sets the lock leds through an output report on the control endpoint
+        let mut buf = [0u8; 1];
+        if let Ok(info) = self.hid.pull_raw_report(&mut buf) {
+            if matches!(info.report_type, ReportType::Output) && info.len > 0 {
+                self.leds = buf[0];
+            }
+        }
+        buff_status & REPORT_SENT_BIT != 0
+    }
+
+    #[inline]
+    pub fn host_leds(&self) -> u8 {
+        self.leds
+    }
+
+    #[inline]
+    pub fn is_configured(&self) -> bool {
+        self.dev.state() == UsbDeviceState::Configured
+    }
+
+    #[inline]
+    pub fn is_suspended(&self) -> bool {
+        self.dev.state() == UsbDeviceState::Suspend
+    }
+
+    /// Signal resume to a suspended host, if the host has allowed it
+    pub fn remote_wakeup(&self) -> bool {
+        if self.is_suspended() && self.dev.remote_wakeup_enabled() {
+            self.dev.bus().remote_wakeup();
+            true
+        } else {
+            false
+        }
     }
 }
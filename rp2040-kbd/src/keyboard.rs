@@ -1,21 +1,116 @@
 //! Common between sides, put everything with the same pinouts and shared hardware
 //! code here
-pub mod debounce;
-#[cfg(feature = "left")]
+pub mod encoder;
 pub mod left;
 pub mod oled;
+#[cfg(feature = "pio-scan")]
+pub mod pio_scanner;
 pub mod power_led;
-#[cfg(feature = "right")]
 pub mod right;
+pub mod scanner;
 pub mod split_serial;
 #[cfg(feature = "serial")]
 pub mod usb_serial;
+pub mod wake;
 
+use rp2040_hal::fugit::HertzU32;
 use rp2040_hal::gpio::{FunctionSio, Pin, PullUp, SioInput};
+use rp2040_hal::timer::Alarm0;
+use rp2040_hal::Timer;
+use rp2040_kbd_lib::matrix::MatrixUpdate;
 
 pub type ButtonPin<Id> = Pin<Id, FunctionSio<SioInput>, PullUp>;
 
-#[cfg(all(feature = "serial", feature = "left"))]
+// Effectively, maximum presses per single key becomes is 1 per 20 millis, that's a 600 WPM with a single finger
+pub const DEBOUNCE_MICROS: u32 = 10_000;
+
+/// A half's key debouncer, with `serial` it also keeps stats for the `chatter` command
+#[cfg(feature = "serial")]
+pub type Tracked<D> = rp2040_kbd_lib::debounce::ChatterTracker<D, u64>;
+#[cfg(not(feature = "serial"))]
+pub type Tracked<D> = D;
+
+#[cfg(feature = "serial")]
+pub const fn tracked<D>(debouncer: D) -> Tracked<D> {
+    rp2040_kbd_lib::debounce::ChatterTracker::new(debouncer)
+}
+
+#[cfg(not(feature = "serial"))]
+pub const fn tracked<D>(debouncer: D) -> Tracked<D> {
+    debouncer
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+impl Side {
+    #[inline]
+    pub const fn other(self) -> Self {
+        match self {
+            Side::Left => Side::Right,
+            Side::Right => Side::Left,
+        }
+    }
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Side::Left => "LEFT",
+            Side::Right => "RIGHT",
+        }
+    }
+}
+
+/// Receives debounced changes from a half's own keys
+pub trait MatrixSink {
+    /// `detected_at` is the low half of the timer's micros when the scan saw the change
+    fn matrix_update(&mut self, update: MatrixUpdate, detected_at: u32);
+}
+
+/// The keys on this half, matrix indices are always local to the half, whoever runs the
+/// keymap uses [`Self::SIDE`] to tell them apart from the other half's.
+pub trait LocalMatrix {
+    const SIDE: Side;
+
+    /// Pressing this reboots into usb boot when this half is the slave,
+    /// the master has one in its keymap. Boards without a spare switch have none.
+    const REBOOT_KEY: Option<MatrixUpdate>;
+
+    /// Returns the number of changes
+    fn scan_matrix(&mut self, timer: Timer, sink: &mut impl MatrixSink) -> u16;
+
+    /// Returns true if any encoder moved or had its push switch change
+    fn scan_encoder(&mut self, timer: Timer, sink: &mut impl MatrixSink) -> bool;
+
+    /// Gets ready for the key core to park, the first press or encoder movement afterwards
+    /// raises an interrupt on the calling core, see [`wake`]
+    fn arm_wake(&mut self);
+
+    /// Undoes [`Self::arm_wake`], scans work as usual again
+    fn disarm_wake(&mut self);
+
+    /// Parks the key core while idle, for `micros` or until a key or encoder moves.
+    /// Callers [`wake::reset`] first, anything else they arm before also wakes it.
+    fn park(&mut self, alarm: &mut Alarm0, micros: u32, wake_early: impl Fn() -> bool) {
+        self.arm_wake();
+        crate::timer::park_micros(alarm, micros, || wake::woken() || wake_early());
+        self.disarm_wake();
+    }
+
+    /// The system clock now runs at `system_freq`, for whatever was timed off the old one
+    fn clock_changed(&mut self, system_freq: HertzU32);
+
+    /// Switches the system clock to `profile` if it isn't running already, see [`crate::clock`]
+    fn switch_clock(&mut self, profile: u8, timer: Timer) {
+        if crate::clock::switch_to(profile, timer) {
+            self.clock_changed(crate::clock::system_freq());
+        }
+    }
+}
+
+#[cfg(feature = "serial")]
 pub const fn matrix_ind_to_row_col(matrix_ind: u8) -> (u8, u8) {
     (
         matrix_ind / rp2040_kbd_lib::matrix::NUM_COLS,
@@ -0,0 +1,134 @@
+use crate::keyboard::{wake, ButtonPin, MatrixSink, DEBOUNCE_MICROS};
+use embedded_hal::digital::InputPin;
+use rp2040_hal::gpio::{DynPinId, Interrupt};
+use rp2040_hal::Timer;
+use rp2040_kbd_lib::debounce::{Debouncer, EagerPerKey};
+use rp2040_kbd_lib::encoder::{
+    AccelerationCurve, EncoderVelocity, QuadratureDecoder, StepsPerDetent,
+};
+use rp2040_kbd_lib::matrix::{MatrixIndex, MatrixUpdate};
+
+/// Encoders can sit on any pins, so they don't carry the pin in their type
+pub type EncoderPin = ButtonPin<DynPinId>;
+
+/// Bindings pick how many of these steps they act on
+const ENCODER_ACCELERATION: AccelerationCurve = AccelerationCurve::DEFAULT;
+
+/// A switch on its own pin, reported as a key at `key` in the half's matrix.
+/// Not needed when the switch is wired into the matrix, like on the lily58.
+struct PushSwitch {
+    pin: EncoderPin,
+    key: MatrixIndex,
+    debounce: EagerPerKey<u64>,
+}
+
+pub struct RotaryEncoder {
+    a: EncoderPin,
+    b: EncoderPin,
+    decoder: QuadratureDecoder,
+    velocity: EncoderVelocity,
+    switch: Option<PushSwitch>,
+}
+
+impl RotaryEncoder {
+    pub fn new(a: EncoderPin, b: EncoderPin, steps_per_detent: StepsPerDetent) -> Self {
+        Self {
+            a,
+            b,
+            decoder: QuadratureDecoder::new(steps_per_detent, false),
+            velocity: EncoderVelocity::new(),
+            switch: None,
+        }
+    }
+
+    /// `key` should be a free slot in the half's matrix, the keymap sees it as any other key
+    #[must_use]
+    pub fn with_switch(self, pin: EncoderPin, key: MatrixIndex) -> Self {
+        Self {
+            switch: Some(PushSwitch {
+                pin,
+                key,
+                debounce: EagerPerKey::new(DEBOUNCE_MICROS),
+            }),
+            ..self
+        }
+    }
+
+    /// Returns true if the encoder clicked into a new detent or its switch changed
+    fn scan(&mut self, index: u8, timer: Timer, sink: &mut impl MatrixSink) -> bool {
+        let mut changed = false;
+        if let Some(switch) = &mut self.switch {
+            let raw = matches!(switch.pin.is_low(), Ok(true));
+            if let Some(pressed) = switch.debounce.update(timer.get_counter().ticks(), raw) {
+                sink.matrix_update(
+                    MatrixUpdate::from_key_update(switch.key, pressed),
+                    timer.get_counter_low(),
+                );
+                changed = true;
+            }
+        }
+        if let Some(clockwise) = self.decoder.update(
+            matches!(self.a.is_high(), Ok(true)),
+            matches!(self.b.is_high(), Ok(true)),
+        ) {
+            let steps = self.velocity.detent(
+                timer.get_counter().ticks(),
+                clockwise,
+                &ENCODER_ACCELERATION,
+            );
+            if let Some(update) = MatrixUpdate::from_encoder_steps(index, steps) {
+                sink.matrix_update(update, timer.get_counter_low());
+            }
+            changed = true;
+        }
+        changed
+    }
+}
+
+/// All encoders on a half, their position here is their index in the split protocol
+/// and the keymap
+pub struct Encoders<const N: usize>([RotaryEncoder; N]);
+
+impl<const N: usize> Encoders<N> {
+    pub const fn new(encoders: [RotaryEncoder; N]) -> Self {
+        const {
+            assert!(N <= MatrixUpdate::MAX_ENCODERS as usize);
+        }
+        Self(encoders)
+    }
+
+    /// Every edge on either contact wakes, a switch wakes when pressed
+    pub fn arm_wake(&mut self) {
+        for encoder in &mut self.0 {
+            for pin in [&mut encoder.a, &mut encoder.b] {
+                wake::arm(pin, Interrupt::EdgeLow);
+                wake::arm(pin, Interrupt::EdgeHigh);
+            }
+            if let Some(switch) = &mut encoder.switch {
+                wake::arm(&mut switch.pin, Interrupt::EdgeLow);
+            }
+        }
+    }
+
+    pub fn disarm_wake(&mut self) {
+        for encoder in &mut self.0 {
+            for pin in [&mut encoder.a, &mut encoder.b] {
+                wake::disarm(pin, Interrupt::EdgeLow);
+                wake::disarm(pin, Interrupt::EdgeHigh);
+            }
+            if let Some(switch) = &mut encoder.switch {
+                wake::disarm(&mut switch.pin, Interrupt::EdgeLow);
+            }
+        }
+    }
+
+    /// Returns true if any encoder moved or had its switch change
+    #[inline]
+    pub fn scan(&mut self, timer: Timer, sink: &mut impl MatrixSink) -> bool {
+        let mut changed = false;
+        for (index, encoder) in (0..).zip(self.0.iter_mut()) {
+            changed |= encoder.scan(index, timer, sink);
+        }
+        changed
+    }
+}
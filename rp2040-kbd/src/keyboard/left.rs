@@ -1,53 +1,72 @@
-pub(crate) mod message_receiver;
+use crate::board::{self, PinBank};
+use crate::keyboard::encoder::Encoders;
+#[cfg(feature = "pio-scan")]
+use crate::keyboard::pio_scanner::{PioMatrixScanner, PioScanHardware};
+#[cfg(not(feature = "pio-scan"))]
+use crate::keyboard::scanner::MatrixScanner;
+use crate::keyboard::{LocalMatrix, MatrixSink, Side, DEBOUNCE_MICROS};
+use rp2040_hal::fugit::HertzU32;
+use rp2040_hal::Timer;
+use rp2040_kbd_lib::debounce::EagerPerKey;
+use rp2040_kbd_lib::matrix::MatrixUpdate;
 
-use crate::keyboard::ButtonPin;
-use rp2040_hal::gpio::bank0::{
-    Gpio20, Gpio21, Gpio22, Gpio23, Gpio26, Gpio27, Gpio29, Gpio6, Gpio7, Gpio8, Gpio9,
-};
-
-pub const ROW0: u32 = 1 << 29;
-pub const ROW1: u32 = 1 << 27;
-pub const ROW2: u32 = 1 << 6;
-pub const ROW3: u32 = 1 << 7;
-pub const ROW4: u32 = 1 << 8;
-pub const ROW_MASK: u32 = ROW0 | ROW1 | ROW2 | ROW3 | ROW4;
+/// Any of the lib's debouncers fit here, fed the timer's micros
+type KeyDebouncer = EagerPerKey<u64>;
+const KEY_DEBOUNCER: KeyDebouncer = EagerPerKey::new(DEBOUNCE_MICROS);
 
 pub struct LeftButtons {
-    pub _rows: (
-        ButtonPin<Gpio29>,
-        ButtonPin<Gpio27>,
-        ButtonPin<Gpio6>,
-        ButtonPin<Gpio7>,
-        ButtonPin<Gpio8>,
-    ),
-    pub cols: (
-        Option<ButtonPin<Gpio9>>,
-        Option<ButtonPin<Gpio26>>,
-        Option<ButtonPin<Gpio22>>,
-        Option<ButtonPin<Gpio20>>,
-        Option<ButtonPin<Gpio23>>,
-        Option<ButtonPin<Gpio21>>,
-    ),
+    #[cfg(not(feature = "pio-scan"))]
+    matrix: MatrixScanner<KeyDebouncer, { board::ROWS }, { board::COLS }>,
+    #[cfg(feature = "pio-scan")]
+    matrix: PioMatrixScanner<KeyDebouncer, { board::ROWS }, { board::COLS }>,
+    encoders: Encoders<{ board::LEFT_ENCODERS.len() }>,
 }
 
 impl LeftButtons {
+    /// Takes the pins in the board's [`board::LEFT`] layout and its encoders
     pub fn new(
-        rows: (
-            ButtonPin<Gpio29>,
-            ButtonPin<Gpio27>,
-            ButtonPin<Gpio6>,
-            ButtonPin<Gpio7>,
-            ButtonPin<Gpio8>,
-        ),
-        cols: (
-            Option<ButtonPin<Gpio9>>,
-            Option<ButtonPin<Gpio26>>,
-            Option<ButtonPin<Gpio22>>,
-            Option<ButtonPin<Gpio20>>,
-            Option<ButtonPin<Gpio23>>,
-            Option<ButtonPin<Gpio21>>,
-        ),
+        pins: &mut PinBank,
+        #[cfg(feature = "pio-scan")] scan_hardware: PioScanHardware,
     ) -> Self {
-        Self { _rows: rows, cols }
+        let (rows, cols) = pins.matrix(&board::LEFT);
+        Self {
+            #[cfg(not(feature = "pio-scan"))]
+            matrix: MatrixScanner::new(&board::LEFT, rows, cols, KEY_DEBOUNCER),
+            #[cfg(feature = "pio-scan")]
+            matrix: PioMatrixScanner::new(&board::LEFT, rows, cols, KEY_DEBOUNCER, scan_hardware),
+            encoders: pins.encoders(&board::LEFT_ENCODERS),
+        }
+    }
+}
+
+impl LocalMatrix for LeftButtons {
+    const SIDE: Side = Side::Left;
+    const REBOOT_KEY: Option<MatrixUpdate> = match board::LEFT_REBOOT_KEY {
+        Some(key) => Some(MatrixUpdate::from_key_update(key, true)),
+        None => None,
+    };
+
+    #[inline]
+    fn scan_matrix(&mut self, timer: Timer, sink: &mut impl MatrixSink) -> u16 {
+        self.matrix.scan(timer, sink)
+    }
+
+    #[inline]
+    fn scan_encoder(&mut self, timer: Timer, sink: &mut impl MatrixSink) -> bool {
+        self.encoders.scan(timer, sink)
+    }
+
+    fn arm_wake(&mut self) {
+        self.matrix.arm_wake();
+        self.encoders.arm_wake();
+    }
+
+    fn disarm_wake(&mut self) {
+        self.encoders.disarm_wake();
+        self.matrix.disarm_wake();
+    }
+
+    fn clock_changed(&mut self, system_freq: HertzU32) {
+        self.matrix.clock_changed(system_freq);
     }
 }
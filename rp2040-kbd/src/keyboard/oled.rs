@@ -1,8 +1,8 @@
-#[cfg(feature = "left")]
-pub mod left;
-#[cfg(feature = "right")]
-pub mod right;
+pub mod master;
+pub mod slave;
 
+use crate::board::OledSize;
+use core::fmt::Write;
 use embedded_graphics::draw_target::DrawTarget;
 use embedded_graphics::geometry::Point;
 use embedded_graphics::mono_font::iso_8859_4::{FONT_4X6, FONT_5X7};
@@ -15,8 +15,10 @@ use embedded_graphics::Drawable;
 use liatris::pac::I2C1;
 use rp2040_hal::gpio::bank0::{Gpio2, Gpio3};
 use rp2040_hal::gpio::{FunctionI2c, PullUp};
+use rp2040_kbd_lib::crash::CrashReport;
+use rp2040_kbd_lib::reset::ResetReason;
 use ssd1306::mode::BufferedGraphicsMode;
-use ssd1306::prelude::{Brightness, DisplaySize128x32, I2CInterface};
+use ssd1306::prelude::{Brightness, I2CInterface};
 use ssd1306::Ssd1306;
 
 #[macro_export]
@@ -33,6 +35,19 @@ pub type OledLineString = heapless::String<8>;
 pub const OLED_LINE_HEIGHT: u32 = 8;
 pub const OLED_LINE_WIDTH: u32 = 32;
 
+/// Micros in at most 4 characters, switching to millis and then seconds, a line is that narrow
+pub struct CompactMicros(pub u32);
+
+impl core::fmt::Display for CompactMicros {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self.0 {
+            micros @ 0..1_000 => write!(f, "{micros}"),
+            micros @ 1_000..1_000_000 => write!(f, "{}m", micros / 1_000),
+            micros => write!(f, "{}s", (micros / 1_000_000).min(999)),
+        }
+    }
+}
+
 pub struct DrawUnit {
     pub content: OledLineString,
     pub needs_redraw: bool,
@@ -58,8 +73,8 @@ pub struct OledHandle {
                 ),
             >,
         >,
-        DisplaySize128x32,
-        BufferedGraphicsMode<DisplaySize128x32>,
+        OledSize,
+        BufferedGraphicsMode<OledSize>,
     >,
 }
 
@@ -75,8 +90,8 @@ impl OledHandle {
                     ),
                 >,
             >,
-            DisplaySize128x32,
-            BufferedGraphicsMode<DisplaySize128x32>,
+            OledSize,
+            BufferedGraphicsMode<OledSize>,
         >,
     ) -> Self {
         let _ = display.set_brightness(Brightness::BRIGHTEST);
@@ -156,13 +171,36 @@ impl OledHandle {
         }
     }
     #[inline(never)]
-    pub fn write_bad_boot_msg(&mut self) {
+    pub fn write_bad_side_msg(&mut self) {
         let _ = self.display.clear(BinaryColor::Off);
         let _ = self.display.flush();
-        let _ = self.write(0, "BAD");
-        let _ = self.write(9, "IMAGE");
-        let _ = self.write(18, "FORCE");
-        let _ = self.write(27, "BOOT");
+        let _ = self.write(0, "SIDE");
+        let _ = self.write(9, "PIN");
+        let _ = self.write(18, "UNCLEAR");
+        let _ = self.write(27, "RESET");
+        let _ = self.display.flush();
+    }
+
+    /// Which core panicked and where, the file cut to fit
+    pub fn write_crash_msg(&mut self, report: &CrashReport) {
+        let _ = self.display.clear(BinaryColor::Off);
+        let _ = self.write(0, "CRASH");
+        let mut line = OledLineString::new();
+        let _ = line.write_fmt(format_args!("CORE{}", report.core()));
+        let _ = self.write(9, &line);
+        let stem = report.file_stem();
+        let _ = self.write(18, stem.get(..8).unwrap_or(stem));
+        line.clear();
+        let _ = line.write_fmt(format_args!("L{}", report.line()));
+        let _ = self.write(27, &line);
+        let _ = self.display.flush();
+    }
+
+    /// A reset that something went wrong before, without a panic to show
+    pub fn write_reset_msg(&mut self, reason: ResetReason) {
+        let _ = self.display.clear(BinaryColor::Off);
+        let _ = self.write(0, "RESET");
+        let _ = self.write(9, reason.short_name());
         let _ = self.display.flush();
     }
 }
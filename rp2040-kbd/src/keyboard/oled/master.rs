@@ -1,18 +1,23 @@
-use crate::keyboard::oled::{DrawUnit, OledHandle, OledLineString};
+use crate::keyboard::oled::{CompactMicros, DrawUnit, OledHandle, OledLineString};
+use crate::keyboard::Side;
 use crate::static_draw_unit_string;
 use core::fmt::Write;
 use rp2040_hal::fugit::HertzU32;
+use rp2040_kbd_lib::histogram::LatencySummary;
 
-pub struct LeftOledDrawer {
+pub struct MasterOledDrawer {
     handle: OledHandle,
+    side: Side,
     hidden: bool,
     header: DrawUnit,
     scan_loop_header: DrawUnit,
     scan_loop_content: DrawUnit,
     press_left_loop_content: DrawUnit,
     press_right_loop_content: DrawUnit,
-    dbg_header: DrawUnit,
+    link_header: DrawUnit,
+    link_status: DrawUnit,
     dbg_rx: DrawUnit,
+    dbg_errors: DrawUnit,
     dbg_queue: DrawUnit,
     clk_header: DrawUnit,
     clk_freq: DrawUnit,
@@ -21,29 +26,35 @@ pub struct LeftOledDrawer {
     underscores_need_redraw: bool,
 }
 
-impl LeftOledDrawer {
-    pub fn new(handle: OledHandle) -> Self {
-        let header_content = static_draw_unit_string!("LEFT");
-        let scan_loop_header_content = static_draw_unit_string!("PERF");
+impl MasterOledDrawer {
+    pub fn new(handle: OledHandle, side: Side) -> Self {
+        let mut header_content = OledLineString::new();
+        let _ = header_content.push_str(side.name());
+        let scan_loop_header_content = static_draw_unit_string!("P99");
         let scan_loop_content = static_draw_unit_string!("S ...");
         let press_left_loop_content = static_draw_unit_string!("L ...");
         let press_right_loop_content = static_draw_unit_string!("R ...");
-        let dbg_header = static_draw_unit_string!("DEBUG");
+        let link_header = static_draw_unit_string!("LINK");
+        let link_status = static_draw_unit_string!("...");
         let dbg_rx = static_draw_unit_string!("R ...");
+        let dbg_errors = static_draw_unit_string!("E 0");
         let dbg_queue = static_draw_unit_string!("Q ...");
         let clk_header = static_draw_unit_string!("CLOCK");
         let clk_freq = static_draw_unit_string!("...");
         let layer_header = static_draw_unit_string!("LAYER");
         Self {
             handle,
+            side,
             hidden: false,
             header: DrawUnit::new(header_content, true),
             scan_loop_header: DrawUnit::new(scan_loop_header_content, true),
             scan_loop_content: DrawUnit::new(scan_loop_content, true),
             press_left_loop_content: DrawUnit::new(press_left_loop_content, true),
             press_right_loop_content: DrawUnit::new(press_right_loop_content, true),
-            dbg_header: DrawUnit::new(dbg_header, true),
+            link_header: DrawUnit::new(link_header, true),
+            link_status: DrawUnit::new(link_status, true),
             dbg_rx: DrawUnit::new(dbg_rx, true),
+            dbg_errors: DrawUnit::new(dbg_errors, true),
             dbg_queue: DrawUnit::new(dbg_queue, true),
             clk_header: DrawUnit::new(clk_header, true),
             clk_freq: DrawUnit::new(clk_freq, true),
@@ -65,8 +76,10 @@ impl LeftOledDrawer {
             self.scan_loop_content.needs_redraw = true;
             self.press_left_loop_content.needs_redraw = true;
             self.press_right_loop_content.needs_redraw = true;
-            self.dbg_header.needs_redraw = true;
+            self.link_header.needs_redraw = true;
+            self.link_status.needs_redraw = true;
             self.dbg_rx.needs_redraw = true;
+            self.dbg_errors.needs_redraw = true;
             self.dbg_queue.needs_redraw = true;
             self.clk_header.needs_redraw = true;
             self.clk_freq.needs_redraw = true;
@@ -77,30 +90,30 @@ impl LeftOledDrawer {
         self.hidden = false;
     }
 
-    pub fn update_scan_loop(&mut self, avg_scan_latency: f32) {
+    pub fn update_scan_loop(&mut self, scan: LatencySummary) {
         self.scan_loop_content.content.clear();
         let _ = self
             .scan_loop_content
             .content
-            .write_fmt(format_args!("S {avg_scan_latency:.1}"));
+            .write_fmt(format_args!("S {}", CompactMicros(scan.p99)));
         self.scan_loop_content.needs_redraw = true;
     }
 
-    pub fn update_left_counter(&mut self, avg_latency: f32) {
+    pub fn update_left_counter(&mut self, latency: LatencySummary) {
         self.press_left_loop_content.content.clear();
         let _ = self
             .press_left_loop_content
             .content
-            .write_fmt(format_args!("L {avg_latency:.1}"));
+            .write_fmt(format_args!("L {}", CompactMicros(latency.p99)));
         self.press_left_loop_content.needs_redraw = true;
     }
 
-    pub fn update_right_counter(&mut self, avg_latency: f32) {
+    pub fn update_right_counter(&mut self, latency: LatencySummary) {
         self.press_right_loop_content.content.clear();
         let _ = self
             .press_right_loop_content
             .content
-            .write_fmt(format_args!("R {avg_latency:.1}"));
+            .write_fmt(format_args!("R {}", CompactMicros(latency.p99)));
         self.press_right_loop_content.needs_redraw = true;
     }
 
@@ -115,6 +128,25 @@ impl LeftOledDrawer {
         self.dbg_rx.needs_redraw = true;
     }
 
+    pub fn update_link(&mut self, up: bool) {
+        self.link_status.content.clear();
+        let _ = self
+            .link_status
+            .content
+            .push_str(if up { "OK" } else { "LOST" });
+        self.link_status.needs_redraw = true;
+    }
+
+    /// Corrupt and missed frames
+    pub fn update_link_errors(&mut self, count: u32) {
+        self.dbg_errors.content.clear();
+        let _ = self
+            .dbg_errors
+            .content
+            .write_fmt(format_args!("E {}", count % 10_000));
+        self.dbg_errors.needs_redraw = true;
+    }
+
     pub fn update_queue(&mut self, count: usize) {
         self.dbg_queue.content.clear();
         let _ = self.dbg_queue.content.write_fmt(format_args!("Q {count}"));
@@ -127,8 +159,10 @@ impl LeftOledDrawer {
             .clk_freq
             .content
             .write_fmt(format_args!("{}Mhz", freq.to_MHz()));
+        self.clk_freq.needs_redraw = true;
     }
 
+    #[expect(clippy::too_many_lines)]
     pub fn render(&mut self) {
         if self.hidden {
             return;
@@ -166,49 +200,65 @@ impl LeftOledDrawer {
                 .write_header(36, self.press_right_loop_content.content.as_str());
             self.press_right_loop_content.needs_redraw = false;
         }
-        if self.dbg_header.needs_redraw {
+        if self.link_header.needs_redraw {
             let _ = self.handle.clear_line(48);
             let _ = self
                 .handle
-                .write_header(48, self.dbg_header.content.as_str());
-            self.dbg_header.needs_redraw = false;
+                .write_header(48, self.link_header.content.as_str());
+            self.link_header.needs_redraw = false;
         }
-        if self.dbg_rx.needs_redraw {
+        if self.link_status.needs_redraw {
             let _ = self.handle.clear_line(56);
-            let _ = self.handle.write_header(56, self.dbg_rx.content.as_str());
+            let _ = self
+                .handle
+                .write_header(56, self.link_status.content.as_str());
+            self.link_status.needs_redraw = false;
+        }
+        if self.dbg_rx.needs_redraw {
+            let _ = self.handle.clear_line(64);
+            let _ = self.handle.write_header(64, self.dbg_rx.content.as_str());
             self.dbg_rx.needs_redraw = false;
         }
+        if self.dbg_errors.needs_redraw {
+            let _ = self.handle.clear_line(72);
+            let _ = self
+                .handle
+                .write_header(72, self.dbg_errors.content.as_str());
+            self.dbg_errors.needs_redraw = false;
+        }
         if self.dbg_queue.needs_redraw {
-            let _ = self.handle.clear_line(64);
+            let _ = self.handle.clear_line(80);
             let _ = self
                 .handle
-                .write_header(64, self.dbg_queue.content.as_str());
+                .write_header(80, self.dbg_queue.content.as_str());
             self.dbg_queue.needs_redraw = false;
         }
         if self.clk_header.needs_redraw {
-            let _ = self.handle.clear_line(76);
+            let _ = self.handle.clear_line(92);
             let _ = self
                 .handle
-                .write_header(76, self.clk_header.content.as_str());
+                .write_header(92, self.clk_header.content.as_str());
             self.clk_header.needs_redraw = false;
         }
         if self.clk_freq.needs_redraw {
-            let _ = self.handle.clear_line(84);
-            let _ = self.handle.write_header(84, self.clk_freq.content.as_str());
+            let _ = self.handle.clear_line(100);
+            let _ = self
+                .handle
+                .write_header(100, self.clk_freq.content.as_str());
             self.clk_freq.needs_redraw = false;
         }
         if self.layer_header.needs_redraw {
-            let _ = self.handle.clear_line(96);
+            let _ = self.handle.clear_line(112);
             let _ = self
                 .handle
-                .write_header(96, self.layer_header.content.as_str());
+                .write_header(112, self.layer_header.content.as_str());
             self.layer_header.needs_redraw = false;
         }
         if self.perm_layer.needs_redraw {
-            let _ = self.handle.clear_line(104);
+            let _ = self.handle.clear_line(120);
             let _ = self
                 .handle
-                .write_header(104, self.perm_layer.content.as_str());
+                .write_header(120, self.perm_layer.content.as_str());
             self.perm_layer.needs_redraw = false;
         }
         if self.underscores_need_redraw {
@@ -216,59 +266,20 @@ impl LeftOledDrawer {
             let _ = self.handle.write_underscored_at(8);
             // Perf
             let _ = self.handle.write_underscored_at(44);
-            // Clock
-            let _ = self.handle.write_underscored_at(72);
-            // Dbg
-            let _ = self.handle.write_underscored_at(92);
-            // Layer
-            let _ = self.handle.write_underscored_at(112);
+            // Link
+            let _ = self.handle.write_underscored_at(88);
+            // Clock, layer runs to the bottom of the screen
+            let _ = self.handle.write_underscored_at(108);
             self.underscores_need_redraw = false;
         }
     }
 
     pub fn render_boot_msg(&mut self) {
         self.handle.clear();
-        let _ = self.handle.write_header(0, "LEFT");
+        let _ = self.handle.write_header(0, self.side.name());
         let _ = self.handle.write_header(9, "SIDE");
         let _ = self.handle.write_header(18, "ENTER");
         let _ = self.handle.write_header(27, "USB");
         let _ = self.handle.write_header(36, "BOOT");
     }
 }
-
-pub fn layer_to_string(keymap_layer: crate::keymap::KeymapLayer) -> OledLineString {
-    let mut s = heapless::String::new();
-    match keymap_layer {
-        crate::keymap::KeymapLayer::DvorakSe => {
-            let _ = s.push_str("DV-SE");
-        }
-        crate::keymap::KeymapLayer::DvorakAnsi => {
-            let _ = s.push_str("DV-AN");
-        }
-        crate::keymap::KeymapLayer::DvorakSeMac => {
-            let _ = s.push_str("DV-SM");
-        }
-        crate::keymap::KeymapLayer::QwertyGaming => {
-            let _ = s.push_str("QW-GM");
-        }
-        crate::keymap::KeymapLayer::Lower => {
-            let _ = s.push_str("LO");
-        }
-        crate::keymap::KeymapLayer::LowerSeMac => {
-            let _ = s.push_str("LO-MA");
-        }
-        crate::keymap::KeymapLayer::LowerAnsi => {
-            let _ = s.push_str("LO-AN");
-        }
-        crate::keymap::KeymapLayer::Raise => {
-            let _ = s.push_str("RA");
-        }
-        crate::keymap::KeymapLayer::Num => {
-            let _ = s.push_str("NUM");
-        }
-        crate::keymap::KeymapLayer::Settings => {
-            let _ = s.push_str("SET");
-        }
-    }
-    s
-}
@@ -1,10 +1,14 @@
-use crate::keyboard::oled::{DrawUnit, OledHandle};
+use crate::keyboard::oled::{CompactMicros, DrawUnit, OledHandle, OledLineString};
+use crate::keyboard::Side;
 use crate::static_draw_unit_string;
 use core::fmt::Write;
 use rp2040_hal::fugit::HertzU32;
+use rp2040_kbd_lib::histogram::LatencySummary;
+use rp2040_kbd_lib::split::{CAPS_LOCK, NUM_LOCK, SCROLL_LOCK};
 
-pub struct RightOledDrawer {
+pub struct SlaveOledDrawer {
     handle: OledHandle,
+    side: Side,
     hidden: bool,
     header: DrawUnit,
     scan_loop_header: DrawUnit,
@@ -15,13 +19,17 @@ pub struct RightOledDrawer {
     dbg_queue: DrawUnit,
     clk_header: DrawUnit,
     clk_freq: DrawUnit,
+    layer_header: DrawUnit,
+    layer: DrawUnit,
+    locks: DrawUnit,
     underscores_need_redraw: bool,
 }
 
-impl RightOledDrawer {
-    pub fn new(handle: OledHandle) -> Self {
-        let header_content = static_draw_unit_string!("RIGHT");
-        let scan_loop_header_content = static_draw_unit_string!("PERF");
+impl SlaveOledDrawer {
+    pub fn new(handle: OledHandle, side: Side) -> Self {
+        let mut header_content = OledLineString::new();
+        let _ = header_content.push_str(side.name());
+        let scan_loop_header_content = static_draw_unit_string!("P99");
         let scan_loop_content = static_draw_unit_string!("S ...");
         let press_loop_content = static_draw_unit_string!("P ...");
         let dbg_header = static_draw_unit_string!("DEBUG");
@@ -29,8 +37,12 @@ impl RightOledDrawer {
         let dbg_queue = static_draw_unit_string!("Q ...");
         let clk_header = static_draw_unit_string!("CLOCK");
         let clk_freq = static_draw_unit_string!("...");
+        let layer_header = static_draw_unit_string!("LAYER");
+        let layer = static_draw_unit_string!("...");
+        let locks = static_draw_unit_string!("...");
         Self {
             handle,
+            side,
             hidden: false,
             header: DrawUnit::new(header_content, true),
             scan_loop_header: DrawUnit::new(scan_loop_header_content, true),
@@ -41,6 +53,9 @@ impl RightOledDrawer {
             dbg_queue: DrawUnit::new(dbg_queue, true),
             clk_header: DrawUnit::new(clk_header, true),
             clk_freq: DrawUnit::new(clk_freq, true),
+            layer_header: DrawUnit::new(layer_header, true),
+            layer: DrawUnit::new(layer, true),
+            locks: DrawUnit::new(locks, true),
             underscores_need_redraw: true,
         }
     }
@@ -63,26 +78,29 @@ impl RightOledDrawer {
             self.dbg_queue.needs_redraw = true;
             self.clk_header.needs_redraw = true;
             self.clk_freq.needs_redraw = true;
+            self.layer_header.needs_redraw = true;
+            self.layer.needs_redraw = true;
+            self.locks.needs_redraw = true;
             self.underscores_need_redraw = true;
         }
         self.hidden = false;
     }
 
-    pub fn update_scan_loop(&mut self, avg_scan_latency: f32) {
+    pub fn update_scan_loop(&mut self, scan: LatencySummary) {
         self.scan_loop_content.content.clear();
         let _ = self
             .scan_loop_content
             .content
-            .write_fmt(format_args!("S {avg_scan_latency:.1}"));
+            .write_fmt(format_args!("S {}", CompactMicros(scan.p99)));
         self.scan_loop_content.needs_redraw = true;
     }
 
-    pub fn update_touch(&mut self, transmitted: u16, avg_latency: f32) {
+    pub fn update_touch(&mut self, transmitted: u16, latency: LatencySummary) {
         self.press_loop_content.content.clear();
         let _ = self
             .press_loop_content
             .content
-            .write_fmt(format_args!("P {avg_latency:.1}"));
+            .write_fmt(format_args!("P {}", CompactMicros(latency.p99)));
         self.press_loop_content.needs_redraw = true;
         self.dbg_tx.content.clear();
         let _ = self
@@ -97,6 +115,22 @@ impl RightOledDrawer {
         self.dbg_queue.needs_redraw = true;
     }
 
+    pub fn update_layer(&mut self, layer: OledLineString) {
+        self.layer.content = layer;
+        self.layer.needs_redraw = true;
+    }
+
+    pub fn update_locks(&mut self, leds: u8) {
+        self.locks.content.clear();
+        for (bit, c) in [(CAPS_LOCK, 'C'), (NUM_LOCK, 'N'), (SCROLL_LOCK, 'S')] {
+            let _ = self
+                .locks
+                .content
+                .push(if leds & bit != 0 { c } else { '.' });
+        }
+        self.locks.needs_redraw = true;
+    }
+
     pub fn set_clock(&mut self, freq: HertzU32) {
         self.clk_freq.content.clear();
         let _ = self
@@ -167,6 +201,23 @@ impl RightOledDrawer {
             let _ = self.handle.write_header(76, self.clk_freq.content.as_str());
             self.clk_freq.needs_redraw = false;
         }
+        if self.layer_header.needs_redraw {
+            let _ = self.handle.clear_line(96);
+            let _ = self
+                .handle
+                .write_header(96, self.layer_header.content.as_str());
+            self.layer_header.needs_redraw = false;
+        }
+        if self.layer.needs_redraw {
+            let _ = self.handle.clear_line(104);
+            let _ = self.handle.write_header(104, self.layer.content.as_str());
+            self.layer.needs_redraw = false;
+        }
+        if self.locks.needs_redraw {
+            let _ = self.handle.clear_line(112);
+            let _ = self.handle.write_header(112, self.locks.content.as_str());
+            self.locks.needs_redraw = false;
+        }
         if self.underscores_need_redraw {
             // Header
             let _ = self.handle.write_underscored_at(8);
@@ -176,13 +227,15 @@ impl RightOledDrawer {
             let _ = self.handle.write_underscored_at(64);
             // Clock
             let _ = self.handle.write_underscored_at(84);
+            // Layer
+            let _ = self.handle.write_underscored_at(120);
             self.underscores_need_redraw = false;
         }
     }
 
     pub fn render_boot_msg(&mut self) {
         self.handle.clear();
-        let _ = self.handle.write(0, "RIGHT");
+        let _ = self.handle.write(0, self.side.name());
         let _ = self.handle.write(9, "SIDE");
         let _ = self.handle.write(18, "ENTER");
         let _ = self.handle.write(27, "USB");
@@ -0,0 +1,315 @@
+//! Drives and samples a half's key matrix without the cpu. A state machine on `PIO1` drives one
+//! column at a time and samples every gpio, one dma channel feeds it the columns from a ring and
+//! another writes the samples into a ring of their own. The key core still polls the latest
+//! sample of each column every time around its loop, it's spared the settle delays of driving
+//! the columns itself, not the polling. It only runs the debouncers when a sample changed or a
+//! key is still settling.
+//! The columns belong to the state machine, the rows stay sio inputs, `in pins` reads them anyway.
+use crate::keyboard::scanner::MatrixLayout;
+use crate::keyboard::{tracked, wake, ButtonPin, MatrixSink, Tracked};
+use core::sync::atomic::{AtomicU32, Ordering};
+use liatris::pac::PIO1;
+use rp2040_hal::dma::{Channel, CH0, CH1};
+use rp2040_hal::fugit;
+use rp2040_hal::gpio::{DynPinId, FunctionPio1, Interrupt, Pin, PullUp};
+use rp2040_hal::pac;
+use rp2040_hal::pio::{
+    PIOBuilder, Running, Rx, ShiftDirection, StateMachine, Tx, UninitStateMachine, PIO, SM0,
+};
+use rp2040_hal::Timer;
+use rp2040_kbd_lib::debounce::Debouncer;
+use rp2040_kbd_lib::matrix::{NUM_COLS, NUM_ROWS};
+use rp2040_kbd_lib::split::KeyBitmap;
+
+const NUM_KEYS: usize = NUM_ROWS as usize * NUM_COLS as usize;
+
+/// Both rings wrap on a power of two, columns past the half's last one drive nothing
+const RING_LEN: usize = 8;
+/// `log2` of the ring size in bytes, for the dma's address wrap
+const RING_SIZE_BITS: u8 = 5;
+
+/// One state machine clock is 100ns, a column is driven for 300ns before it's sampled,
+/// then the rows get 3.2 micros to be pulled back up
+const SM_FREQ_HZ: u32 = 10_000_000;
+
+#[repr(C, align(32))]
+struct Ring([AtomicU32; RING_LEN]);
+
+impl Ring {
+    fn address(&self) -> u32 {
+        self.0.as_ptr() as u32
+    }
+}
+
+/// Read by the dma, written once when the scanner starts
+static COLUMN_DRIVES: Ring = Ring([const { AtomicU32::new(0) }; RING_LEN]);
+/// Written by the dma, the bank 0 input levels while the column at the same position was driven
+static SAMPLES: Ring = Ring([const { AtomicU32::new(u32::MAX) }; RING_LEN]);
+
+/// What the pio scanner takes over, only one can exist since it owns `PIO1`
+pub struct PioScanHardware {
+    pub pio: PIO<PIO1>,
+    pub sm: UninitStateMachine<(PIO1, SM0)>,
+    pub tx_dma: Channel<CH0>,
+    pub rx_dma: Channel<CH1>,
+    pub system_freq: fugit::HertzU32,
+}
+
+pub struct PioMatrixScanner<D, const ROWS: usize, const COLS: usize> {
+    row_bits: [u32; ROWS],
+    row_mask: u32,
+    present: u32,
+    rows: [ButtonPin<DynPinId>; ROWS],
+    _cols: [Pin<DynPinId, FunctionPio1, PullUp>; COLS],
+    sm: StateMachine<(PIO1, SM0), Running>,
+    _tx: Tx<(PIO1, SM0)>,
+    _rx: Rx<(PIO1, SM0)>,
+    _tx_dma: Channel<CH0>,
+    _rx_dma: Channel<CH1>,
+    /// Row bits of each column's last sample
+    samples: [u32; COLS],
+    /// Undebounced state of every key, as of the last samples
+    raw: u32,
+    debouncers: [Tracked<D>; NUM_KEYS],
+    pressed: KeyBitmap,
+}
+
+impl<D: Debouncer<u64> + Copy, const ROWS: usize, const COLS: usize>
+    PioMatrixScanner<D, ROWS, COLS>
+{
+    /// The pins have to be the ones in the layout, in the same order
+    pub fn new(
+        layout: &MatrixLayout<ROWS, COLS>,
+        rows: [ButtonPin<DynPinId>; ROWS],
+        cols: [ButtonPin<DynPinId>; COLS],
+        debouncer: D,
+        hardware: PioScanHardware,
+    ) -> Self {
+        const {
+            assert!(ROWS <= NUM_ROWS as usize && COLS <= RING_LEN && COLS <= NUM_COLS as usize);
+        }
+        for (pin, num) in rows
+            .iter()
+            .zip(layout.rows)
+            .chain(cols.iter().zip(layout.cols))
+        {
+            assert_eq!(num, pin.id().num, "matrix pins don't match the layout");
+        }
+        let cols = cols.map(|pin| {
+            let Ok(pin) = pin.try_into_function::<FunctionPio1>() else {
+                unreachable!("every bank 0 pin can be driven by pio");
+            };
+            pin
+        });
+        for (drive, num) in COLUMN_DRIVES.0.iter().zip(layout.cols) {
+            drive.store(1 << num, Ordering::Relaxed);
+        }
+        let PioScanHardware {
+            mut pio,
+            sm,
+            tx_dma,
+            rx_dma,
+            system_freq,
+        } = hardware;
+        // Pin directions are the only thing that changes, every column outputs low
+        // whenever it's driven
+        let program = pio::pio_asm!(
+            "    mov pins, null",
+            ".wrap_target",
+            "    pull block",
+            "    out pindirs, 32 [2]",
+            "    in pins, 32",
+            "    push block",
+            "    mov osr, null",
+            "    out pindirs, 32 [31]",
+            ".wrap",
+        );
+        // Should never fail, nothing else is loaded into PIO1
+        let installed = pio.install(&program.program).ok().unwrap();
+        let (int, frac) = clock_divisor(system_freq);
+        let (sm, rx, tx) = PIOBuilder::from_installed_program(installed)
+            .out_pins(0, 32)
+            .in_pin_base(0)
+            .out_shift_direction(ShiftDirection::Right)
+            .in_shift_direction(ShiftDirection::Left)
+            .clock_divisor_fixed_point(int, frac)
+            .build(sm);
+        start_dma(&rx, &tx);
+        Self {
+            row_bits: layout.rows.map(|num| 1 << num),
+            row_mask: MatrixLayout::<ROWS, COLS>::mask(&layout.rows),
+            present: layout.present(),
+            rows,
+            _cols: cols,
+            sm: sm.start(),
+            _tx: tx,
+            _rx: rx,
+            _tx_dma: tx_dma,
+            _rx_dma: rx_dma,
+            samples: [MatrixLayout::<ROWS, COLS>::mask(&layout.rows); COLS],
+            raw: 0,
+            debouncers: [tracked(debouncer); NUM_KEYS],
+            pressed: KeyBitmap::EMPTY,
+        }
+    }
+
+    /// Hands the keys that changed since the last call to `sink`.
+    /// Returns the number of changes.
+    #[inline]
+    pub fn scan(&mut self, timer: Timer, sink: &mut impl MatrixSink) -> u16 {
+        Self::keep_running();
+        let mut samples = [0; COLS];
+        for (sample, shared) in samples.iter_mut().zip(SAMPLES.0.iter()) {
+            *sample = shared.load(Ordering::Relaxed) & self.row_mask;
+        }
+        // Nothing moved and no key is waiting on its debouncer
+        if samples == self.samples && self.raw == self.pressed.bits() {
+            return 0;
+        }
+        self.samples = samples;
+        let now = timer.get_counter().ticks();
+        #[expect(clippy::cast_possible_truncation)]
+        let detected_at = now as u32;
+        let mut raw = 0;
+        let mut pressed = self.pressed;
+        for (col, sample) in (0u8..).zip(samples) {
+            for (row, row_bit) in (0u8..).zip(self.row_bits) {
+                let index = row * NUM_COLS + col;
+                let bit = 1 << index;
+                if self.present & bit == 0 {
+                    continue;
+                }
+                let key_raw = sample & row_bit == 0;
+                if key_raw {
+                    raw |= bit;
+                }
+                // Debouncers only act on an edge or while the key differs from its state
+                let was_raw = self.raw & bit != 0;
+                if key_raw == was_raw && key_raw == pressed.is_pressed(index) {
+                    continue;
+                }
+                if let Some(state) = self.update_key(index, now, key_raw) {
+                    pressed.set(index, state);
+                }
+            }
+        }
+        self.raw = raw;
+        let mut changes = 0;
+        for update in self.pressed.changes_to(pressed) {
+            sink.matrix_update(update, detected_at);
+            changes += 1;
+        }
+        self.pressed = pressed;
+        changes
+    }
+
+    /// The state machine keeps scanning, the driven column pulls the row of a press low
+    pub fn arm_wake(&mut self) {
+        for row in &mut self.rows {
+            wake::arm(row, Interrupt::EdgeLow);
+        }
+    }
+
+    pub fn disarm_wake(&mut self) {
+        for row in &mut self.rows {
+            wake::disarm(row, Interrupt::EdgeLow);
+        }
+    }
+
+    /// Keeps the state machine at [`SM_FREQ_HZ`], a running one can take a new divisor
+    pub fn clock_changed(&mut self, system_freq: fugit::HertzU32) {
+        let (int, frac) = clock_divisor(system_freq);
+        self.sm.clock_divisor_fixed_point(int, frac);
+    }
+
+    /// The dma stops after `u32::MAX` columns, a few hours of scanning, restart it from where
+    /// it stopped
+    #[inline]
+    fn keep_running() {
+        // Safety: Both channels are ours, re-triggering keeps the addresses
+        unsafe {
+            let dma = &*pac::DMA::ptr();
+            for ch in [dma.ch(1), dma.ch(0)] {
+                if ch.ch_ctrl_trig().read().busy().bit_is_clear() {
+                    ch.ch_al1_trans_count_trig().write(|w| w.bits(u32::MAX));
+                }
+            }
+        }
+    }
+
+    #[inline]
+    fn update_key(&mut self, index: u8, now: u64, raw: bool) -> Option<bool> {
+        let debouncer = &mut self.debouncers[usize::from(index)];
+        #[cfg(feature = "serial")]
+        let edge = debouncer.raw() != raw;
+        let change = debouncer.update(now, raw);
+        #[cfg(feature = "serial")]
+        if edge {
+            crate::runtime::shared::chatter::publish(index, debouncer.stats());
+        }
+        change
+    }
+}
+
+/// Starts feeding the state machine columns and collecting its samples, for as long as
+/// the dma's transfer count lasts
+fn start_dma(rx: &Rx<(PIO1, SM0)>, tx: &Tx<(PIO1, SM0)>) {
+    // Safety: Both channels are ours, the rings are static and only this scanner exists.
+    // A channel chaining to itself doesn't chain.
+    unsafe {
+        let dma = &*pac::DMA::ptr();
+        let rx_ch = dma.ch(1);
+        rx_ch
+            .ch_read_addr()
+            .write(|w| w.bits(rx.fifo_address() as u32));
+        rx_ch.ch_write_addr().write(|w| w.bits(SAMPLES.address()));
+        rx_ch.ch_trans_count().write(|w| w.bits(u32::MAX));
+        rx_ch.ch_ctrl_trig().write(|w| {
+            w.data_size()
+                .size_word()
+                .incr_write()
+                .set_bit()
+                .ring_sel()
+                .set_bit()
+                .ring_size()
+                .bits(RING_SIZE_BITS)
+                .treq_sel()
+                .bits(rx.dreq_value())
+                .chain_to()
+                .bits(1)
+                .en()
+                .set_bit()
+        });
+        let tx_ch = dma.ch(0);
+        tx_ch
+            .ch_read_addr()
+            .write(|w| w.bits(COLUMN_DRIVES.address()));
+        tx_ch
+            .ch_write_addr()
+            .write(|w| w.bits(tx.fifo_address() as u32));
+        tx_ch.ch_trans_count().write(|w| w.bits(u32::MAX));
+        tx_ch.ch_ctrl_trig().write(|w| {
+            w.data_size()
+                .size_word()
+                .incr_read()
+                .set_bit()
+                .ring_size()
+                .bits(RING_SIZE_BITS)
+                .treq_sel()
+                .bits(tx.dreq_value())
+                .chain_to()
+                .bits(0)
+                .en()
+                .set_bit()
+        });
+    }
+}
+
+/// [`SM_FREQ_HZ`] as a 16.8 fixed point divisor of the system clock
+fn clock_divisor(system_freq: fugit::HertzU32) -> (u16, u8) {
+    let div = u64::from(system_freq.to_Hz()) * 256 / u64::from(SM_FREQ_HZ);
+    let int = u16::try_from(div >> 8).unwrap_or(u16::MAX);
+    #[expect(clippy::cast_possible_truncation)]
+    let frac = div as u8;
+    (int, frac)
+}
@@ -0,0 +1,203 @@
+//! Scans a half's key matrix. Columns rest as pulled up inputs with their output low, a column
+//! is driven by enabling its output through the SIO set and clear registers, and all rows are
+//! read at once with `read_bank0`.
+//! With `pio-scan` only the layout is used from here, see [`super::pio_scanner`].
+#[cfg(not(feature = "pio-scan"))]
+use crate::keyboard::{tracked, wake, ButtonPin, MatrixSink, Tracked};
+#[cfg(not(feature = "pio-scan"))]
+use rp2040_hal::fugit::HertzU32;
+#[cfg(not(feature = "pio-scan"))]
+use rp2040_hal::gpio::{DynPinId, Interrupt};
+#[cfg(not(feature = "pio-scan"))]
+use rp2040_hal::pac;
+#[cfg(not(feature = "pio-scan"))]
+use rp2040_hal::{Sio, Timer};
+#[cfg(not(feature = "pio-scan"))]
+use rp2040_kbd_lib::debounce::Debouncer;
+use rp2040_kbd_lib::matrix::NUM_COLS;
+#[cfg(not(feature = "pio-scan"))]
+use rp2040_kbd_lib::matrix::NUM_ROWS;
+#[cfg(not(feature = "pio-scan"))]
+use rp2040_kbd_lib::split::KeyBitmap;
+
+#[cfg(not(feature = "pio-scan"))]
+const NUM_KEYS: usize = NUM_ROWS as usize * NUM_COLS as usize;
+
+/// Which gpio is which row and column on a half, and which positions have no switch
+pub struct MatrixLayout<const ROWS: usize, const COLS: usize> {
+    /// Read, pulled up, low when a key on the driven column is pressed
+    pub rows: [u8; ROWS],
+    /// Driven low one at a time
+    pub cols: [u8; COLS],
+    /// `(row, col)` positions without a switch, never reported
+    pub missing: &'static [(u8, u8)],
+}
+
+impl<const ROWS: usize, const COLS: usize> MatrixLayout<ROWS, COLS> {
+    pub(crate) const fn mask(pins: &[u8]) -> u32 {
+        let mut mask = 0;
+        let mut i = 0;
+        while i < pins.len() {
+            mask |= 1 << pins[i];
+            i += 1;
+        }
+        mask
+    }
+
+    /// Bits of the matrix indices that have a switch
+    pub(crate) const fn present(&self) -> u32 {
+        let mut present = 0;
+        let mut row = 0;
+        while row < ROWS {
+            let mut col = 0;
+            while col < COLS {
+                present |= 1 << (row * NUM_COLS as usize + col);
+                col += 1;
+            }
+            row += 1;
+        }
+        let mut i = 0;
+        while i < self.missing.len() {
+            let (row, col) = self.missing[i];
+            present &= !(1 << (row * NUM_COLS + col));
+            i += 1;
+        }
+        present
+    }
+}
+
+#[cfg(not(feature = "pio-scan"))]
+pub struct MatrixScanner<D, const ROWS: usize, const COLS: usize> {
+    row_bits: [u32; ROWS],
+    col_bits: [u32; COLS],
+    row_mask: u32,
+    col_mask: u32,
+    present: u32,
+    // Configured by the hal, held so that nothing else can use them
+    rows: [ButtonPin<DynPinId>; ROWS],
+    _cols: [ButtonPin<DynPinId>; COLS],
+    debouncers: [Tracked<D>; NUM_KEYS],
+    pressed: KeyBitmap,
+}
+
+#[cfg(not(feature = "pio-scan"))]
+impl<D: Debouncer<u64> + Copy, const ROWS: usize, const COLS: usize> MatrixScanner<D, ROWS, COLS> {
+    /// The pins have to be the ones in the layout, in the same order
+    pub fn new(
+        layout: &MatrixLayout<ROWS, COLS>,
+        rows: [ButtonPin<DynPinId>; ROWS],
+        cols: [ButtonPin<DynPinId>; COLS],
+        debouncer: D,
+    ) -> Self {
+        const {
+            assert!(ROWS <= NUM_ROWS as usize && COLS <= NUM_COLS as usize);
+        }
+        for (pin, num) in rows
+            .iter()
+            .zip(layout.rows)
+            .chain(cols.iter().zip(layout.cols))
+        {
+            assert_eq!(num, pin.id().num, "matrix pins don't match the layout");
+        }
+        let col_mask = MatrixLayout::<ROWS, COLS>::mask(&layout.cols);
+        // Safety: Set and clear registers only touch the bits written, these pins are ours
+        unsafe {
+            (*pac::SIO::PTR).gpio_out_clr().write(|w| w.bits(col_mask));
+        }
+        Self {
+            row_bits: layout.rows.map(|num| 1 << num),
+            col_bits: layout.cols.map(|num| 1 << num),
+            row_mask: MatrixLayout::<ROWS, COLS>::mask(&layout.rows),
+            col_mask,
+            present: layout.present(),
+            rows,
+            _cols: cols,
+            debouncers: [tracked(debouncer); NUM_KEYS],
+            pressed: KeyBitmap::EMPTY,
+        }
+    }
+
+    /// Scans every column, then hands the keys that changed to `sink`.
+    /// Returns the number of changes.
+    #[inline]
+    pub fn scan(&mut self, timer: Timer, sink: &mut impl MatrixSink) -> u16 {
+        let detected_at = timer.get_counter_low();
+        let mut pressed = self.pressed;
+        for (col, col_bit) in (0u8..).zip(self.col_bits) {
+            // Safety: Set and clear registers only touch the bits written, this column is ours
+            unsafe {
+                (*pac::SIO::PTR).gpio_oe_set().write(|w| w.bits(col_bit));
+            }
+            // Just pulling chibios defaults of 0.25 micros, could probably be 0
+            crate::timer::wait_nanos(timer, 250);
+            let bank = Sio::read_bank0();
+            // Can immediately release the column, pins settle while we go through the rows
+            unsafe {
+                (*pac::SIO::PTR).gpio_oe_clr().write(|w| w.bits(col_bit));
+            }
+            let now = timer.get_counter().ticks();
+            for (row, row_bit) in (0u8..).zip(self.row_bits) {
+                let index = row * NUM_COLS + col;
+                if self.present & (1 << index) == 0 {
+                    continue;
+                }
+                let raw = bank & row_bit == 0;
+                if let Some(state) = self.update_key(index, now, raw) {
+                    pressed.set(index, state);
+                }
+            }
+            // Wait for pins to settle
+            while Sio::read_bank0() & self.row_mask != self.row_mask {}
+        }
+        let mut changes = 0;
+        for update in self.pressed.changes_to(pressed) {
+            sink.matrix_update(update, detected_at);
+            changes += 1;
+        }
+        self.pressed = pressed;
+        changes
+    }
+
+    /// Drives every column, a press pulls its row low and wakes the key core
+    pub fn arm_wake(&mut self) {
+        // Safety: Set and clear registers only touch the bits written, these pins are ours
+        unsafe {
+            (*pac::SIO::PTR)
+                .gpio_oe_set()
+                .write(|w| w.bits(self.col_mask));
+        }
+        for row in &mut self.rows {
+            wake::arm(row, Interrupt::EdgeLow);
+        }
+    }
+
+    pub fn disarm_wake(&mut self) {
+        for row in &mut self.rows {
+            wake::disarm(row, Interrupt::EdgeLow);
+        }
+        unsafe {
+            (*pac::SIO::PTR)
+                .gpio_oe_clr()
+                .write(|w| w.bits(self.col_mask));
+        }
+        // With no column driven every row is pulled back up
+        while Sio::read_bank0() & self.row_mask != self.row_mask {}
+    }
+
+    /// Every wait is timed off the timer, which doesn't run off the system clock
+    #[expect(clippy::unused_self)]
+    pub fn clock_changed(&mut self, _system_freq: HertzU32) {}
+
+    #[inline]
+    fn update_key(&mut self, index: u8, now: u64, raw: bool) -> Option<bool> {
+        let debouncer = &mut self.debouncers[usize::from(index)];
+        #[cfg(feature = "serial")]
+        let edge = debouncer.raw() != raw;
+        let change = debouncer.update(now, raw);
+        #[cfg(feature = "serial")]
+        if edge {
+            crate::runtime::shared::chatter::publish(index, debouncer.stats());
+        }
+        change
+    }
+}
@@ -1,69 +1,259 @@
+pub(crate) mod message_receiver;
+pub(crate) mod message_serializer;
+
+use core::sync::atomic::{AtomicU8, Ordering};
 use liatris::pac::{PIO0, RESETS};
+use rp2040_hal::dma::{Channel, CH2};
 use rp2040_hal::fugit;
 use rp2040_hal::gpio::bank0::Gpio1;
-use rp2040_hal::gpio::{FunctionNull, Pin, PullDown};
-use rp2040_hal::pio::{PIOExt, Running, UninitStateMachine, SM0, SM1, SM2, SM3};
-
-#[cfg(feature = "left")]
-pub struct UartLeft {
-    pub(crate) inner: pio_uart::PioUartRx<Gpio1, PIO0, SM0, Running>,
-    _prog: pio_uart::RxProgram<PIO0>,
-    _sm1: UninitStateMachine<(PIO0, SM1)>,
+use rp2040_hal::gpio::{
+    FunctionNull, FunctionPio0, InputOverride, Interrupt, Pin, PullDown, PullUp,
+};
+use rp2040_hal::pac;
+use rp2040_hal::pio::{
+    Buffers, PIOBuilder, PIOExt, PinDir, PinState, Running, Rx, ShiftDirection, StateMachine, Tx,
+    UninitStateMachine, SM0, SM1, SM2, SM3,
+};
+use rp2040_kbd_lib::matrix::{MAX_FRAME_LEN, NUM_COLS, NUM_ROWS};
+
+const LINK_PIN: u8 = 1;
+
+/// The worst the link is built for, the master not reading for a whole 10ms park while the
+/// slave sends a snapshot, a poll, and a press and a release of every one of its keys
+const WORST_BURST: usize = MAX_FRAME_LEN * (2 + 2 * NUM_ROWS as usize * NUM_COLS as usize);
+
+/// Received bytes, the dma wraps on a power of two
+const RX_RING_LEN: usize = 512;
+/// `log2` of [`RX_RING_LEN`], for the dma's address wrap
+const RX_RING_SIZE_BITS: u8 = 9;
+const _: () = assert!(1 << RX_RING_SIZE_BITS == RX_RING_LEN && WORST_BURST <= RX_RING_LEN);
+
+/// The dma channel that drains the receiving state machine
+const RX_DMA_CH: u8 = 2;
+
+#[repr(C, align(512))]
+struct RxRing([AtomicU8; RX_RING_LEN]);
+
+impl RxRing {
+    fn address(&self) -> u32 {
+        self.0.as_ptr() as u32
+    }
+}
+
+/// Written by the dma, only read by whichever core owns the [`SplitLink`]
+static RX_RING: RxRing = RxRing([const { AtomicU8::new(0) }; RX_RING_LEN]);
+
+/// Half-duplex uart over the single wire between the halves, one state machine receives,
+/// one transmits. Both halves pull the line up, the transmitter only drives it while
+/// sending a byte, so whoever has the turn can send.
+/// A dma channel moves every received byte into [`RX_RING`] as soon as it's pushed, the
+/// receiving state machine never stalls on a full fifo however long the key core takes.
+pub struct SplitLink {
+    pin: Pin<Gpio1, FunctionPio0, PullUp>,
+    _rx: Rx<(PIO0, SM0)>,
+    _rx_dma: Channel<CH2>,
+    /// Position in [`RX_RING`] of the next byte to read
+    read: usize,
+    tx: Tx<(PIO0, SM1)>,
+    _rx_sm: StateMachine<(PIO0, SM0), Running>,
+    _tx_sm: StateMachine<(PIO0, SM1), Running>,
     _sm2: UninitStateMachine<(PIO0, SM2)>,
     _sm3: UninitStateMachine<(PIO0, SM3)>,
 }
 
-#[cfg(feature = "left")]
-impl UartLeft {
+impl SplitLink {
     pub fn new(
         uart_pin: Pin<Gpio1, FunctionNull, PullDown>,
         baud: fugit::HertzU32,
         system_freq: fugit::HertzU32,
         pio: PIO0,
+        rx_dma: Channel<CH2>,
         resets: &mut RESETS,
     ) -> Self {
-        let rx_pin = uart_pin.reconfigure();
+        let pin = uart_pin.reconfigure();
         let (mut pio, sm0, sm1, sm2, sm3) = pio.split(resets);
-        let mut rx_program = pio_uart::install_rx_program(&mut pio).ok().unwrap(); // Should never fail, because no program was loaded yet
-        let rx = pio_uart::PioUartRx::new(rx_pin, sm0, &mut rx_program, baud, system_freq).enable();
+        // 8 clocks per bit, wait for the start bit, then sample in the middle of each bit
+        let rx_program = pio::pio_asm!(
+            "start:",
+            "    wait 0 pin 0",
+            "    set x, 7 [10]",
+            "bitloop:",
+            "    in pins, 1",
+            "    jmp x-- bitloop [6]",
+            "    jmp pin good_stop",
+            // Framing error, wait for the line to go idle again
+            "    wait 1 pin 0",
+            "    jmp start",
+            "good_stop:",
+            "    push",
+        );
+        // Side-set drives the pin direction, the line is only driven between the start bit
+        // and the end of the stop bit
+        let tx_program = pio::pio_asm!(
+            ".side_set 1 opt pindirs",
+            ".wrap_target",
+            "    pull block side 0",
+            "    set x, 7",
+            "    set pins, 0 side 1 [7]",
+            "bitloop:",
+            "    out pins, 1",
+            "    jmp x-- bitloop [6]",
+            "    set pins, 1 [7]",
+            ".wrap",
+        );
+        // Should never fail, because no program was loaded yet
+        let rx_installed = pio.install(&rx_program.program).ok().unwrap();
+        let tx_installed = pio.install(&tx_program.program).ok().unwrap();
+        let (int, frac) = clock_divisor(baud, system_freq);
+        let (mut rx_sm, rx, _) = PIOBuilder::from_installed_program(rx_installed)
+            .in_pin_base(LINK_PIN)
+            .jmp_pin(LINK_PIN)
+            .in_shift_direction(ShiftDirection::Right)
+            .buffers(Buffers::OnlyRx)
+            .clock_divisor_fixed_point(int, frac)
+            .build(sm0);
+        rx_sm.set_pindirs([(LINK_PIN, PinDir::Input)]);
+        start_rx_dma(&rx);
+        let (mut tx_sm, _, tx) = PIOBuilder::from_installed_program(tx_installed)
+            .out_pins(LINK_PIN, 1)
+            .set_pins(LINK_PIN, 1)
+            .side_set_pin_base(LINK_PIN)
+            .out_shift_direction(ShiftDirection::Right)
+            .clock_divisor_fixed_point(int, frac)
+            .build(sm1);
+        tx_sm.set_pins([(LINK_PIN, PinState::High)]);
+        tx_sm.set_pindirs([(LINK_PIN, PinDir::Input)]);
         Self {
-            inner: rx,
-            _prog: rx_program,
-            _sm1: sm1,
+            pin,
+            _rx: rx,
+            _rx_dma: rx_dma,
+            read: ring_written(),
+            tx,
+            _rx_sm: rx_sm.start(),
+            _tx_sm: tx_sm.start(),
             _sm2: sm2,
             _sm3: sm3,
         }
     }
+
+    /// Bytes more than [`RX_RING_LEN`] behind are overwritten, the decoder sees a bad frame
+    #[inline]
+    pub fn read_one(&mut self) -> Option<u8> {
+        if self.read == ring_written() {
+            keep_rx_dma_running();
+            return None;
+        }
+        let byte = RX_RING.0[self.read].load(Ordering::Relaxed);
+        self.read = (self.read + 1) % RX_RING_LEN;
+        Some(byte)
+    }
+
+    /// Bytes the dma moved in that [`Self::read_one`] hasn't returned yet
+    #[inline]
+    pub fn has_unread(&self) -> bool {
+        self.read != ring_written()
+    }
+
+    /// Wakes the parked key core on the start bit of the next byte, see [`crate::keyboard::wake`].
+    /// An edge from before isn't cleared, it may belong to a byte that isn't in the ring yet.
+    pub fn arm_wake(&mut self) {
+        self.pin.set_interrupt_enabled(Interrupt::EdgeLow, true);
+    }
+
+    pub fn disarm_wake(&mut self) {
+        self.pin.set_interrupt_enabled(Interrupt::EdgeLow, false);
+        self.pin.clear_interrupt(Interrupt::EdgeLow);
+    }
+
+    /// Blocks until the last stop bit is out and the line is released again.
+    pub fn write_all(&mut self, bytes: &[u8]) {
+        // The receiver would pick up everything we send, have it see an idle line instead
+        self.pin.set_input_override(InputOverride::AlwaysHigh);
+        for byte in bytes {
+            while !self.tx.write(u32::from(*byte)) {}
+        }
+        while !self.tx.is_empty() {}
+        // The last byte is pulled, it's done when the machine stalls waiting for the next one
+        self.tx.clear_stalled_flag();
+        while !self.tx.has_stalled() {}
+        self.pin.set_input_override(InputOverride::Normal);
+    }
 }
 
-#[cfg(feature = "right")]
-pub struct UartRight {
-    pub(crate) inner: pio_uart::PioUartTx<Gpio1, PIO0, SM0, Running>,
-    _prog: pio_uart::TxProgram<PIO0>,
-    _sm1: UninitStateMachine<(PIO0, SM1)>,
-    _sm2: UninitStateMachine<(PIO0, SM2)>,
-    _sm3: UninitStateMachine<(PIO0, SM3)>,
+/// Starts moving received bytes into [`RX_RING`], for as long as the dma's transfer count lasts
+fn start_rx_dma(rx: &Rx<(PIO0, SM0)>) {
+    // Safety: The channel is ours, the ring is static and only one link exists.
+    // A channel chaining to itself doesn't chain.
+    unsafe {
+        let ch = (*pac::DMA::ptr()).ch(usize::from(RX_DMA_CH));
+        // Shifted in from the left, the byte ends up in the top of the word
+        ch.ch_read_addr()
+            .write(|w| w.bits(rx.fifo_address() as u32 + 3));
+        ch.ch_write_addr().write(|w| w.bits(RX_RING.address()));
+        ch.ch_trans_count().write(|w| w.bits(u32::MAX));
+        ch.ch_ctrl_trig().write(|w| {
+            w.data_size()
+                .size_byte()
+                .incr_write()
+                .set_bit()
+                .ring_sel()
+                .set_bit()
+                .ring_size()
+                .bits(RX_RING_SIZE_BITS)
+                .treq_sel()
+                .bits(rx.dreq_value())
+                .chain_to()
+                .bits(RX_DMA_CH)
+                .high_priority()
+                .set_bit()
+                .en()
+                .set_bit()
+        });
+    }
 }
 
-#[cfg(feature = "right")]
-impl UartRight {
-    pub fn new(
-        uart_pin: Pin<Gpio1, FunctionNull, PullDown>,
-        baud: fugit::HertzU32,
-        system_freq: fugit::HertzU32,
-        pio: PIO0,
-        resets: &mut RESETS,
-    ) -> Self {
-        let rx_pin = uart_pin.reconfigure();
-        let (mut inner_pio, sm0, sm1, sm2, sm3) = pio.split(resets);
-        let mut tx_program = pio_uart::install_tx_program(&mut inner_pio).ok().unwrap(); // Should never fail, because no program was loaded yet
-        let rx = pio_uart::PioUartTx::new(rx_pin, sm0, &mut tx_program, baud, system_freq).enable();
-        Self {
-            inner: rx,
-            _prog: tx_program,
-            _sm1: sm1,
-            _sm2: sm2,
-            _sm3: sm3,
+/// Position in [`RX_RING`] the dma writes next, the write address updates once a write is done
+#[inline]
+fn ring_written() -> usize {
+    // Safety: Read only
+    let addr = unsafe {
+        (*pac::DMA::ptr())
+            .ch(usize::from(RX_DMA_CH))
+            .ch_write_addr()
+            .read()
+            .bits()
+    };
+    addr.wrapping_sub(RX_RING.address()) as usize % RX_RING_LEN
+}
+
+/// The dma stops after `u32::MAX` bytes, restart it from where it stopped
+#[inline]
+fn keep_rx_dma_running() {
+    // Safety: The channel is ours, re-triggering keeps the addresses
+    unsafe {
+        let ch = (*pac::DMA::ptr()).ch(usize::from(RX_DMA_CH));
+        if ch.ch_ctrl_trig().read().busy().bit_is_clear() {
+            ch.ch_al1_trans_count_trig().write(|w| w.bits(u32::MAX));
         }
     }
 }
+
+/// 8 state machine clocks per bit, as 16.8 fixed point
+fn clock_divisor(baud: fugit::HertzU32, system_freq: fugit::HertzU32) -> (u16, u8) {
+    let div = u64::from(system_freq.to_Hz()) * 256 / (u64::from(baud.to_Hz()) * 8);
+    let int = u16::try_from(div >> 8).unwrap_or(u16::MAX);
+    #[expect(clippy::cast_possible_truncation)]
+    let frac = div as u8;
+    (int, frac)
+}
+
+/// Frames that went corrupt between two reads of the decoder's stats
+#[inline]
+fn trace_rejected(corrupt_before: u32, corrupt_after: u32) {
+    let rejected = corrupt_after.wrapping_sub(corrupt_before);
+    if rejected > 0 {
+        crate::runtime::shared::trace::record(rp2040_kbd_lib::trace::TraceEvent::LinkRejected(
+            u8::try_from(rejected).unwrap_or(u8::MAX),
+        ));
+    }
+}
@@ -0,0 +1,74 @@
+use crate::keyboard::split_serial::{trace_rejected, SplitLink};
+use crate::runtime::shared::trace;
+use rp2040_kbd_lib::matrix::{FrameDecoder, FrameEncoder, FramePayload, FrameStats};
+use rp2040_kbd_lib::split::{MasterToSlave, SlaveToMaster};
+use rp2040_kbd_lib::trace::TraceEvent;
+
+pub(crate) struct MessageReceiver {
+    link: SplitLink,
+    decoder: FrameDecoder<SlaveToMaster>,
+    encoder: FrameEncoder<MasterToSlave>,
+}
+
+impl MessageReceiver {
+    pub fn new(link: SplitLink) -> Self {
+        Self {
+            link,
+            decoder: FrameDecoder::new(),
+            encoder: FrameEncoder::new(),
+        }
+    }
+
+    #[inline]
+    pub(crate) fn try_read(&mut self) -> Option<SlaveToMaster> {
+        let corrupt = self.decoder.stats().corrupt;
+        let mut msg = None;
+        while let Some(byte) = self.link.read_one() {
+            msg = self.decoder.push(byte);
+            if msg.is_some() {
+                break;
+            }
+        }
+        trace_rejected(corrupt, self.decoder.stats().corrupt);
+        // Polls are heartbeats, they'd push everything else out of the trace
+        if let Some(msg) = msg.filter(|msg| !matches!(msg, SlaveToMaster::Poll)) {
+            trace::record(TraceEvent::LinkReceived(msg.to_byte()));
+        }
+        msg
+    }
+
+    /// A frame has started coming in, or several are waiting
+    #[inline]
+    pub(crate) fn has_unread(&self) -> bool {
+        self.link.has_unread()
+    }
+
+    /// Also wake a parked key core when the slave starts sending
+    #[inline]
+    pub(crate) fn arm_wake(&mut self) {
+        self.link.arm_wake();
+    }
+
+    #[inline]
+    pub(crate) fn disarm_wake(&mut self) {
+        self.link.disarm_wake();
+    }
+
+    /// Answer a poll, the slave is listening for exactly one frame
+    #[inline]
+    pub(crate) fn reply(&mut self, msg: MasterToSlave) {
+        let frame = self.encoder.encode(&msg);
+        self.link.write_all(&frame);
+    }
+
+    /// The slave may reboot while the link is down, its sequence numbers start over
+    #[inline]
+    pub(crate) fn resync(&mut self) {
+        self.decoder.resync();
+    }
+
+    #[inline]
+    pub(crate) fn stats(&self) -> FrameStats {
+        self.decoder.stats()
+    }
+}
@@ -0,0 +1,85 @@
+use crate::keyboard::split_serial::{trace_rejected, SplitLink};
+use rp2040_hal::Timer;
+use rp2040_kbd_lib::matrix::{FrameDecoder, FrameEncoder, MatrixChange, MatrixUpdate};
+use rp2040_kbd_lib::split::{KeyBitmap, MasterToSlave, QuietWindow, SlaveToMaster};
+
+/// How long to wait for the master to answer a poll, a frame takes a few microseconds
+/// on the wire, the rest is the master key core finishing its loop.
+pub(crate) const POLL_TIMEOUT_MICROS: u64 = 1_000;
+
+pub(crate) struct MessageSerializer {
+    link: SplitLink,
+    encoder: FrameEncoder<SlaveToMaster>,
+    decoder: FrameDecoder<MasterToSlave>,
+    // Everything that was sent as pressed, for snapshots
+    pressed: KeyBitmap,
+    timer: Timer,
+    quiet: QuietWindow,
+}
+
+impl MessageSerializer {
+    /// `age_micros` is how long ago the change was detected, saturates at 255
+    #[inline]
+    pub(crate) fn serialize_matrix_state(&mut self, update: MatrixUpdate, age_micros: u32) {
+        if let MatrixChange::KeyUpdate(ind, pressed) = update.interpret_byte() {
+            self.pressed.set(ind.byte(), pressed);
+        }
+        let age = u8::try_from(age_micros).unwrap_or(u8::MAX);
+        let frame = self.encoder.encode(&SlaveToMaster::Matrix(update, age));
+        self.write_frame(&frame);
+    }
+
+    /// Lets the master catch up on anything it missed
+    #[inline]
+    pub(crate) fn send_snapshot(&mut self) {
+        let frame = self.encoder.encode(&SlaveToMaster::Snapshot(self.pressed));
+        self.write_frame(&frame);
+    }
+
+    /// Hand the line to the master and wait for its answer
+    pub(crate) fn poll_master(&mut self) -> Option<MasterToSlave> {
+        let frame = self.encoder.encode(&SlaveToMaster::Poll);
+        self.write_frame(&frame);
+        let timer = self.timer;
+        let start = timer.get_counter();
+        let corrupt = self.decoder.stats().corrupt;
+        let reply = 'wait: loop {
+            while let Some(byte) = self.link.read_one() {
+                if let Some(msg) = self.decoder.push(byte) {
+                    break 'wait Some(msg);
+                }
+            }
+            if timer
+                .get_counter()
+                .checked_duration_since(start)
+                .is_some_and(|dur| dur.to_micros() > POLL_TIMEOUT_MICROS)
+            {
+                break None;
+            }
+        };
+        trace_rejected(corrupt, self.decoder.stats().corrupt);
+        reply
+    }
+
+    /// The master switches clocks as of now, nothing goes out until it's done
+    #[inline]
+    pub(crate) fn hold_off(&mut self) {
+        self.quiet.start(self.timer.get_counter().ticks());
+    }
+
+    fn write_frame(&mut self, frame: &[u8]) {
+        while self.quiet.is_quiet(self.timer.get_counter().ticks()) {}
+        self.link.write_all(frame);
+    }
+
+    pub fn new(link: SplitLink, timer: Timer) -> Self {
+        Self {
+            link,
+            encoder: FrameEncoder::new(),
+            decoder: FrameDecoder::new(),
+            pressed: KeyBitmap::EMPTY,
+            timer,
+            quiet: QuietWindow::new(),
+        }
+    }
+}
@@ -2,7 +2,9 @@ use core::borrow::BorrowMut;
 use core::fmt::Write;
 use rp2040_hal::usb::UsbBus;
 use usb_device::bus::UsbBusAllocator;
-use usb_device::device::{StringDescriptors, UsbDevice, UsbDeviceBuilder, UsbVidPid};
+use usb_device::device::{
+    StringDescriptors, UsbDevice, UsbDeviceBuilder, UsbDeviceState, UsbVidPid,
+};
 use usb_device::UsbError;
 use usbd_serial::SerialPort;
 
@@ -66,4 +68,9 @@ impl<'a> UsbSerialDevice<'a> {
             .unwrap();
         Self { inner }
     }
+
+    #[inline]
+    pub fn is_suspended(&self) -> bool {
+        self.inner.state() == UsbDeviceState::Suspend
+    }
 }
@@ -0,0 +1,64 @@
+//! Wakes the parked key core on the first key or encoder movement while idle, and the master's
+//! on the slave starting to send. The pins get an edge interrupt on the key core, the handler disables every gpio interrupt
+//! on that core again and notes when it fired.
+use crate::keyboard::ButtonPin;
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use liatris::pac::interrupt;
+use rp2040_hal::gpio::{DynPinId, Interrupt};
+use rp2040_hal::pac;
+
+/// Set by the handler, cleared before parking or when taken
+static WOKEN: AtomicBool = AtomicBool::new(false);
+/// Low half of the timer when the handler ran
+static WOKE_AT: AtomicU32 = AtomicU32::new(0);
+
+/// Forgets an earlier wake, before arming for the next one
+pub fn reset() {
+    WOKEN.store(false, Ordering::Relaxed);
+}
+
+/// Has to be called from the key core, the interrupt goes to the calling core
+pub fn arm(pin: &mut ButtonPin<DynPinId>, interrupt: Interrupt) {
+    pin.clear_interrupt(interrupt);
+    pin.set_interrupt_enabled(interrupt, true);
+}
+
+pub fn disarm(pin: &mut ButtonPin<DynPinId>, interrupt: Interrupt) {
+    pin.set_interrupt_enabled(interrupt, false);
+    pin.clear_interrupt(interrupt);
+}
+
+#[inline]
+pub fn woken() -> bool {
+    WOKEN.load(Ordering::Relaxed)
+}
+
+/// The low half of the timer's micros when a pin woke the key core, once per wake
+#[inline]
+pub fn take_woke_at() -> Option<u32> {
+    if !WOKEN.load(Ordering::Relaxed) {
+        return None;
+    }
+    WOKEN.store(false, Ordering::Relaxed);
+    Some(WOKE_AT.load(Ordering::Relaxed))
+}
+
+/// Todo: Change to 'expect' after [this PR](https://github.com/rust-embedded/cortex-m/pull/557)
+/// Only unmasked on the key core, which is `proc1`
+#[interrupt]
+#[allow(clippy::allow_attributes, non_snake_case)]
+fn IO_IRQ_BANK0() {
+    // Safety: Only the key core enables gpio interrupts, through `arm` or the split link's.
+    // Edge interrupts stay raised until cleared, `disarm` clears them.
+    unsafe {
+        let io = &*pac::IO_BANK0::ptr();
+        for n in 0..4 {
+            io.proc1_inte(n).write(|w| w.bits(0));
+        }
+        WOKE_AT.store(
+            (*pac::TIMER::ptr()).timerawl().read().bits(),
+            Ordering::Relaxed,
+        );
+    }
+    WOKEN.store(true, Ordering::Relaxed);
+}
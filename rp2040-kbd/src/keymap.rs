@@ -1,60 +1,32 @@
+mod encoder;
 mod mapping;
 
-#[cfg(feature = "serial")]
-use core::fmt::Write;
 use core::hint::unreachable_unchecked;
 use core::ptr;
 use paste::paste;
-use rp2040_hal::gpio::PinState;
-use rp2040_hal::Timer;
-use rp2040_kbd_lib::queue::Queue;
-use usbd_hid::descriptor::KeyboardReport;
-
-use crate::keyboard::debounce::PinDebouncer;
-use crate::keyboard::left::LeftButtons;
-use crate::runtime::shared::cores_left::{push_layer_change, Producer};
-use rp2040_kbd_lib::keycodes::{KeyCode, Modifier};
-use rp2040_kbd_lib::matrix::{MatrixChange, MatrixUpdate};
-
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
-pub enum KeymapLayer {
-    DvorakSe,
-    DvorakAnsi,
-    DvorakSeMac,
-    QwertyGaming,
-    Lower,
-    LowerSeMac,
-    LowerAnsi,
-    Raise,
-    Num,
-    Settings,
-}
-
-const EMPTY_REPORT: KeyboardReport = KeyboardReport {
-    modifier: 0,
-    reserved: 0,
-    leds: 0,
-    keycodes: [0u8; 6],
-};
+use rp2040_kbd_lib::report::{Report, ReportQueue};
 
-fn copy_report(keyboard_report: &KeyboardReport) -> KeyboardReport {
-    KeyboardReport {
-        modifier: keyboard_report.modifier,
-        reserved: keyboard_report.reserved,
-        leds: keyboard_report.leds,
-        keycodes: keyboard_report.keycodes,
-    }
-}
+use crate::board;
+use crate::keyboard::Side;
+use crate::layer::KeymapLayer;
+use crate::runtime::shared::cores_master::Producer;
+use crate::runtime::shared::trace;
+use rp2040_kbd_lib::keycodes::{KeyCode, Modifier};
+use rp2040_kbd_lib::matrix::{MatrixChange, MatrixUpdate, NUM_COLS, NUM_ROWS};
+use rp2040_kbd_lib::split::KeyBitmap;
+use rp2040_kbd_lib::trace::TraceEvent;
 
 pub struct KeyboardReportState {
     generation: usize,
-    inner_report: KeyboardReport,
+    inner_report: Report,
     user_mods: Modifier,
     user_key_state: [u8; 6],
-    outbound_reports: Queue<KeyboardReport, 16>,
+    outbound_reports: ReportQueue<16>,
     active_layer: KeymapLayer,
     last_perm_layer: Option<KeymapLayer>,
     jank: JankState,
+    encoder_hold: Option<encoder::EncoderHold>,
+    now_micros: u64,
 }
 
 #[expect(clippy::struct_excessive_bools)]
@@ -73,10 +45,10 @@ impl KeyboardReportState {
     pub fn new() -> Self {
         Self {
             generation: 0,
-            inner_report: EMPTY_REPORT,
+            inner_report: Report::EMPTY,
             user_mods: Modifier(0),
             user_key_state: [0; 6],
-            outbound_reports: Queue::new(),
+            outbound_reports: ReportQueue::new(),
             active_layer: KeymapLayer::DvorakSe,
             last_perm_layer: None,
             jank: JankState {
@@ -89,6 +61,19 @@ impl KeyboardReportState {
                 pressing_reg_colon: false,
                 pressing_semicolon: false,
             },
+            encoder_hold: None,
+            now_micros: 0,
+        }
+    }
+
+    /// Needs to be called every loop, before any updates
+    pub fn tick(&mut self, now_micros: u64) {
+        self.now_micros = now_micros;
+        if self
+            .encoder_hold
+            .is_some_and(|hold| now_micros >= hold.release_at)
+        {
+            self.release_encoder_hold();
         }
     }
 
@@ -97,13 +82,36 @@ impl KeyboardReportState {
     }
 
     #[cfg(feature = "hiddev")]
-    pub fn report(&self) -> Option<&KeyboardReport> {
-        self.outbound_reports.peek()
+    pub fn report(&self) -> Option<usbd_hid::descriptor::KeyboardReport> {
+        self.outbound_reports
+            .peek()
+            .map(|r| usbd_hid::descriptor::KeyboardReport {
+                modifier: r.modifier,
+                reserved: 0,
+                leds: 0,
+                keycodes: r.keycodes,
+            })
     }
 
     #[cfg(feature = "hiddev")]
     pub fn accept(&mut self) {
-        self.outbound_reports.pop_front();
+        if let Some(report) = self.outbound_reports.peek() {
+            trace::record(TraceEvent::ReportSent {
+                modifier: report.modifier,
+                keys: report.key_count(),
+            });
+        }
+        self.outbound_reports.accept();
+    }
+
+    #[cfg(feature = "serial")]
+    pub fn report_stats(&self) -> rp2040_kbd_lib::report::ReportStats {
+        self.outbound_reports.stats()
+    }
+
+    #[inline]
+    pub fn active_layer(&self) -> KeymapLayer {
+        self.active_layer
     }
 
     fn restore_to_user_if_not_stale(&mut self, generation: usize) {
@@ -118,12 +126,39 @@ impl KeyboardReportState {
     }
 
     fn restore_to_user_mods(&mut self) {
-        if self.inner_report.modifier != self.user_mods.0 {
-            self.inner_report.modifier = self.user_mods.0;
+        let mods = self.user_report_mods();
+        if self.inner_report.modifier != mods {
+            self.inner_report.modifier = mods;
             self.report_current();
         }
     }
 
+    /// What the user is holding, and what the encoder holds for them
+    #[inline]
+    fn user_report_mods(&self) -> u8 {
+        self.user_mods.0 | self.encoder_hold.map_or(0, |hold| hold.modifier.0)
+    }
+
+    fn hold_modifier_until(&mut self, modifier: Modifier, release_at: u64) {
+        self.encoder_hold = Some(encoder::EncoderHold {
+            modifier,
+            release_at,
+        });
+        self.push_temp_modifiers(&[modifier]);
+    }
+
+    #[inline]
+    fn encoder_holds(&self, modifier: Modifier) -> bool {
+        self.encoder_hold
+            .is_some_and(|hold| hold.modifier.0 & modifier.0 != 0)
+    }
+
+    fn release_encoder_hold(&mut self) {
+        if self.encoder_hold.take().is_some() {
+            self.restore_to_user_mods();
+        }
+    }
+
     fn restore_to_user_keys(&mut self) {
         if self.inner_report.keycodes != self.user_key_state {
             self.inner_report.keycodes = self.user_key_state;
@@ -150,25 +185,19 @@ impl KeyboardReportState {
             }
         }
         // Overflow, pop first, unlikely
+        trace::record(TraceEvent::KeyOverflow(key_code.0));
         unsafe {
-            #[cfg(feature = "serial")]
-            {
-                let _ = crate::runtime::shared::usb::acquire_usb()
-                    .write_fmt(format_args!("Pre: {arr:?}\r\n"));
-            }
             copy_within_unchecked(arr, 1, 5, 0);
             *arr.get_unchecked_mut(5) = key_code.0;
-            #[cfg(feature = "serial")]
-            {
-                let _ = crate::runtime::shared::usb::acquire_usb()
-                    .write_fmt(format_args!("Post: {arr:?}\r\n"));
-            }
         }
     }
 
     fn report_current(&mut self) {
-        self.outbound_reports
-            .push_back(copy_report(&self.inner_report));
+        trace::record(TraceEvent::ReportQueued {
+            modifier: self.inner_report.modifier,
+            keys: self.inner_report.key_count(),
+        });
+        self.outbound_reports.push(self.inner_report);
     }
 
     fn pop_key(&mut self, key_code: KeyCode) {
@@ -218,6 +247,14 @@ impl KeyboardReportState {
         }
     }
 
+    /// Press and release `key_code` with `modifiers` added, then go back to the user's modifiers
+    fn tap_key(&mut self, key_code: KeyCode, modifiers: &[Modifier]) {
+        self.push_temp_modifiers(modifiers);
+        self.push_temp_key(key_code);
+        self.pop_temp_key(key_code);
+        self.restore_to_user_mods();
+    }
+
     pub fn pop_key_from_arr(key_code: KeyCode, arr: &mut [u8; 6]) -> bool {
         let mut at_ind = None;
         for (ind, val) in arr.iter().enumerate() {
@@ -230,17 +267,7 @@ impl KeyboardReportState {
         }
         if let Some(ind) = at_ind {
             unsafe {
-                #[cfg(feature = "serial")]
-                {
-                    let _ = crate::runtime::shared::usb::acquire_usb()
-                        .write_fmt(format_args!("Pre: {arr:?}\r\n"));
-                }
                 Self::pop_copy_back_arr(ind, arr);
-                #[cfg(feature = "serial")]
-                {
-                    let _ = crate::runtime::shared::usb::acquire_usb()
-                        .write_fmt(format_args!("Post: {arr:?}\r\n"));
-                }
             }
             true
         } else {
@@ -293,10 +320,7 @@ impl KeyboardReportState {
     fn pop_modifier(&mut self, modifier: Modifier) {
         if self.user_mods.0 & modifier.0 != 0 {
             self.user_mods.0 &= !modifier.0;
-            if self.inner_report.modifier != self.user_mods.0 {
-                self.inner_report.modifier = self.user_mods.0;
-                self.report_current();
-            }
+            self.restore_to_user_mods();
         }
     }
 
@@ -366,7 +390,6 @@ pub struct LastPressState {
 
 pub struct PinStructState {
     last_state: Option<LastPressState>,
-    jitter: PinDebouncer,
 }
 
 impl PinStructState {
@@ -389,41 +412,10 @@ impl PinStructState {
 
 impl PinStructState {
     pub const fn new() -> Self {
-        Self {
-            last_state: None,
-            jitter: PinDebouncer::new(),
-        }
+        Self { last_state: None }
     }
 }
 
-macro_rules! impl_check_update {
-    ($entity: ty) => {
-        impl $entity {
-            #[inline(never)]
-            pub fn check_update_state(
-                &mut self,
-                pressed: bool,
-                keyboard_report_state: &mut KeyboardReportState,
-                timer: Timer,
-                producer: &Producer,
-            ) -> bool {
-                if self.0.jitter.try_submit(timer.get_counter(), pressed) {
-                    if let Some(prev) = self.0.last_state.take() {
-                        self.on_release(prev, keyboard_report_state, producer);
-                    } else {
-                        keyboard_report_state.restore_to_user_state();
-                        self.on_press(keyboard_report_state, producer);
-                        self.0.update_last_state(keyboard_report_state);
-                        keyboard_report_state.increment_generation();
-                    }
-                    return true;
-                }
-                false
-            }
-        }
-    };
-}
-
 macro_rules! keyboard_key {
     ($($side: ident, $row: expr, $col: expr),*,) => {
         paste! {
@@ -462,479 +454,333 @@ keyboard_key!(
     Left, 0, 0, Left, 0, 1, Left, 0, 2, Left, 0, 3, Left, 0, 4, Left, 0, 5, Left, 1, 0, Left, 1, 1,
     Left, 1, 2, Left, 1, 3, Left, 1, 4, Left, 1, 5, Left, 2, 0, Left, 2, 1, Left, 2, 2, Left, 2, 3,
     Left, 2, 4, Left, 2, 5, Left, 3, 0, Left, 3, 1, Left, 3, 2, Left, 3, 3, Left, 3, 4, Left, 3, 5,
-    Left, 4, 1, Left, 4, 2, Left, 4, 3, Left, 4, 4, Left, 4, 5, Right, 0, 0, Right, 0, 1, Right, 0,
-    2, Right, 0, 3, Right, 0, 4, Right, 0, 5, Right, 1, 0, Right, 1, 1, Right, 1, 2, Right, 1, 3,
-    Right, 1, 4, Right, 1, 5, Right, 2, 0, Right, 2, 1, Right, 2, 2, Right, 2, 3, Right, 2, 4,
-    Right, 2, 5, Right, 3, 0, Right, 3, 1, Right, 3, 2, Right, 3, 3, Right, 3, 4, Right, 3, 5,
-    Right, 4, 1, Right, 4, 2, Right, 4, 3, Right, 4, 4, Right, 4, 5,
-);
-
-impl_check_update!(LeftRow0Col0);
-impl_check_update!(LeftRow0Col1);
-impl_check_update!(LeftRow0Col2);
-impl_check_update!(LeftRow0Col3);
-impl_check_update!(LeftRow0Col4);
-impl_check_update!(LeftRow0Col5);
-impl_check_update!(LeftRow1Col0);
-impl_check_update!(LeftRow1Col1);
-impl_check_update!(LeftRow1Col2);
-impl_check_update!(LeftRow1Col3);
-impl_check_update!(LeftRow1Col4);
-impl_check_update!(LeftRow1Col5);
-impl_check_update!(LeftRow2Col0);
-impl_check_update!(LeftRow2Col1);
-impl_check_update!(LeftRow2Col2);
-impl_check_update!(LeftRow2Col3);
-impl_check_update!(LeftRow2Col4);
-impl_check_update!(LeftRow2Col5);
-impl_check_update!(LeftRow3Col0);
-impl_check_update!(LeftRow3Col1);
-impl_check_update!(LeftRow3Col2);
-impl_check_update!(LeftRow3Col3);
-impl_check_update!(LeftRow3Col4);
-impl_check_update!(LeftRow3Col5);
-impl_check_update!(LeftRow4Col1);
-impl_check_update!(LeftRow4Col2);
-impl_check_update!(LeftRow4Col3);
-impl_check_update!(LeftRow4Col4);
-impl_check_update!(LeftRow4Col5);
-
-macro_rules! impl_read_pin_col {
-    ($($structure: expr, $row: tt,)*, $col: tt) => {
-        paste! {
-            #[inline]
-            pub fn [<read_col _ $col _pins>]($([< $structure:snake >]: &mut $structure,)* left_buttons: &mut LeftButtons, keyboard_report_state: &mut KeyboardReportState, timer: Timer, producer: &Producer) -> bool {
-                // Safety: Make sure this is properly initialized and restored
-                // at the end of this function, makes a noticeable difference in performance
-                let col = unsafe {left_buttons.cols.$col.take().unwrap_unchecked()};
-                let col = col.into_push_pull_output_in_state(PinState::Low);
-                // Just pulling chibios defaults of 0.25 micros, could probably be 0
-                crate::timer::wait_nanos(timer, 250);
-                let bank = rp2040_hal::Sio::read_bank0();
-                // Can immediately restore column, pins settle while we're reading the state that's
-                // now in mem
-                left_buttons.cols.$col = Some(col.into_pull_up_input());
-                let mut any_change = false;
-                $(
-                    let state = bank & crate::keyboard::left::[<ROW $row>] == 0;
-                    if [< $structure:snake >].0.is_pressed() != state && [< $structure:snake >].check_update_state(state, keyboard_report_state, timer, producer) {
-                        any_change = true;
-                    }
-
-                )*
-                // Wait for pins to settle
-                while rp2040_hal::Sio::read_bank0() & crate::keyboard::left::ROW_MASK != crate::keyboard::left::ROW_MASK {}
-                any_change
-            }
-
-        }
-
-    };
-}
-
-// Column pin gets toggled, more efficient to check all rows for each col at once
-// Col 0 doesn't exist on row 4
-impl_read_pin_col!(
-    LeftRow0Col0, 0,
-    LeftRow1Col0, 1,
-    LeftRow2Col0, 2,
-    LeftRow3Col0, 3,
-    ,0
+    Left, 4, 0, Left, 4, 1, Left, 4, 2, Left, 4, 3, Left, 4, 4, Left, 4, 5, Right, 0, 0, Right, 0,
+    1, Right, 0, 2, Right, 0, 3, Right, 0, 4, Right, 0, 5, Right, 1, 0, Right, 1, 1, Right, 1, 2,
+    Right, 1, 3, Right, 1, 4, Right, 1, 5, Right, 2, 0, Right, 2, 1, Right, 2, 2, Right, 2, 3,
+    Right, 2, 4, Right, 2, 5, Right, 3, 0, Right, 3, 1, Right, 3, 2, Right, 3, 3, Right, 3, 4,
+    Right, 3, 5, Right, 4, 0, Right, 4, 1, Right, 4, 2, Right, 4, 3, Right, 4, 4, Right, 4, 5,
 );
 
-impl_read_pin_col!(
-    LeftRow0Col1, 0,
-    LeftRow1Col1, 1,
-    LeftRow2Col1, 2,
-    LeftRow3Col1, 3,
-    LeftRow4Col1, 4,
-    ,1
-);
-
-impl_read_pin_col!(
-    LeftRow0Col2, 0,
-    LeftRow1Col2, 1,
-    LeftRow2Col2, 2,
-    LeftRow3Col2, 3,
-    LeftRow4Col2, 4,
-    ,2
-);
-
-impl_read_pin_col!(
-    LeftRow0Col3, 0,
-    LeftRow1Col3, 1,
-    LeftRow2Col3, 2,
-    LeftRow3Col3, 3,
-    LeftRow4Col3, 4,
-    ,3
-);
-
-impl_read_pin_col!(
-    LeftRow0Col4, 0,
-    LeftRow1Col4, 1,
-    LeftRow2Col4, 2,
-    LeftRow3Col4, 3,
-    LeftRow4Col4, 4,
-    ,4
-);
-
-// Last row only has 5 pins
-impl_read_pin_col!(
-    LeftRow0Col5, 0,
-    LeftRow1Col5, 1,
-    LeftRow2Col5, 2,
-    LeftRow3Col5, 3,
-    LeftRow4Col5, 4,
-    ,5
-);
-
-macro_rules! handle_update_right {
+macro_rules! handle_update {
     ($change: expr, $field: expr, $state: expr, $producer: expr) => {{
         if $change != $field.0.is_pressed() {
             if let Some(prev) = $field.0.last_state.take() {
                 $field.on_release(prev, $state, $producer);
             } else {
+                $state.release_encoder_hold();
                 $state.restore_to_user_state();
                 $field.on_press($state, $producer);
                 $field.0.update_last_state($state);
                 $state.increment_generation();
             }
+            true
+        } else {
+            false
         }
     }};
 }
 
 impl KeyboardState {
-    #[inline]
-    pub fn scan_left(
-        &mut self,
-        left_buttons: &mut LeftButtons,
-        keyboard_report_state: &mut KeyboardReportState,
-        timer: Timer,
-        producer: &Producer,
-    ) -> bool {
-        let col0_change = read_col_0_pins(
-            &mut self.left_row0_col0,
-            &mut self.left_row1_col0,
-            &mut self.left_row2_col0,
-            &mut self.left_row3_col0,
-            left_buttons,
-            keyboard_report_state,
-            timer,
-            producer,
-        );
-        let col1_change = read_col_1_pins(
-            &mut self.left_row0_col1,
-            &mut self.left_row1_col1,
-            &mut self.left_row2_col1,
-            &mut self.left_row3_col1,
-            &mut self.left_row4_col1,
-            left_buttons,
-            keyboard_report_state,
-            timer,
-            producer,
-        );
-        let col2_change = read_col_2_pins(
-            &mut self.left_row0_col2,
-            &mut self.left_row1_col2,
-            &mut self.left_row2_col2,
-            &mut self.left_row3_col2,
-            &mut self.left_row4_col2,
-            left_buttons,
-            keyboard_report_state,
-            timer,
-            producer,
-        );
-        let col3_change = read_col_3_pins(
-            &mut self.left_row0_col3,
-            &mut self.left_row1_col3,
-            &mut self.left_row2_col3,
-            &mut self.left_row3_col3,
-            &mut self.left_row4_col3,
-            left_buttons,
-            keyboard_report_state,
-            timer,
-            producer,
-        );
-        let col4_change = read_col_4_pins(
-            &mut self.left_row0_col4,
-            &mut self.left_row1_col4,
-            &mut self.left_row2_col4,
-            &mut self.left_row3_col4,
-            &mut self.left_row4_col4,
-            left_buttons,
-            keyboard_report_state,
-            timer,
-            producer,
-        );
-        let col5_change = read_col_5_pins(
-            &mut self.left_row0_col5,
-            &mut self.left_row1_col5,
-            &mut self.left_row2_col5,
-            &mut self.left_row3_col5,
-            &mut self.left_row4_col5,
-            left_buttons,
-            keyboard_report_state,
-            timer,
-            producer,
-        );
-        col0_change || col1_change || col2_change || col3_change || col4_change || col5_change
-    }
-
-    #[expect(clippy::too_many_lines)]
-    pub fn update_right(
+    /// Apply a debounced change from either half, returns true if a key changed
+    pub fn update(
         &mut self,
+        side: Side,
         update: MatrixUpdate,
         keyboard_report_state: &mut KeyboardReportState,
         producer: &Producer,
-    ) {
+    ) -> bool {
+        trace::record(TraceEvent::Matrix {
+            left: side == Side::Left,
+            update,
+        });
         match update.interpret_byte() {
-            MatrixChange::EncoderUpdate(enc) => {
-                rotate_layer(enc, keyboard_report_state, producer);
+            MatrixChange::EncoderUpdate { index, steps } => {
+                encoder::rotate(side, index, steps, keyboard_report_state, producer);
+                false
             }
             MatrixChange::KeyUpdate(ind, change) => {
-                #[cfg(feature = "serial")]
-                {
-                    let (row, col) = crate::keyboard::matrix_ind_to_row_col(ind.byte());
-                    let _ = core::fmt::Write::write_fmt(
-                        &mut crate::runtime::shared::usb::acquire_usb(),
-                        format_args!("R: R{row}, C{col} -> {change}\r\n"),
-                    );
-                }
-                match ind.byte() {
-                    0 => handle_update_right!(
-                        change,
-                        self.right_row0_col0,
-                        keyboard_report_state,
-                        producer
-                    ),
-                    1 => handle_update_right!(
-                        change,
-                        self.right_row0_col1,
-                        keyboard_report_state,
-                        producer
-                    ),
-                    2 => handle_update_right!(
-                        change,
-                        self.right_row0_col2,
-                        keyboard_report_state,
-                        producer
-                    ),
-                    3 => handle_update_right!(
-                        change,
-                        self.right_row0_col3,
-                        keyboard_report_state,
-                        producer
-                    ),
-                    4 => handle_update_right!(
-                        change,
-                        self.right_row0_col4,
-                        keyboard_report_state,
-                        producer
-                    ),
-                    5 => handle_update_right!(
-                        change,
-                        self.right_row0_col5,
-                        keyboard_report_state,
-                        producer
-                    ),
-                    6 => handle_update_right!(
-                        change,
-                        self.right_row1_col0,
-                        keyboard_report_state,
-                        producer
-                    ),
-                    7 => handle_update_right!(
-                        change,
-                        self.right_row1_col1,
-                        keyboard_report_state,
-                        producer
-                    ),
-                    8 => handle_update_right!(
-                        change,
-                        self.right_row1_col2,
-                        keyboard_report_state,
-                        producer
-                    ),
-                    9 => handle_update_right!(
-                        change,
-                        self.right_row1_col3,
-                        keyboard_report_state,
-                        producer
-                    ),
-                    10 => handle_update_right!(
-                        change,
-                        self.right_row1_col4,
-                        keyboard_report_state,
-                        producer
-                    ),
-                    11 => handle_update_right!(
-                        change,
-                        self.right_row1_col5,
-                        keyboard_report_state,
-                        producer
-                    ),
-                    12 => handle_update_right!(
-                        change,
-                        self.right_row2_col0,
-                        keyboard_report_state,
-                        producer
-                    ),
-                    13 => handle_update_right!(
-                        change,
-                        self.right_row2_col1,
-                        keyboard_report_state,
-                        producer
-                    ),
-                    14 => handle_update_right!(
-                        change,
-                        self.right_row2_col2,
-                        keyboard_report_state,
-                        producer
-                    ),
-                    15 => handle_update_right!(
-                        change,
-                        self.right_row2_col3,
-                        keyboard_report_state,
-                        producer
-                    ),
-                    16 => handle_update_right!(
-                        change,
-                        self.right_row2_col4,
-                        keyboard_report_state,
-                        producer
-                    ),
-                    17 => handle_update_right!(
-                        change,
-                        self.right_row2_col5,
-                        keyboard_report_state,
-                        producer
-                    ),
-                    18 => handle_update_right!(
-                        change,
-                        self.right_row3_col0,
-                        keyboard_report_state,
-                        producer
-                    ),
-                    19 => handle_update_right!(
-                        change,
-                        self.right_row3_col1,
-                        keyboard_report_state,
-                        producer
-                    ),
-                    20 => handle_update_right!(
-                        change,
-                        self.right_row3_col2,
-                        keyboard_report_state,
-                        producer
-                    ),
-                    21 => handle_update_right!(
-                        change,
-                        self.right_row3_col3,
-                        keyboard_report_state,
-                        producer
-                    ),
-                    22 => handle_update_right!(
-                        change,
-                        self.right_row3_col4,
-                        keyboard_report_state,
-                        producer
-                    ),
-                    23 => handle_update_right!(
-                        change,
-                        self.right_row3_col5,
-                        keyboard_report_state,
-                        producer
-                    ),
-                    25 => handle_update_right!(
-                        change,
-                        self.right_row4_col1,
-                        keyboard_report_state,
-                        producer
-                    ),
-                    26 => handle_update_right!(
-                        change,
-                        self.right_row4_col2,
-                        keyboard_report_state,
-                        producer
-                    ),
-                    27 => handle_update_right!(
-                        change,
-                        self.right_row4_col3,
-                        keyboard_report_state,
-                        producer
-                    ),
-                    28 => handle_update_right!(
-                        change,
-                        self.right_row4_col4,
-                        keyboard_report_state,
-                        producer
-                    ),
-                    29 => handle_update_right!(
-                        change,
-                        self.right_row4_col5,
-                        keyboard_report_state,
-                        producer
-                    ),
-                    _ => {}
-                }
+                self.update_key(side, ind.byte(), change, keyboard_report_state, producer)
             }
         }
     }
-}
 
-fn rotate_layer(
-    clockwise: bool,
-    keyboard_report_state: &mut KeyboardReportState,
-    producer: &Producer,
-) {
-    match (
-        keyboard_report_state.active_layer,
-        keyboard_report_state.last_perm_layer,
-    ) {
-        (KeymapLayer::DvorakSe, _) => {
-            if clockwise {
-                keyboard_report_state.set_perm_layer(KeymapLayer::DvorakAnsi);
-                push_layer_change(producer, keyboard_report_state.active_layer);
-            } else {
-                keyboard_report_state.set_perm_layer(KeymapLayer::QwertyGaming);
-                push_layer_change(producer, keyboard_report_state.active_layer);
-            }
-        }
-        (KeymapLayer::DvorakAnsi, _) => {
-            if clockwise {
-                keyboard_report_state.set_perm_layer(KeymapLayer::DvorakSeMac);
-                push_layer_change(producer, keyboard_report_state.active_layer);
-            } else {
-                keyboard_report_state.set_perm_layer(KeymapLayer::DvorakSe);
-                push_layer_change(producer, keyboard_report_state.active_layer);
-            }
+    /// Bring one half's keys in line with a snapshot, only keys that differ get
+    /// a press or release. An empty snapshot releases whatever that half left pressed.
+    /// Returns true if any key changed.
+    pub fn reconcile(
+        &mut self,
+        side: Side,
+        snapshot: KeyBitmap,
+        keyboard_report_state: &mut KeyboardReportState,
+        producer: &Producer,
+    ) -> bool {
+        let mut changed = false;
+        for ind in 0..NUM_ROWS * NUM_COLS {
+            changed |= self.update_key(
+                side,
+                ind,
+                snapshot.is_pressed(ind),
+                keyboard_report_state,
+                producer,
+            );
         }
-        (KeymapLayer::DvorakSeMac, _) => {
-            if clockwise {
-                keyboard_report_state.set_perm_layer(KeymapLayer::QwertyGaming);
-                push_layer_change(producer, keyboard_report_state.active_layer);
-            } else {
-                keyboard_report_state.set_perm_layer(KeymapLayer::DvorakAnsi);
-                push_layer_change(producer, keyboard_report_state.active_layer);
-            }
+        changed
+    }
+
+    /// `ind` is where the board has the key, the keymap has a key at every position it's
+    /// remapped to
+    #[inline]
+    fn update_key(
+        &mut self,
+        side: Side,
+        ind: u8,
+        change: bool,
+        keyboard_report_state: &mut KeyboardReportState,
+        producer: &Producer,
+    ) -> bool {
+        let Some(position) = board::KEYMAP.keymap_position(side == Side::Left, ind) else {
+            return false;
+        };
+        let ind = position.byte();
+        match side {
+            Side::Left => self.update_left_key(ind, change, keyboard_report_state, producer),
+            Side::Right => self.update_right_key(ind, change, keyboard_report_state, producer),
         }
-        (KeymapLayer::QwertyGaming, _) => {
-            if clockwise {
-                keyboard_report_state.set_perm_layer(KeymapLayer::DvorakSe);
-                push_layer_change(producer, keyboard_report_state.active_layer);
-            } else {
-                keyboard_report_state.set_perm_layer(KeymapLayer::DvorakAnsi);
-                push_layer_change(producer, keyboard_report_state.active_layer);
-            }
+    }
+
+    fn update_left_key(
+        &mut self,
+        ind: u8,
+        change: bool,
+        keyboard_report_state: &mut KeyboardReportState,
+        producer: &Producer,
+    ) -> bool {
+        match ind {
+            0 => handle_update!(change, self.left_row0_col0, keyboard_report_state, producer),
+            1 => handle_update!(change, self.left_row0_col1, keyboard_report_state, producer),
+            2 => handle_update!(change, self.left_row0_col2, keyboard_report_state, producer),
+            3 => handle_update!(change, self.left_row0_col3, keyboard_report_state, producer),
+            4 => handle_update!(change, self.left_row0_col4, keyboard_report_state, producer),
+            5 => handle_update!(change, self.left_row0_col5, keyboard_report_state, producer),
+            6 => handle_update!(change, self.left_row1_col0, keyboard_report_state, producer),
+            7 => handle_update!(change, self.left_row1_col1, keyboard_report_state, producer),
+            8 => handle_update!(change, self.left_row1_col2, keyboard_report_state, producer),
+            9 => handle_update!(change, self.left_row1_col3, keyboard_report_state, producer),
+            10 => handle_update!(change, self.left_row1_col4, keyboard_report_state, producer),
+            11 => handle_update!(change, self.left_row1_col5, keyboard_report_state, producer),
+            12 => handle_update!(change, self.left_row2_col0, keyboard_report_state, producer),
+            13 => handle_update!(change, self.left_row2_col1, keyboard_report_state, producer),
+            14 => handle_update!(change, self.left_row2_col2, keyboard_report_state, producer),
+            15 => handle_update!(change, self.left_row2_col3, keyboard_report_state, producer),
+            16 => handle_update!(change, self.left_row2_col4, keyboard_report_state, producer),
+            17 => handle_update!(change, self.left_row2_col5, keyboard_report_state, producer),
+            18 => handle_update!(change, self.left_row3_col0, keyboard_report_state, producer),
+            19 => handle_update!(change, self.left_row3_col1, keyboard_report_state, producer),
+            20 => handle_update!(change, self.left_row3_col2, keyboard_report_state, producer),
+            21 => handle_update!(change, self.left_row3_col3, keyboard_report_state, producer),
+            22 => handle_update!(change, self.left_row3_col4, keyboard_report_state, producer),
+            23 => handle_update!(change, self.left_row3_col5, keyboard_report_state, producer),
+            24 => handle_update!(change, self.left_row4_col0, keyboard_report_state, producer),
+            25 => handle_update!(change, self.left_row4_col1, keyboard_report_state, producer),
+            26 => handle_update!(change, self.left_row4_col2, keyboard_report_state, producer),
+            27 => handle_update!(change, self.left_row4_col3, keyboard_report_state, producer),
+            28 => handle_update!(change, self.left_row4_col4, keyboard_report_state, producer),
+            29 => handle_update!(change, self.left_row4_col5, keyboard_report_state, producer),
+            _ => false,
         }
-        _ => {}
     }
-    #[cfg(feature = "serial")]
-    {
-        let _ = crate::runtime::shared::usb::acquire_usb().write_fmt(format_args!(
-            "Post rotate layer: {:?}\r\n",
-            keyboard_report_state.active_layer
-        ));
+
+    #[expect(clippy::too_many_lines)]
+    fn update_right_key(
+        &mut self,
+        ind: u8,
+        change: bool,
+        keyboard_report_state: &mut KeyboardReportState,
+        producer: &Producer,
+    ) -> bool {
+        match ind {
+            0 => handle_update!(
+                change,
+                self.right_row0_col0,
+                keyboard_report_state,
+                producer
+            ),
+            1 => handle_update!(
+                change,
+                self.right_row0_col1,
+                keyboard_report_state,
+                producer
+            ),
+            2 => handle_update!(
+                change,
+                self.right_row0_col2,
+                keyboard_report_state,
+                producer
+            ),
+            3 => handle_update!(
+                change,
+                self.right_row0_col3,
+                keyboard_report_state,
+                producer
+            ),
+            4 => handle_update!(
+                change,
+                self.right_row0_col4,
+                keyboard_report_state,
+                producer
+            ),
+            5 => handle_update!(
+                change,
+                self.right_row0_col5,
+                keyboard_report_state,
+                producer
+            ),
+            6 => handle_update!(
+                change,
+                self.right_row1_col0,
+                keyboard_report_state,
+                producer
+            ),
+            7 => handle_update!(
+                change,
+                self.right_row1_col1,
+                keyboard_report_state,
+                producer
+            ),
+            8 => handle_update!(
+                change,
+                self.right_row1_col2,
+                keyboard_report_state,
+                producer
+            ),
+            9 => handle_update!(
+                change,
+                self.right_row1_col3,
+                keyboard_report_state,
+                producer
+            ),
+            10 => handle_update!(
+                change,
+                self.right_row1_col4,
+                keyboard_report_state,
+                producer
+            ),
+            11 => handle_update!(
+                change,
+                self.right_row1_col5,
+                keyboard_report_state,
+                producer
+            ),
+            12 => handle_update!(
+                change,
+                self.right_row2_col0,
+                keyboard_report_state,
+                producer
+            ),
+            13 => handle_update!(
+                change,
+                self.right_row2_col1,
+                keyboard_report_state,
+                producer
+            ),
+            14 => handle_update!(
+                change,
+                self.right_row2_col2,
+                keyboard_report_state,
+                producer
+            ),
+            15 => handle_update!(
+                change,
+                self.right_row2_col3,
+                keyboard_report_state,
+                producer
+            ),
+            16 => handle_update!(
+                change,
+                self.right_row2_col4,
+                keyboard_report_state,
+                producer
+            ),
+            17 => handle_update!(
+                change,
+                self.right_row2_col5,
+                keyboard_report_state,
+                producer
+            ),
+            18 => handle_update!(
+                change,
+                self.right_row3_col0,
+                keyboard_report_state,
+                producer
+            ),
+            19 => handle_update!(
+                change,
+                self.right_row3_col1,
+                keyboard_report_state,
+                producer
+            ),
+            20 => handle_update!(
+                change,
+                self.right_row3_col2,
+                keyboard_report_state,
+                producer
+            ),
+            21 => handle_update!(
+                change,
+                self.right_row3_col3,
+                keyboard_report_state,
+                producer
+            ),
+            22 => handle_update!(
+                change,
+                self.right_row3_col4,
+                keyboard_report_state,
+                producer
+            ),
+            23 => handle_update!(
+                change,
+                self.right_row3_col5,
+                keyboard_report_state,
+                producer
+            ),
+            24 => handle_update!(
+                change,
+                self.right_row4_col0,
+                keyboard_report_state,
+                producer
+            ),
+            25 => handle_update!(
+                change,
+                self.right_row4_col1,
+                keyboard_report_state,
+                producer
+            ),
+            26 => handle_update!(
+                change,
+                self.right_row4_col2,
+                keyboard_report_state,
+                producer
+            ),
+            27 => handle_update!(
+                change,
+                self.right_row4_col3,
+                keyboard_report_state,
+                producer
+            ),
+            28 => handle_update!(
+                change,
+                self.right_row4_col4,
+                keyboard_report_state,
+                producer
+            ),
+            29 => handle_update!(
+                change,
+                self.right_row4_col5,
+                keyboard_report_state,
+                producer
+            ),
+            _ => false,
+        }
     }
 }
 
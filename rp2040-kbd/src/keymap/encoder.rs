@@ -0,0 +1,175 @@
+use super::{mapping, KeyboardReportState};
+use crate::keyboard::Side;
+use crate::layer::KeymapLayer;
+use crate::runtime::shared::cores_master::{push_layer_change, Producer};
+use rp2040_kbd_lib::keycodes::{KeyCode, Modifier};
+
+/// How long a modifier held by [`EncoderAction::HoldAndTap`] stays down after the last detent
+const ENCODER_HOLD_MICROS: u64 = 800_000;
+
+/// What a single step does, bound per layer and direction in [`mapping::encoder_binding`].
+/// There's no pointer report, so no scroll wheel, page up/down is the closest thing.
+#[derive(Copy, Clone)]
+pub enum EncoderAction {
+    Nothing,
+    /// Press and release a key
+    Tap(KeyCode),
+    /// Press and release a key with extra modifiers, ex: undo
+    Chord(KeyCode, &'static [Modifier]),
+    /// Puts `hold` down and keeps it there until the encoder has been still for a while,
+    /// or another key is pressed, then taps `key` with `with` added. Ex: Alt-Tab
+    HoldAndTap {
+        hold: Modifier,
+        key: KeyCode,
+        with: &'static [Modifier],
+    },
+    /// Anything else
+    Macro(fn(&mut KeyboardReportState, &Producer)),
+}
+
+/// How many of the incoming steps a binding acts on, the half with the encoder has
+/// already scaled them by how fast it's turning
+#[derive(Copy, Clone)]
+pub enum Acceleration {
+    /// Once per detent, no matter how fast
+    Off,
+    /// Once per step, at most this many times per detent
+    UpTo(u8),
+}
+
+#[derive(Copy, Clone)]
+pub struct EncoderBinding {
+    pub clockwise: EncoderAction,
+    pub counter_clockwise: EncoderAction,
+    pub acceleration: Acceleration,
+}
+
+impl EncoderBinding {
+    pub const NOTHING: Self = Self::new(EncoderAction::Nothing, EncoderAction::Nothing);
+
+    pub const fn new(clockwise: EncoderAction, counter_clockwise: EncoderAction) -> Self {
+        Self {
+            clockwise,
+            counter_clockwise,
+            acceleration: Acceleration::Off,
+        }
+    }
+
+    pub const fn accelerated(self, up_to: u8) -> Self {
+        Self {
+            acceleration: Acceleration::UpTo(up_to),
+            ..self
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+pub(super) struct EncoderHold {
+    pub(super) modifier: Modifier,
+    pub(super) release_at: u64,
+}
+
+pub(super) fn rotate(
+    side: Side,
+    index: u8,
+    steps: i8,
+    keyboard_report_state: &mut KeyboardReportState,
+    producer: &Producer,
+) {
+    let binding = mapping::encoder_binding(side, index, keyboard_report_state);
+    let action = if steps > 0 {
+        binding.clockwise
+    } else {
+        binding.counter_clockwise
+    };
+    let times = match binding.acceleration {
+        Acceleration::Off => 1,
+        Acceleration::UpTo(max) => steps.unsigned_abs().min(max),
+    };
+    for _ in 0..times {
+        run_action(action, keyboard_report_state, producer);
+    }
+}
+
+fn run_action(
+    action: EncoderAction,
+    keyboard_report_state: &mut KeyboardReportState,
+    producer: &Producer,
+) {
+    match action {
+        EncoderAction::Nothing => {}
+        EncoderAction::Tap(key_code) => keyboard_report_state.tap_key(key_code, &[]),
+        EncoderAction::Chord(key_code, modifiers) => {
+            keyboard_report_state.tap_key(key_code, modifiers);
+        }
+        EncoderAction::HoldAndTap { hold, key, with } => {
+            let release_at = keyboard_report_state.now_micros + ENCODER_HOLD_MICROS;
+            keyboard_report_state.hold_modifier_until(hold, release_at);
+            keyboard_report_state.tap_key(key, with);
+        }
+        EncoderAction::Macro(action) => action(keyboard_report_state, producer),
+    }
+}
+
+pub(super) fn next_base_layer(
+    keyboard_report_state: &mut KeyboardReportState,
+    producer: &Producer,
+) {
+    rotate_layer(true, keyboard_report_state, producer);
+}
+
+pub(super) fn previous_base_layer(
+    keyboard_report_state: &mut KeyboardReportState,
+    producer: &Producer,
+) {
+    rotate_layer(false, keyboard_report_state, producer);
+}
+
+fn rotate_layer(
+    clockwise: bool,
+    keyboard_report_state: &mut KeyboardReportState,
+    producer: &Producer,
+) {
+    match (
+        keyboard_report_state.active_layer,
+        keyboard_report_state.last_perm_layer,
+    ) {
+        (KeymapLayer::DvorakSe, _) => {
+            if clockwise {
+                keyboard_report_state.set_perm_layer(KeymapLayer::DvorakAnsi);
+                push_layer_change(producer, keyboard_report_state.active_layer);
+            } else {
+                keyboard_report_state.set_perm_layer(KeymapLayer::QwertyGaming);
+                push_layer_change(producer, keyboard_report_state.active_layer);
+            }
+        }
+        (KeymapLayer::DvorakAnsi, _) => {
+            if clockwise {
+                keyboard_report_state.set_perm_layer(KeymapLayer::DvorakSeMac);
+                push_layer_change(producer, keyboard_report_state.active_layer);
+            } else {
+                keyboard_report_state.set_perm_layer(KeymapLayer::DvorakSe);
+                push_layer_change(producer, keyboard_report_state.active_layer);
+            }
+        }
+        (KeymapLayer::DvorakSeMac, _) => {
+            if clockwise {
+                keyboard_report_state.set_perm_layer(KeymapLayer::QwertyGaming);
+                push_layer_change(producer, keyboard_report_state.active_layer);
+            } else {
+                keyboard_report_state.set_perm_layer(KeymapLayer::DvorakAnsi);
+                push_layer_change(producer, keyboard_report_state.active_layer);
+            }
+        }
+        (KeymapLayer::QwertyGaming, _) => {
+            if clockwise {
+                keyboard_report_state.set_perm_layer(KeymapLayer::DvorakSe);
+                push_layer_change(producer, keyboard_report_state.active_layer);
+            } else {
+                keyboard_report_state.set_perm_layer(KeymapLayer::DvorakAnsi);
+                push_layer_change(producer, keyboard_report_state.active_layer);
+            }
+        }
+        _ => {}
+    }
+}
@@ -1,17 +1,19 @@
+use super::encoder::{self, EncoderAction, EncoderBinding};
 use super::{
     KeyboardButton, KeyboardReportState, KeymapLayer, LastPressState, LeftRow0Col0, LeftRow0Col1,
     LeftRow0Col2, LeftRow0Col3, LeftRow0Col4, LeftRow0Col5, LeftRow1Col0, LeftRow1Col1,
     LeftRow1Col2, LeftRow1Col3, LeftRow1Col4, LeftRow1Col5, LeftRow2Col0, LeftRow2Col1,
     LeftRow2Col2, LeftRow2Col3, LeftRow2Col4, LeftRow2Col5, LeftRow3Col0, LeftRow3Col1,
-    LeftRow3Col2, LeftRow3Col3, LeftRow3Col4, LeftRow3Col5, LeftRow4Col1, LeftRow4Col2,
-    LeftRow4Col3, LeftRow4Col4, LeftRow4Col5, RightRow0Col0, RightRow0Col1, RightRow0Col2,
-    RightRow0Col3, RightRow0Col4, RightRow0Col5, RightRow1Col0, RightRow1Col1, RightRow1Col2,
-    RightRow1Col3, RightRow1Col4, RightRow1Col5, RightRow2Col0, RightRow2Col1, RightRow2Col2,
-    RightRow2Col3, RightRow2Col4, RightRow2Col5, RightRow3Col0, RightRow3Col1, RightRow3Col2,
-    RightRow3Col3, RightRow3Col4, RightRow3Col5, RightRow4Col1, RightRow4Col2, RightRow4Col3,
-    RightRow4Col4, RightRow4Col5,
+    LeftRow3Col2, LeftRow3Col3, LeftRow3Col4, LeftRow3Col5, LeftRow4Col0, LeftRow4Col1,
+    LeftRow4Col2, LeftRow4Col3, LeftRow4Col4, LeftRow4Col5, RightRow0Col0, RightRow0Col1,
+    RightRow0Col2, RightRow0Col3, RightRow0Col4, RightRow0Col5, RightRow1Col0, RightRow1Col1,
+    RightRow1Col2, RightRow1Col3, RightRow1Col4, RightRow1Col5, RightRow2Col0, RightRow2Col1,
+    RightRow2Col2, RightRow2Col3, RightRow2Col4, RightRow2Col5, RightRow3Col0, RightRow3Col1,
+    RightRow3Col2, RightRow3Col3, RightRow3Col4, RightRow3Col5, RightRow4Col0, RightRow4Col1,
+    RightRow4Col2, RightRow4Col3, RightRow4Col4, RightRow4Col5,
 };
-use crate::runtime::shared::cores_left::{push_layer_change, push_reboot_and_halt, Producer};
+use crate::keyboard::Side;
+use crate::runtime::shared::cores_master::{push_layer_change, push_reboot_and_halt, Producer};
 use crate::{base_layer, temp_layer};
 use rp2040_kbd_lib::keycodes::{KeyCode, Modifier};
 
@@ -1261,7 +1263,20 @@ impl KeyboardButton for LeftRow3Col5 {
     }
 }
 
-// Row 4 col 0 does not exist
+// Not on the lily58, the sofle's number row has a switch here
+impl KeyboardButton for LeftRow4Col0 {
+    fn on_press(&mut self, _keyboard_report_state: &mut KeyboardReportState, _producer: &Producer) {
+    }
+
+    fn on_release(
+        &mut self,
+        _prev: LastPressState,
+        _keyboard_report_state: &mut KeyboardReportState,
+        _producer: &Producer,
+    ) {
+    }
+}
+
 impl KeyboardButton for LeftRow4Col1 {
     fn on_press(&mut self, _keyboard_report_state: &mut KeyboardReportState, producer: &Producer) {
         push_reboot_and_halt(producer);
@@ -1774,6 +1789,10 @@ impl KeyboardButton for RightRow1Col2 {
             keyboard_report_state.last_perm_layer,
             keyboard_report_state.active_layer,
         ) {
+            // 48Mhz, both halves switch on the slave's next poll
+            temp_layer!(KeymapLayer::Settings) => {
+                crate::clock::request(0);
+            }
             temp_layer!(KeymapLayer::Num) => {
                 keyboard_report_state.push_key(KeyCode::N9);
             }
@@ -1848,6 +1867,10 @@ impl KeyboardButton for RightRow1Col3 {
             keyboard_report_state.last_perm_layer,
             keyboard_report_state.active_layer,
         ) {
+            // 133Mhz, both halves switch on the slave's next poll
+            temp_layer!(KeymapLayer::Settings) => {
+                crate::clock::request(1);
+            }
             temp_layer!(KeymapLayer::Num) => {
                 keyboard_report_state.push_key(KeyCode::N8);
             }
@@ -1922,6 +1945,10 @@ impl KeyboardButton for RightRow1Col4 {
             keyboard_report_state.last_perm_layer,
             keyboard_report_state.active_layer,
         ) {
+            // 199.5Mhz, the default, both halves switch on the slave's next poll
+            temp_layer!(KeymapLayer::Settings) => {
+                crate::clock::request(2);
+            }
             temp_layer!(KeymapLayer::Num) => {
                 keyboard_report_state.push_key(KeyCode::N7);
             }
@@ -2593,6 +2620,20 @@ impl KeyboardButton for RightRow3Col5 {
     }
 }
 
+// Not on the lily58, the sofle's number row has a switch here
+impl KeyboardButton for RightRow4Col0 {
+    fn on_press(&mut self, _keyboard_report_state: &mut KeyboardReportState, _producer: &Producer) {
+    }
+
+    fn on_release(
+        &mut self,
+        _prev: LastPressState,
+        _keyboard_report_state: &mut KeyboardReportState,
+        _producer: &Producer,
+    ) {
+    }
+}
+
 impl KeyboardButton for RightRow4Col1 {
     fn on_press(&mut self, _keyboard_report_state: &mut KeyboardReportState, _producer: &Producer) {
     }
@@ -2652,9 +2693,10 @@ impl KeyboardButton for RightRow4Col4 {
     }
 }
 
+// The rotary encoder's push switch
 impl KeyboardButton for RightRow4Col5 {
     fn on_press(&mut self, keyboard_report_state: &mut KeyboardReportState, _producer: &Producer) {
-        keyboard_report_state.push_key(KeyCode::N5);
+        keyboard_report_state.push_key(KeyCode::KC_MUTE);
     }
 
     fn on_release(
@@ -2663,6 +2705,91 @@ impl KeyboardButton for RightRow4Col5 {
         keyboard_report_state: &mut KeyboardReportState,
         _producer: &Producer,
     ) {
-        keyboard_report_state.pop_key(KeyCode::N5);
+        keyboard_report_state.pop_key(KeyCode::KC_MUTE);
+    }
+}
+
+/// Encoder actions for the current layer and held modifiers, `index` is the encoder's
+/// position on its half
+pub(super) fn encoder_binding(
+    side: Side,
+    index: u8,
+    keyboard_report_state: &KeyboardReportState,
+) -> EncoderBinding {
+    if (side, index) != (Side::Right, 0) {
+        return EncoderBinding::NOTHING;
+    }
+    let holding = |modifier: Modifier| {
+        keyboard_report_state.has_user_modifier(modifier)
+            || keyboard_report_state.encoder_holds(modifier)
+    };
+    match (
+        keyboard_report_state.last_perm_layer,
+        keyboard_report_state.active_layer,
+    ) {
+        temp_layer!(KeymapLayer::Settings) => EncoderBinding::new(
+            EncoderAction::Tap(KeyCode::KC_VOUP),
+            EncoderAction::Tap(KeyCode::KC_VODN),
+        )
+        .accelerated(4),
+        temp_layer!(KeymapLayer::Num) => EncoderBinding::new(
+            EncoderAction::Tap(KeyCode::PAGE_DOWN),
+            EncoderAction::Tap(KeyCode::PAGE_UP),
+        )
+        .accelerated(3),
+        temp_layer!(KeymapLayer::Raise) => EncoderBinding::new(
+            EncoderAction::Tap(KeyCode::RIGHT_ARROW),
+            EncoderAction::Tap(KeyCode::LEFT_ARROW),
+        )
+        .accelerated(6),
+        // Redo, undo
+        temp_layer!(KeymapLayer::Lower | KeymapLayer::LowerAnsi) => EncoderBinding::new(
+            EncoderAction::Chord(KeyCode::Z, &[Modifier::LEFT_CONTROL, Modifier::LEFT_SHIFT]),
+            EncoderAction::Chord(KeyCode::Z, &[Modifier::LEFT_CONTROL]),
+        ),
+        temp_layer!(KeymapLayer::LowerSeMac) => EncoderBinding::new(
+            EncoderAction::Chord(KeyCode::Z, &[Modifier::LEFT_GUI, Modifier::LEFT_SHIFT]),
+            EncoderAction::Chord(KeyCode::Z, &[Modifier::LEFT_GUI]),
+        ),
+        // Cmd-Tab
+        base_layer!(KeymapLayer::DvorakSeMac) if holding(Modifier::LEFT_GUI) => {
+            EncoderBinding::new(
+                EncoderAction::HoldAndTap {
+                    hold: Modifier::LEFT_GUI,
+                    key: KeyCode::TAB,
+                    with: &[],
+                },
+                EncoderAction::HoldAndTap {
+                    hold: Modifier::LEFT_GUI,
+                    key: KeyCode::TAB,
+                    with: &[Modifier::LEFT_SHIFT],
+                },
+            )
+        }
+        // Alt-Tab
+        base_layer!(
+            KeymapLayer::DvorakSe | KeymapLayer::DvorakAnsi | KeymapLayer::QwertyGaming
+        ) if holding(Modifier::LEFT_ALT) => EncoderBinding::new(
+            EncoderAction::HoldAndTap {
+                hold: Modifier::LEFT_ALT,
+                key: KeyCode::TAB,
+                with: &[],
+            },
+            EncoderAction::HoldAndTap {
+                hold: Modifier::LEFT_ALT,
+                key: KeyCode::TAB,
+                with: &[Modifier::LEFT_SHIFT],
+            },
+        ),
+        base_layer!(
+            KeymapLayer::DvorakSe
+                | KeymapLayer::DvorakAnsi
+                | KeymapLayer::DvorakSeMac
+                | KeymapLayer::QwertyGaming
+        ) => EncoderBinding::new(
+            EncoderAction::Macro(encoder::next_base_layer),
+            EncoderAction::Macro(encoder::previous_base_layer),
+        ),
+        _ => EncoderBinding::NOTHING,
     }
 }
@@ -0,0 +1,77 @@
+use crate::keyboard::oled::OledLineString;
+
+#[repr(u8)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum KeymapLayer {
+    DvorakSe,
+    DvorakAnsi,
+    DvorakSeMac,
+    QwertyGaming,
+    Lower,
+    LowerSeMac,
+    LowerAnsi,
+    Raise,
+    Num,
+    Settings,
+}
+
+impl KeymapLayer {
+    /// Stable index, used to send the layer over the split link
+    #[inline]
+    pub fn index(self) -> u8 {
+        self as u8
+    }
+
+    pub fn from_index(index: u8) -> Option<Self> {
+        const ALL: [KeymapLayer; 10] = [
+            KeymapLayer::DvorakSe,
+            KeymapLayer::DvorakAnsi,
+            KeymapLayer::DvorakSeMac,
+            KeymapLayer::QwertyGaming,
+            KeymapLayer::Lower,
+            KeymapLayer::LowerSeMac,
+            KeymapLayer::LowerAnsi,
+            KeymapLayer::Raise,
+            KeymapLayer::Num,
+            KeymapLayer::Settings,
+        ];
+        ALL.get(usize::from(index)).copied()
+    }
+}
+
+pub fn layer_to_string(keymap_layer: KeymapLayer) -> OledLineString {
+    let mut s = heapless::String::new();
+    match keymap_layer {
+        KeymapLayer::DvorakSe => {
+            let _ = s.push_str("DV-SE");
+        }
+        KeymapLayer::DvorakAnsi => {
+            let _ = s.push_str("DV-AN");
+        }
+        KeymapLayer::DvorakSeMac => {
+            let _ = s.push_str("DV-SM");
+        }
+        KeymapLayer::QwertyGaming => {
+            let _ = s.push_str("QW-GM");
+        }
+        KeymapLayer::Lower => {
+            let _ = s.push_str("LO");
+        }
+        KeymapLayer::LowerSeMac => {
+            let _ = s.push_str("LO-MA");
+        }
+        KeymapLayer::LowerAnsi => {
+            let _ = s.push_str("LO-AN");
+        }
+        KeymapLayer::Raise => {
+            let _ = s.push_str("RA");
+        }
+        KeymapLayer::Num => {
+            let _ = s.push_str("NUM");
+        }
+        KeymapLayer::Settings => {
+            let _ = s.push_str("SET");
+        }
+    }
+    s
+}
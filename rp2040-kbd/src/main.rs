@@ -1,12 +1,16 @@
 #![cfg_attr(not(test), no_std)]
 #![no_main]
 
+mod board;
+mod clock;
+mod crash;
 mod hid;
 pub(crate) mod keyboard;
-#[cfg(feature = "left")]
 mod keymap;
+mod layer;
 pub(crate) mod runtime;
 mod timer;
+mod watchdog;
 
 use core::ops::Div;
 use embedded_graphics::draw_target::DrawTarget;
@@ -28,70 +32,35 @@ use liatris::hal;
 use crate::keyboard::oled::OledHandle;
 use crate::keyboard::power_led::PowerLed;
 use embedded_hal::digital::InputPin;
-use liatris::pac::vreg_and_chip_reset::vreg::VSEL_A;
 use liatris::pac::I2C1;
 use rp2040_hal::clocks::{ClocksManager, PeripheralClock};
-use rp2040_hal::fugit::{HertzU32, RateExtU32};
+use rp2040_hal::dma::DMAExt;
+use rp2040_hal::fugit::RateExtU32;
 use rp2040_hal::gpio::bank0::{Gpio2, Gpio3};
 use rp2040_hal::gpio::{FunctionI2C, Pin, PullDown};
 use rp2040_hal::multicore::Multicore;
 use rp2040_hal::pll::common_configs::PLL_USB_48MHZ;
-use rp2040_hal::pll::{setup_pll_blocking, PLLConfig};
+use rp2040_hal::pll::setup_pll_blocking;
 use rp2040_hal::vreg::{get_voltage, set_voltage};
 use rp2040_hal::xosc::setup_xosc_blocking;
 use rp2040_hal::Clock;
 use ssd1306::mode::DisplayConfig;
-use ssd1306::prelude::DisplayRotation;
-use ssd1306::size::DisplaySize128x32;
 use ssd1306::Ssd1306;
 
 #[cfg(all(feature = "serial", feature = "hiddev"))]
 const _ILLEGAL_FEATURES: () = assert!(false, "Can't compile as both serial and hiddev");
 
-#[cfg(all(feature = "left", feature = "right"))]
-const _ILLEGAL_SIDES: () = assert!(false, "Can't compile as both right and left");
+/// How long a half waits for a host to configure it at boot before becoming the slave,
+/// the slave still takes over as master if a host shows up later.
+#[cfg(feature = "hiddev")]
+const HOST_WAIT_MICROS: u64 = 2_000_000;
 
-#[cfg(all(feature = "hiddev", feature = "right"))]
-const _RIGHT_HIDDEN: () = assert!(false, "Can't compile right as hiddev");
+/// The side pin is sampled this many times at boot, every sample has to agree
+const SIDE_PIN_SAMPLES: usize = 32;
+const SIDE_PIN_SAMPLE_SPACING_NANOS: u64 = 10_000;
 
-/// FREF is the xosc crystal freq (12Mhz)
-/// POSTDIV(both) 1 -7
-/// If postdiv has different values, POSTDIV1 should be higher
-/// for energy efficiency
-/// Refdiv recommended to be 1
-/// MAX VCO = 1600Mhz
-/// Docs says FBDIV which is, but that can be replaced with VCO since it's a part of it
-/// Calculate by (FREF / REFDIV) * FBDIV / (POSTDIV1 * POSTDIV2)
-/// Where VCO = FREF * FBDIV => FBDIV = VCO / FREF
-/// Ex: (12 / 1) * 133 / (6 * 2) => VCO = 12 * 133 = 1596Mhz
-/// There's a script for finding your optimal clock frequency here:
-/// <https://github.com/raspberrypi/pico-sdk/blob/master/src/rp2_common/hardware_clocks/scripts/vcocalc.py>
-/// Ex legal config for 200Mhz
-/// `VCO_FREQ` = 1200Mhz
-/// refdiv = 1
-/// postdiv1 = 6, postdiv2 = 1
-/// Higher VCO-freq decreases jitter but increases power consumption,
-/// The only way to increase VCO is to compromize on output frequency
-/// Below is a Legal config for 199.5Mhz
-/// `VCO_FREQ` = 1596
-/// REFDIV = 1
-/// FBDIV = 133
-/// POSTDIV1 = 4
-/// POSTDIV2 = 2
-const PLL_1995_MHZ: PLLConfig = PLLConfig {
-    vco_freq: HertzU32::MHz(1596),
-    refdiv: 1,
-    post_div1: 4,
-    post_div2: 2,
-};
-
-/*/// Example 133Mhz config
-const PLL_133_MHZ: PLLConfig = PLLConfig {
-    vco_freq: HertzU32::MHz(1596),
-    refdiv: 1,
-    post_div1: 6,
-    post_div2: 2,
-};*/
+/// How long a panic or a stall from before the reset stays on the oled, before booting on
+const CRASH_SHOWN_NANOS: u64 = 3_000_000_000;
 
 /// Entry point to our bare-metal application.
 ///
@@ -110,13 +79,15 @@ fn setup_kbd() -> ! {
     // Grab our singleton objects
     let mut pac = pac::Peripherals::take().unwrap();
 
-    // Up voltage if necessary for 200Mhz clock
-    if get_voltage(&pac.VREG_AND_CHIP_RESET) != Some(VSEL_A::VOLTAGE1_15) {
-        set_voltage(&mut pac.VREG_AND_CHIP_RESET, VSEL_A::VOLTAGE1_15);
+    let profile = &clock::PROFILES[usize::from(clock::DEFAULT_PROFILE)];
+    // Up voltage if necessary for the 200Mhz clock
+    if get_voltage(&pac.VREG_AND_CHIP_RESET) != Some(profile.voltage()) {
+        set_voltage(&mut pac.VREG_AND_CHIP_RESET, profile.voltage());
     }
 
     // Set up the watchdog driver - needed by the clock setup code
     let mut watchdog = hal::Watchdog::new(pac.WATCHDOG);
+    let reset_reason = watchdog::boot(&mut watchdog);
 
     let xosc = setup_xosc_blocking(pac.XOSC, liatris::XOSC_CRYSTAL_FREQ.Hz()).unwrap();
     watchdog.enable_tick_generation((liatris::XOSC_CRYSTAL_FREQ / 1_000_000) as u8);
@@ -125,7 +96,7 @@ fn setup_kbd() -> ! {
     let pll_sys = setup_pll_blocking(
         pac.PLL_SYS,
         xosc.operating_frequency(),
-        PLL_1995_MHZ,
+        profile.pll(),
         &mut clocks,
         &mut pac.RESETS,
     )
@@ -139,12 +110,17 @@ fn setup_kbd() -> ! {
     )
     .unwrap();
     clocks.init_default(&xosc, &pll_sys, &pll_usb).unwrap();
+    // The system clock can be switched at runtime, keep the i2c's clock out of that
+    clocks
+        .peripheral_clock
+        .configure_clock(&pll_usb, pll_usb.operating_frequency())
+        .unwrap();
     // I want this high, but also as a clean divisor of the system clock
-    // I'm using some weird protocol now with a header and footer on the message,
-    // stretching it to 16 bits, to account for connects/disconnects of the right side
-    // producing faulty messages.
-    // The 'fake-uart' clock divisor is the baud-rate * 16, and can at most be 1,
-    // I'm putting it at 1, so that's `199_500_000 / 16 => 12_468_750`.
+    // Each event is sent as a 4 byte frame with a start marker and a CRC, to account for
+    // connects/disconnects of the other half producing faulty messages.
+    // The link runs its state machines at 8 clocks per bit, at a divisor of 2 that's
+    // `199_500_000 / 16 => 12_468_750`.
+    // The divisor stays 2 under every clock profile, the baud follows the system clock.
     // Todo: Maybe check that this is a clean divisor
     let uart_baud = clocks.system_clock.freq().div(16);
 
@@ -166,9 +142,18 @@ fn setup_kbd() -> ! {
         scl_pin,
         &clocks.peripheral_clock,
     );
+    if let Some(report) = crash::take_new() {
+        oled.write_crash_msg(&report);
+        timer::wait_nanos(timer, CRASH_SHOWN_NANOS);
+        oled.clear();
+    } else if reset_reason.is_fault() {
+        oled.write_reset_msg(reset_reason);
+        timer::wait_nanos(timer, CRASH_SHOWN_NANOS);
+        oled.clear();
+    }
 
     // Set up the USB driver
-    #[cfg(any(feature = "serial", feature = "left"))]
+    #[cfg(any(feature = "serial", feature = "hiddev"))]
     let usb_bus = usb_device::bus::UsbBusAllocator::new(hal::usb::UsbBus::new(
         pac.USBCTRL_REGS,
         pac.USBCTRL_DPRAM,
@@ -177,107 +162,133 @@ fn setup_kbd() -> ! {
         &mut pac.RESETS,
     ));
 
-    let mut side_check_pin = pins.gpio28.into_pull_up_input();
+    // Safety: Only the pins every board shares are taken from `pins`
+    let mut board_pins = unsafe { board::PinBank::new() };
+    let mut side_check_pin = board_pins.button(board::SIDE_PIN);
 
     let power_led_pin = pins.power_led.into_push_pull_output();
     let pl = PowerLed::new(power_led_pin);
-    let is_left = side_check_pin.is_high().unwrap();
+    let Some(is_left) = read_side_pin(&mut side_check_pin, timer) else {
+        // A floating or bouncing pin, picking a side at random gives a half with the wrong
+        // pinout, show it and try again from the top
+        oled.write_bad_side_msg();
+        timer::wait_nanos(timer, 2_000_000_000);
+        pac::SCB::sys_reset();
+    };
+    // Whichever half is plugged into the host runs the keymap, the serial console
+    // is for debugging with both halves plugged in, there the left side stays master
+    #[cfg(feature = "hiddev")]
+    let is_master = unsafe {
+        runtime::shared::usb::init_usb_hiddev(usb_bus);
+        runtime::shared::usb::wait_for_host(timer, HOST_WAIT_MICROS)
+    };
+    #[cfg(not(feature = "hiddev"))]
+    let is_master = is_left;
+    let dma = pac.DMA.split(&mut pac.RESETS);
+    #[cfg(feature = "pio-scan")]
+    let scan_hardware = {
+        use rp2040_hal::pio::PIOExt;
+        let (pio, sm, _, _, _) = pac.PIO1.split(&mut pac.RESETS);
+        keyboard::pio_scanner::PioScanHardware {
+            pio,
+            sm,
+            tx_dma: dma.ch0,
+            rx_dma: dma.ch1,
+            system_freq: clocks.system_clock.freq(),
+        }
+    };
     let mut mc = Multicore::new(&mut pac.PSM, &mut pac.PPB, &mut sio.fifo);
     if is_left {
-        #[cfg(feature = "left")]
-        {
-            // Left side flips tx/rx, check qmk for proton-c in kyria for reference
-            let uart = keyboard::split_serial::UartLeft::new(
-                pins.gpio1,
-                uart_baud,
-                clocks.system_clock.freq(),
-                pac.PIO0,
-                &mut pac.RESETS,
-            );
-            let left = crate::keyboard::left::LeftButtons::new(
-                (
-                    pins.gpio29.into_pull_up_input(),
-                    pins.gpio27.into_pull_up_input(),
-                    pins.gpio6.into_pull_up_input(),
-                    pins.gpio7.into_pull_up_input(),
-                    pins.gpio8.into_pull_up_input(),
-                ),
-                (
-                    Some(pins.gpio9.into_pull_up_input()),
-                    Some(pins.gpio26.into_pull_up_input()),
-                    Some(pins.gpio22.into_pull_up_input()),
-                    Some(pins.gpio20.into_pull_up_input()),
-                    Some(pins.gpio23.into_pull_up_input()),
-                    Some(pins.gpio21.into_pull_up_input()),
-                ),
-            );
-            runtime::left::run_left(
-                &mut mc,
-                usb_bus,
-                oled,
-                uart,
-                left,
-                pl,
-                timer,
-                &clocks.system_clock,
-            );
-        }
-        #[cfg(not(feature = "left"))]
-        {
-            // Hard error, needs new firmware loaded
-            oled.write_bad_boot_msg();
-            rp2040_hal::rom_data::reset_to_usb_boot(0, 0);
-        }
-    } else {
-        #[cfg(feature = "right")]
-        {
-            let uart = keyboard::split_serial::UartRight::new(
-                pins.gpio1.reconfigure(),
-                uart_baud,
-                clocks.system_clock.freq(),
-                pac.PIO0,
-                &mut pac.RESETS,
-            );
-            let right = crate::keyboard::right::RightButtons::new(
-                (
-                    pins.gpio29.into_pull_up_input(),
-                    pins.gpio4.into_pull_up_input(),
-                    pins.gpio20.into_pull_up_input(),
-                    pins.gpio23.into_pull_up_input(),
-                    pins.gpio21.into_pull_up_input(),
-                ),
-                (
-                    pins.gpio22.into_pull_up_input(),
-                    pins.gpio5.into_pull_up_input(),
-                    pins.gpio6.into_pull_up_input(),
-                    pins.gpio7.into_pull_up_input(),
-                    pins.gpio8.into_pull_up_input(),
-                    pins.gpio9.into_pull_up_input(),
-                ),
-                crate::keyboard::right::RotaryEncoder::new(
-                    pins.gpio26.into_pull_up_input(),
-                    pins.gpio27.into_pull_up_input(),
-                ),
-            );
-            runtime::right::run_right(
+        let uart = keyboard::split_serial::SplitLink::new(
+            pins.gpio1,
+            uart_baud,
+            clocks.system_clock.freq(),
+            pac.PIO0,
+            dma.ch2,
+            &mut pac.RESETS,
+        );
+        let left = crate::keyboard::left::LeftButtons::new(
+            &mut board_pins,
+            #[cfg(feature = "pio-scan")]
+            scan_hardware,
+        );
+        if is_master {
+            runtime::master::run_master(
                 &mut mc,
                 #[cfg(feature = "serial")]
                 usb_bus,
                 oled,
                 uart,
-                right,
+                left,
                 pl,
                 timer,
-                &clocks.system_clock,
+                watchdog,
             );
         }
-        #[cfg(not(feature = "right"))]
-        {
-            // Hard error, needs new firmware loaded
-            oled.write_bad_boot_msg();
-            rp2040_hal::rom_data::reset_to_usb_boot(0, 0);
+        runtime::slave::run_slave(
+            &mut mc,
+            #[cfg(feature = "serial")]
+            usb_bus,
+            oled,
+            uart,
+            left,
+            pl,
+            timer,
+            watchdog,
+        );
+    }
+    let uart = keyboard::split_serial::SplitLink::new(
+        pins.gpio1,
+        uart_baud,
+        clocks.system_clock.freq(),
+        pac.PIO0,
+        dma.ch2,
+        &mut pac.RESETS,
+    );
+    let right = crate::keyboard::right::RightButtons::new(
+        &mut board_pins,
+        #[cfg(feature = "pio-scan")]
+        scan_hardware,
+    );
+    if is_master {
+        runtime::master::run_master(
+            &mut mc,
+            #[cfg(feature = "serial")]
+            usb_bus,
+            oled,
+            uart,
+            right,
+            pl,
+            timer,
+            watchdog,
+        );
+    }
+    runtime::slave::run_slave(
+        &mut mc,
+        #[cfg(feature = "serial")]
+        usb_bus,
+        oled,
+        uart,
+        right,
+        pl,
+        timer,
+        watchdog,
+    );
+}
+
+/// The left half leaves the board's side pin high, the right half pulls it to ground.
+/// Returns `None` if the samples disagree.
+fn read_side_pin(pin: &mut impl InputPin, timer: hal::Timer) -> Option<bool> {
+    // Let the pull-up settle before trusting anything
+    timer::wait_nanos(timer, SIDE_PIN_SAMPLE_SPACING_NANOS);
+    let first = pin.is_high().ok()?;
+    for _ in 1..SIDE_PIN_SAMPLES {
+        timer::wait_nanos(timer, SIDE_PIN_SAMPLE_SPACING_NANOS);
+        if pin.is_high().ok()? != first {
+            return None;
         }
     }
+    Some(first)
 }
 
 fn setup_oled(
@@ -297,7 +308,7 @@ fn setup_oled(
     );
 
     let interface = ssd1306::I2CDisplayInterface::new(i2c);
-    let mut display = Ssd1306::new(interface, DisplaySize128x32, DisplayRotation::Rotate90)
+    let mut display = Ssd1306::new(interface, board::OLED_SIZE, board::OLED_ROTATION)
         .into_buffered_graphics_mode();
     display.init().unwrap();
     let _ = display.clear(BinaryColor::Off);
@@ -307,8 +318,6 @@ fn setup_oled(
 
 #[panic_handler]
 #[inline(never)]
-fn halt(_info: &core::panic::PanicInfo) -> ! {
-    loop {
-        core::sync::atomic::compiler_fence(core::sync::atomic::Ordering::SeqCst);
-    }
+fn on_panic(info: &core::panic::PanicInfo) -> ! {
+    crash::record_and_reset(info)
 }
@@ -1,6 +1,4 @@
-#[cfg(feature = "left")]
-pub mod left;
-mod locks;
-#[cfg(feature = "right")]
-pub mod right;
+pub(crate) mod locks;
+pub mod master;
 pub(crate) mod shared;
+pub mod slave;
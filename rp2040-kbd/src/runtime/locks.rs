@@ -1,2 +1,5 @@
 #[cfg(feature = "serial")]
 pub type UsbLock = rp2040_hal::sio::Spinlock15;
+
+/// Held by the core writing a crash report, until the reset
+pub type CrashLock = rp2040_hal::sio::Spinlock14;
@@ -0,0 +1,583 @@
+use crate::clock::{self, IdleClock};
+use crate::keyboard::oled::master::MasterOledDrawer;
+use crate::keyboard::oled::OledHandle;
+use crate::keyboard::power_led::PowerLed;
+use crate::keyboard::split_serial::message_receiver::MessageReceiver;
+use crate::keyboard::split_serial::message_serializer::POLL_TIMEOUT_MICROS;
+use crate::keyboard::split_serial::SplitLink;
+use crate::keyboard::{wake, LocalMatrix, MatrixSink, Side};
+use crate::keymap::{KeyboardReportState, KeyboardState};
+use crate::layer::{layer_to_string, KeymapLayer};
+#[cfg(feature = "serial")]
+use crate::runtime::shared::console::{execute_shared, Console, ProfileList};
+#[cfg(feature = "serial")]
+use crate::runtime::shared::cores_master::push_report_stats;
+use crate::runtime::shared::cores_master::{
+    new_shared_queue, pop_message, push_delivered, push_link_stats, push_link_up,
+    push_loop_to_admin, push_rx_change, push_touch_left_to_admin, push_touch_right_to_admin,
+    Consumer, KeycoreToAdminMessage, Producer,
+};
+use crate::runtime::shared::loop_counter::LoopCounter;
+#[cfg(feature = "serial")]
+use crate::runtime::shared::sleep::WakeLatency;
+use crate::runtime::shared::sleep::{
+    admin_awake, publish_wake_latency, SleepCountdown, UsbSuspendWatch,
+    PARKED_SCAN_INTERVAL_MICROS, PARK_AFTER_CHANGE_MICROS,
+};
+#[cfg(feature = "serial")]
+use crate::runtime::shared::usb::init_usb;
+use crate::watchdog::{key_core_alive, Supervisor};
+#[cfg(feature = "serial")]
+use core::fmt::Write;
+#[cfg(feature = "hiddev")]
+use liatris::pac::interrupt;
+use rp2040_hal::multicore::{Multicore, Stack};
+use rp2040_hal::rom_data::reset_to_usb_boot;
+use rp2040_hal::{Timer, Watchdog};
+#[cfg(feature = "serial")]
+use rp2040_kbd_lib::cli::Command;
+use rp2040_kbd_lib::histogram::Histogram;
+use rp2040_kbd_lib::matrix::MatrixUpdate;
+#[cfg(feature = "hiddev")]
+use rp2040_kbd_lib::report::DeliveryTimer;
+use rp2040_kbd_lib::split::{
+    KeyBitmap, LinkMonitor, MasterState, MasterToSlave, SlaveToMaster, LINK_TIMEOUT_MICROS,
+};
+
+static CORE_1_STACK: Stack<{ 1024 * 8 }> = Stack::new();
+
+const SIDES: [Side; 2] = [Side::Left, Side::Right];
+
+/// A few of the host's polls, for the release to go out before the reset
+#[cfg(feature = "hiddev")]
+const RELEASE_TIMEOUT_MICROS: u64 = 20_000;
+
+/// Runs the keymap, this half's own keys go straight into it and the slave's come
+/// over the link
+#[inline(never)]
+pub fn run_master<'a, B: LocalMatrix + Send + 'static>(
+    mc: &'a mut Multicore<'a>,
+    #[cfg(feature = "serial")] usb_bus: usb_device::bus::UsbBusAllocator<rp2040_hal::usb::UsbBus>,
+    mut oled_handle: OledHandle,
+    uart_driver: SplitLink,
+    buttons: B,
+    power_led_pin: PowerLed,
+    timer: Timer,
+    watchdog: Watchdog,
+) -> ! {
+    #[cfg(feature = "serial")]
+    unsafe {
+        init_usb(usb_bus);
+    }
+    let receiver = MessageReceiver::new(uart_driver);
+    let (producer, consumer) = new_shared_queue();
+    if let Err(_e) = mc.cores()[1].spawn(CORE_1_STACK.take().unwrap(), move || {
+        run_key_processsing_core(receiver, buttons, timer, producer)
+    }) {
+        oled_handle.clear();
+        oled_handle.write(0, "ERROR");
+        oled_handle.write(9, "SPAWN");
+        oled_handle.write(18, "CORE1");
+        oled_handle.write(27, "FAIL");
+        oled_handle.write(36, "BOOT");
+        reset_to_usb_boot(0, 0);
+    }
+    run_admin_core(
+        B::SIDE,
+        oled_handle,
+        consumer,
+        timer,
+        power_led_pin,
+        watchdog,
+    )
+}
+
+#[expect(clippy::needless_pass_by_value, clippy::too_many_lines)]
+pub fn run_admin_core(
+    side: Side,
+    oled_handle: OledHandle,
+    consumer: Consumer,
+    timer: Timer,
+    mut power_led_pin: PowerLed,
+    watchdog: Watchdog,
+) -> ! {
+    let mut oled = MasterOledDrawer::new(oled_handle, side);
+    #[cfg(feature = "serial")]
+    let mut console = Console::new();
+    #[cfg(feature = "serial")]
+    let mut output_all = false;
+    #[cfg(feature = "serial")]
+    let mut has_dumped = false;
+    #[cfg(feature = "serial")]
+    let mut scan_loop = rp2040_kbd_lib::histogram::LatencySummary::default();
+    #[cfg(feature = "serial")]
+    let mut total_rx: u32 = 0;
+    #[cfg(feature = "serial")]
+    let mut report_stats = rp2040_kbd_lib::report::ReportStats::default();
+    #[cfg(feature = "serial")]
+    let mut link_stats = rp2040_kbd_lib::matrix::FrameStats::default();
+    #[cfg(feature = "serial")]
+    let mut link_up = false;
+    let mut sleep = SleepCountdown::new();
+    let mut suspend = UsbSuspendWatch::new();
+    let mut rx: u16 = 0;
+    // Detection to delivery, since boot
+    let mut left_latency = Histogram::new();
+    let mut right_latency = Histogram::new();
+    let mut last_avail = 0;
+    #[cfg(feature = "serial")]
+    let mut layer = KeymapLayer::DvorakSe;
+    oled.update_layer(layer_to_string(KeymapLayer::DvorakSe));
+    let mut clock_profile = clock::running();
+    oled.set_clock(clock::system_freq());
+    let mut supervisor = Supervisor::start(watchdog, timer.get_counter());
+    loop {
+        let avail = consumer.available();
+        let now = timer.get_counter();
+        supervisor.check(now, || release_all_keys(timer));
+        match pop_message(&consumer) {
+            Some(KeycoreToAdminMessage::TouchLeft | KeycoreToAdminMessage::TouchRight) => {
+                sleep.touch(now);
+                if !suspend.is_suspended() {
+                    power_led_pin.turn_on();
+                    oled.show();
+                }
+            }
+            Some(KeycoreToAdminMessage::Delivered(Side::Left, micros)) => {
+                left_latency.record(micros);
+                oled.update_left_counter(left_latency.summary());
+            }
+            Some(KeycoreToAdminMessage::Delivered(Side::Right, micros)) => {
+                right_latency.record(micros);
+                oled.update_right_counter(right_latency.summary());
+            }
+            Some(KeycoreToAdminMessage::Loop(scan)) if sleep.is_awake() => {
+                #[cfg(feature = "serial")]
+                {
+                    scan_loop = scan;
+                }
+                oled.update_scan_loop(scan);
+            }
+            Some(KeycoreToAdminMessage::LayerChange(default)) => {
+                #[cfg(feature = "serial")]
+                {
+                    layer = default;
+                }
+                let dfl_out = layer_to_string(default);
+                oled.update_layer(dfl_out);
+            }
+            Some(KeycoreToAdminMessage::Rx(incr)) => {
+                #[cfg(feature = "serial")]
+                {
+                    total_rx = total_rx.wrapping_add(u32::from(incr));
+                }
+                rx += incr;
+                if rx > 9999 {
+                    rx = incr;
+                }
+                sleep.touch(now);
+                oled.update_rx(rx);
+            }
+            Some(KeycoreToAdminMessage::Link(stats)) => {
+                #[cfg(feature = "serial")]
+                {
+                    link_stats = stats;
+                }
+                oled.update_link_errors(stats.corrupt.wrapping_add(stats.missed));
+            }
+            Some(KeycoreToAdminMessage::LinkUp(up)) => {
+                #[cfg(feature = "serial")]
+                {
+                    link_up = up;
+                }
+                oled.update_link(up);
+            }
+            #[cfg(feature = "serial")]
+            Some(KeycoreToAdminMessage::Reports(stats)) => {
+                report_stats = stats;
+            }
+            Some(KeycoreToAdminMessage::Reboot) => {
+                oled.render_boot_msg();
+                reset_to_usb_boot(0, 0);
+            }
+            _ => {}
+        }
+        if avail != last_avail {
+            oled.update_queue(avail);
+            last_avail = avail;
+        }
+        if clock::running() != clock_profile {
+            clock_profile = clock::running();
+            oled.set_clock(clock::system_freq());
+        }
+        match suspend.check() {
+            Some(true) => {
+                oled.hide();
+                power_led_pin.turn_off();
+                sleep.set_sleeping();
+            }
+            Some(false) => {
+                sleep.touch(now);
+                oled.show();
+                power_led_pin.turn_on();
+            }
+            None => {}
+        }
+        if sleep.should_sleep(now) {
+            oled.hide();
+            power_led_pin.turn_off();
+            sleep.set_sleeping();
+        }
+        oled.render();
+        #[cfg(feature = "serial")]
+        {
+            console.poll(|cmd, usb| {
+                match execute_shared(cmd, usb, &mut power_led_pin, &mut sleep) {
+                    Some(Command::Status) => usb.respond(format_args!(
+                        "side={} role=master uptime={}s clock={}MHz awake={} suspended={} link={} queue={avail} reset={:?}\r\n",
+                        side.name(),
+                        now.duration_since_epoch().to_secs(),
+                        clock::system_freq().to_MHz(),
+                        sleep.is_awake(),
+                        suspend.is_suspended(),
+                        if link_up { "ok" } else { "lost" },
+                        crate::watchdog::boot_reason(),
+                    )),
+                    Some(Command::Layer) => usb.respond(format_args!(
+                        "layer={layer:?} ({})\r\n",
+                        layer_to_string(layer)
+                    )),
+                    Some(Command::Stats) => usb.respond(format_args!(
+                        "min/p50/p99/max scan={scan_loop} left={} right={}\r\nwake={} rx={total_rx} link corrupt={} missed={} reports coalesced={} dropped={}\r\n",
+                        left_latency.summary(),
+                        right_latency.summary(),
+                        WakeLatency,
+                        link_stats.corrupt,
+                        link_stats.missed,
+                        report_stats.coalesced,
+                        report_stats.dropped,
+                    )),
+                    Some(Command::ClockSet(mhz)) => match clock::find_mhz(mhz) {
+                        Some(profile) => {
+                            clock::request(profile);
+                            usb.respond(format_args!(
+                                "clock={mhz}MHz requested, both halves switch on the next poll\r\n"
+                            ));
+                        }
+                        None => usb.respond(format_args!(
+                            "error: no {mhz}MHz profile, try one of {ProfileList}\r\n"
+                        )),
+                    },
+                    _ => {}
+                }
+                output_all = *usb.output;
+            });
+            if output_all && !has_dumped {
+                let _ = crate::runtime::shared::usb::acquire_usb()
+                    .write_fmt(format_args!("{} side running as master\r\n", side.name()));
+                has_dumped = true;
+            }
+        }
+    }
+}
+
+/// Before a reset for a stalled key core, whatever the host has pressed would otherwise
+/// repeat until it notices the disconnect
+#[cfg(feature = "hiddev")]
+fn release_all_keys(timer: Timer) {
+    // Safety: The device is only taken in critical sections, the key core's usb interrupt
+    // can still run and waits for this one
+    let _ = unsafe { crate::runtime::shared::usb::release_all_keys(timer, RELEASE_TIMEOUT_MICROS) };
+}
+
+/// Only a hid device has keys to release
+#[cfg(not(feature = "hiddev"))]
+fn release_all_keys(_timer: Timer) {}
+
+/// Feeds this half's own keys straight into the keymap
+struct LocalKeys<'a> {
+    side: Side,
+    /// The first change that got through the keymap
+    detected_at: Option<u32>,
+    kbd: &'a mut KeyboardState,
+    report_state: &'a mut KeyboardReportState,
+    producer: &'a Producer,
+}
+
+impl MatrixSink for LocalKeys<'_> {
+    #[inline]
+    fn matrix_update(&mut self, update: MatrixUpdate, detected_at: u32) {
+        if self
+            .kbd
+            .update(self.side, update, self.report_state, self.producer)
+        {
+            self.detected_at.get_or_insert(detected_at);
+        }
+    }
+}
+
+#[expect(clippy::needless_pass_by_value, clippy::too_many_lines)]
+pub fn run_key_processsing_core<B: LocalMatrix>(
+    mut receiver: MessageReceiver,
+    mut buttons: B,
+    timer: Timer,
+    producer: Producer,
+) -> ! {
+    let mut kbd = KeyboardState::new();
+    let mut report_state = KeyboardReportState::new();
+    let mut loop_count: LoopCounter<10_000> = LoopCounter::new();
+    let mut alarm = {
+        let mut timer = timer;
+        timer.alarm_0().unwrap()
+    };
+    unsafe {
+        #[cfg(feature = "hiddev")]
+        liatris::hal::pac::NVIC::unmask(liatris::pac::Interrupt::USBCTRL_IRQ);
+        liatris::hal::pac::NVIC::unmask(liatris::pac::Interrupt::TIMER_IRQ_0);
+        liatris::hal::pac::NVIC::unmask(liatris::pac::Interrupt::IO_IRQ_BANK0);
+    }
+    let mut rx = 0;
+    #[cfg(feature = "serial")]
+    let mut report_stats = report_state.report_stats();
+    let mut link_stats = receiver.stats();
+    let mut link = LinkMonitor::new(LINK_TIMEOUT_MICROS);
+    let mut link_up = false;
+    let mut master_state = MasterState::new(report_state.active_layer().index(), clock::running());
+    // Don't go to sleep before the slave knows about it
+    let mut told_asleep = false;
+    let mut idle_clock = IdleClock::new();
+    let mut last_change = timer.get_counter();
+    let mut woke_at = None;
+    // Left then right, like `SIDES`
+    #[cfg(feature = "hiddev")]
+    let mut delivery = [DeliveryTimer::new(); 2];
+    loop {
+        key_core_alive();
+        let suspended = usb_suspended();
+        // A press still gets picked up right away, and wakes the host if suspended.
+        // So does the slave starting to send, its polls are only waited on for a bit.
+        let parked = if told_asleep
+            && (suspended || !admin_awake())
+            && !receiver.has_unread()
+            && timer
+                .get_counter()
+                .checked_duration_since(last_change)
+                .is_some_and(|dur| dur.to_micros() >= PARK_AFTER_CHANGE_MICROS)
+        {
+            wake::reset();
+            receiver.arm_wake();
+            buttons.park(&mut alarm, PARKED_SCAN_INTERVAL_MICROS, || {
+                receiver.has_unread() || (!usb_suspended() && admin_awake())
+            });
+            receiver.disarm_wake();
+            woke_at = wake::take_woke_at();
+            true
+        } else {
+            false
+        };
+        let loop_timer = timer.get_counter();
+        report_state.tick(loop_timer.ticks());
+        let mut changed_remote = false;
+        let mut remote_detected_at = None;
+        let mut polled = false;
+        // Drain, the slave may have sent several updates if we've been parked
+        while let Some(msg) = receiver.try_read() {
+            if link.heard(loop_timer.ticks()) {
+                // We may have missed anything the slave sent before, or we rebooted
+                master_state.request_snapshot();
+            }
+            match msg {
+                SlaveToMaster::Matrix(update, age) => {
+                    // Slave sent an update
+                    rx += 1;
+                    // The few micros on the wire and waiting to be read aren't counted
+                    let detected_at = timer.get_counter_low().wrapping_sub(u32::from(age));
+                    // Update report state
+                    if kbd.update(B::SIDE.other(), update, &mut report_state, &producer) {
+                        remote_detected_at.get_or_insert(detected_at);
+                    }
+                    changed_remote = true;
+                }
+                SlaveToMaster::Snapshot(keys) => {
+                    if kbd.reconcile(B::SIDE.other(), keys, &mut report_state, &producer) {
+                        changed_remote = true;
+                    }
+                }
+                SlaveToMaster::Poll => polled = true,
+            }
+        }
+        // A poll that came in while parked is answered if it woke us, the slave is still
+        // waiting then. Answering one it gave up on could collide with its next transmission.
+        let poll_waited_on = !parked
+            || woke_at.is_some_and(|at| {
+                u64::from(timer.get_counter_low().wrapping_sub(at)) <= POLL_TIMEOUT_MICROS
+            });
+        if polled && poll_waited_on {
+            master_state.set_layer(report_state.active_layer().index());
+            #[cfg(feature = "hiddev")]
+            master_state.set_host_leds(crate::runtime::shared::usb::host_leds());
+            master_state.set_awake(!suspended && admin_awake());
+            // Asleep both halves stay idle, whatever was asked for meanwhile waits for waking
+            master_state.set_clock(if told_asleep {
+                clock::IDLE_PROFILE
+            } else {
+                clock::requested()
+            });
+            let reply = master_state.next_message();
+            receiver.reply(reply);
+            // The slave switches as soon as it has the frame, and so do we
+            match reply {
+                MasterToSlave::Awake(awake) => {
+                    told_asleep = !awake;
+                    if let Some(profile) = idle_clock.awake(awake) {
+                        buttons.switch_clock(profile, timer);
+                    }
+                }
+                MasterToSlave::Clock(profile) => buttons.switch_clock(profile, timer),
+                _ => {}
+            }
+        }
+        let link_lost = link.check(timer.get_counter().ticks());
+        if link_lost {
+            // The slave falls back to the default clock too, drop whatever was asked for
+            // in case it's what broke the link
+            clock::request(clock::DEFAULT_PROFILE);
+            buttons.switch_clock(clock::DEFAULT_PROFILE, timer);
+            idle_clock.forget();
+            // The frame that brings the link back up starts the count over, a rebooted
+            // slave's jump back to 0 isn't frames missed
+            receiver.resync();
+        }
+        // Without a release from the slave, whatever it had pressed would stay pressed
+        if link_lost
+            && kbd.reconcile(
+                B::SIDE.other(),
+                KeyBitmap::EMPTY,
+                &mut report_state,
+                &producer,
+            )
+        {
+            changed_remote = true;
+        }
+        if link.is_up() != link_up && push_link_up(&producer, link.is_up()) {
+            link_up = link.is_up();
+        }
+        // Check our own gpio and update report state
+        let mut local = LocalKeys {
+            side: B::SIDE,
+            detected_at: None,
+            kbd: &mut kbd,
+            report_state: &mut report_state,
+            producer: &producer,
+        };
+        buttons.scan_matrix(timer, &mut local);
+        buttons.scan_encoder(timer, &mut local);
+        let local_detected_at = local.detected_at;
+        let changed_local = local_detected_at.is_some();
+        let (changed_left, changed_right) = match B::SIDE {
+            Side::Left => (changed_local, changed_remote),
+            Side::Right => (changed_remote, changed_local),
+        };
+        let detected_at = match B::SIDE {
+            Side::Left => [local_detected_at, remote_detected_at],
+            Side::Right => [remote_detected_at, local_detected_at],
+        };
+        if changed_local || changed_remote {
+            last_change = loop_timer;
+        }
+        if changed_local {
+            if let Some(woke_at) = woke_at.take() {
+                publish_wake_latency(woke_at, timer.get_counter_low());
+            }
+        }
+
+        #[cfg(feature = "hiddev")]
+        if suspended && (changed_left || changed_right) {
+            unsafe {
+                crate::runtime::shared::usb::request_remote_wakeup();
+            }
+        }
+
+        #[cfg(feature = "hiddev")]
+        for (delivery, detected_at) in delivery.iter_mut().zip(detected_at) {
+            if let Some(at) = detected_at {
+                delivery.detected(at);
+            }
+        }
+        #[cfg(feature = "hiddev")]
+        if let Some(next_update) = report_state.report() {
+            // Published the next update on queue if present
+            if unsafe { crate::runtime::shared::usb::try_push_report(&next_update) } {
+                report_state.accept();
+                delivery.iter_mut().for_each(DeliveryTimer::accepted);
+            }
+        } else {
+            // Whatever was detected didn't change the report
+            delivery.iter_mut().for_each(DeliveryTimer::discard_pending);
+        }
+        #[cfg(feature = "hiddev")]
+        if let Some(polled_at) = crate::runtime::shared::usb::take_report_polled_at() {
+            for (side, delivery) in SIDES.into_iter().zip(&mut delivery) {
+                if let Some(micros) = delivery.polled(polled_at) {
+                    push_delivered(&producer, side, micros);
+                }
+            }
+        }
+        // No host to deliver to, the report being built is as far as a key gets
+        #[cfg(not(feature = "hiddev"))]
+        for (side, detected_at) in SIDES.into_iter().zip(detected_at) {
+            if let Some(at) = detected_at {
+                push_delivered(&producer, side, timer.get_counter_low().wrapping_sub(at));
+            }
+        }
+        // Only push on errors, received frames are already counted through `Rx`
+        let stats = receiver.stats();
+        if (stats.corrupt, stats.missed) != (link_stats.corrupt, link_stats.missed)
+            && push_link_stats(&producer, stats)
+        {
+            link_stats = stats;
+        }
+        #[cfg(feature = "serial")]
+        {
+            let stats = report_state.report_stats();
+            if stats != report_stats && push_report_stats(&producer, stats) {
+                report_stats = stats;
+            }
+        }
+        if rx > 0 && push_rx_change(&producer, rx) {
+            rx = 0;
+        }
+        let now = timer.get_counter();
+        if loop_count.record(loop_timer, now) && push_loop_to_admin(&producer, loop_count.value()) {
+            loop_count.reset();
+        }
+        if changed_left {
+            push_touch_left_to_admin(&producer);
+        }
+        if changed_right {
+            push_touch_right_to_admin(&producer);
+        }
+    }
+}
+
+/// Only with `hiddev` does the key core deal with the host suspending the bus
+#[inline]
+fn usb_suspended() -> bool {
+    #[cfg(feature = "hiddev")]
+    {
+        crate::runtime::shared::usb::usb_suspended()
+    }
+    #[cfg(not(feature = "hiddev"))]
+    {
+        false
+    }
+}
+
+/// Todo: Change to 'expect' after [this PR](https://github.com/rust-embedded/cortex-m/pull/557)
+/// Safety: Called from the same core that publishes
+#[interrupt]
+#[allow(clippy::allow_attributes, non_snake_case)]
+#[cfg(feature = "hiddev")]
+unsafe fn USBCTRL_IRQ() {
+    crate::runtime::shared::usb::hiddev_interrupt_poll();
+}
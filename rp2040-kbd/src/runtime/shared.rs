@@ -1,11 +1,12 @@
-#[cfg(feature = "left")]
-pub mod cores_left;
-#[cfg(feature = "right")]
-pub mod cores_right;
+#[cfg(feature = "serial")]
+pub mod chatter;
+#[cfg(feature = "serial")]
+pub mod console;
+pub mod cores_master;
+pub mod cores_slave;
 pub mod loop_counter;
 pub mod sleep;
+pub mod trace;
 
-pub mod press_latency_counter;
-
-#[cfg(any(feature = "left", feature = "serial"))]
+#[cfg(any(feature = "hiddev", feature = "serial"))]
 pub mod usb;
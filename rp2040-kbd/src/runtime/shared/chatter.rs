@@ -0,0 +1,51 @@
+use core::sync::atomic::{AtomicU32, Ordering};
+use rp2040_kbd_lib::debounce::ChatterStats;
+use rp2040_kbd_lib::matrix::{NUM_COLS, NUM_ROWS};
+
+pub const NUM_KEYS: u8 = NUM_ROWS * NUM_COLS;
+
+/// Stands in for no interval seen yet
+const NO_INTERVAL: u32 = u32::MAX;
+
+struct SharedStats {
+    edges: AtomicU32,
+    changes: AtomicU32,
+    min_interval_micros: AtomicU32,
+}
+
+impl SharedStats {
+    const fn new() -> Self {
+        Self {
+            edges: AtomicU32::new(0),
+            changes: AtomicU32::new(0),
+            min_interval_micros: AtomicU32::new(NO_INTERVAL),
+        }
+    }
+}
+
+/// This half's switches by matrix index, written by the key core, read by the console
+static CHATTER: [SharedStats; NUM_KEYS as usize] =
+    [const { SharedStats::new() }; NUM_KEYS as usize];
+
+/// Only the key core publishes, fields are stored one at a time so a read can mix two
+/// updates, good enough for diagnostics
+#[inline]
+pub fn publish(index: u8, stats: ChatterStats) {
+    let shared = &CHATTER[usize::from(index)];
+    shared.edges.store(stats.edges, Ordering::Relaxed);
+    shared.changes.store(stats.changes, Ordering::Relaxed);
+    shared.min_interval_micros.store(
+        stats.min_interval_micros.unwrap_or(NO_INTERVAL),
+        Ordering::Relaxed,
+    );
+}
+
+pub fn read(index: u8) -> ChatterStats {
+    let shared = &CHATTER[usize::from(index)];
+    let min_interval_micros = shared.min_interval_micros.load(Ordering::Relaxed);
+    ChatterStats {
+        edges: shared.edges.load(Ordering::Relaxed),
+        changes: shared.changes.load(Ordering::Relaxed),
+        min_interval_micros: (min_interval_micros != NO_INTERVAL).then_some(min_interval_micros),
+    }
+}
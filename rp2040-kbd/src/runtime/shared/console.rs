@@ -0,0 +1,212 @@
+use crate::clock;
+use crate::crash;
+use crate::keyboard::matrix_ind_to_row_col;
+use crate::keyboard::power_led::PowerLed;
+use crate::runtime::shared::chatter;
+use crate::runtime::shared::sleep::SleepCountdown;
+use crate::runtime::shared::trace;
+use crate::runtime::shared::usb::{acquire_usb, set_usb_suspended, UsbGuard};
+use rp2040_hal::rom_data::reset_to_usb_boot;
+use rp2040_kbd_lib::cli::{
+    parse, write_help, Command, ConfigKey, ConfigValue, LineBuffer, LineError, LINE_CAPACITY,
+};
+
+pub struct Console {
+    line: LineBuffer<LINE_CAPACITY>,
+    /// A kept crash report is pointed out once, on the first line received
+    crash_offered: bool,
+}
+
+impl Console {
+    pub const fn new() -> Self {
+        Self {
+            line: LineBuffer::new(),
+            crash_offered: false,
+        }
+    }
+
+    /// Poll the usb device, every complete line that parses into a command is
+    /// handed to `execute`, anything else gets an error written back.
+    pub fn poll<F: FnMut(Command, &mut UsbGuard)>(&mut self, mut execute: F) -> Option<()> {
+        let mut usb = acquire_usb();
+        let mut buf = [0u8; 64];
+        let count = {
+            let serial = usb.serial.as_mut()?;
+            let dev = usb.dev.as_mut()?;
+            let has_data = dev.inner.poll(&mut [&mut serial.inner]);
+            set_usb_suspended(dev.is_suspended());
+            if !has_data {
+                return Some(());
+            }
+            serial.inner.read(&mut buf).ok()?
+        };
+        for byte in &buf[..count] {
+            let line = self.line.push(*byte);
+            if line.is_some() && !core::mem::replace(&mut self.crash_offered, true) {
+                if let Some(report) = crash::last() {
+                    usb.respond(format_args!(
+                        "note: core {} panicked before a reset, see 'crash'\r\n",
+                        report.core()
+                    ));
+                }
+            }
+            match line {
+                None => {}
+                Some(Err(LineError::Overflow)) => {
+                    usb.respond(format_args!("error: line too long\r\n"));
+                }
+                Some(Err(LineError::InvalidUtf8)) => {
+                    usb.respond(format_args!("error: invalid utf-8\r\n"));
+                }
+                Some(Ok(line)) => match parse(line) {
+                    Ok(Some(cmd)) => execute(cmd, &mut usb),
+                    Ok(None) => {}
+                    Err(e) => usb.respond(format_args!("error: {e}\r\n")),
+                },
+            }
+        }
+        Some(())
+    }
+}
+
+/// Run the commands that behave the same on both sides, hands back the ones that don't
+pub fn execute_shared<'a>(
+    cmd: Command<'a>,
+    usb: &mut UsbGuard,
+    power_led: &mut PowerLed,
+    sleep: &mut SleepCountdown,
+) -> Option<Command<'a>> {
+    match cmd {
+        Command::Help(command) => {
+            let _ = write_help(&mut ResponseWriter(usb), command);
+        }
+        Command::Clock => {
+            usb.respond(format_args!(
+                "SYS={} profiles={}\r\n",
+                clock::system_freq().to_MHz(),
+                ProfileList
+            ));
+        }
+        Command::ConfigList => {
+            for key in ConfigKey::ALL {
+                respond_config(key, usb, power_led, sleep);
+            }
+        }
+        Command::ConfigGet(key) => {
+            respond_config(key, usb, power_led, sleep);
+        }
+        Command::ConfigSet(key, value) => {
+            match (key, value) {
+                (ConfigKey::Output, ConfigValue::Bool(on)) => *usb.output = on,
+                (ConfigKey::Led, ConfigValue::Bool(true)) => power_led.turn_on(),
+                (ConfigKey::Led, ConfigValue::Bool(false)) => power_led.turn_off(),
+                (ConfigKey::Sleep, ConfigValue::Seconds(secs)) => {
+                    sleep.set_timeout_seconds(u64::from(secs));
+                }
+                // The parser produces values that match the key
+                _ => {}
+            }
+            respond_config(key, usb, power_led, sleep);
+        }
+        Command::Chatter => respond_chatter(usb, false),
+        Command::ChatterAll => respond_chatter(usb, true),
+        Command::Trace => respond_trace(usb),
+        Command::Crash => match crash::last() {
+            Some(report) => usb.respond(format_args!("{report}\r\n")),
+            None => usb.respond(format_args!("no crash\r\n")),
+        },
+        Command::CrashClear => {
+            crash::clear();
+            usb.respond(format_args!("crash cleared\r\n"));
+        }
+        Command::Reboot => {
+            usb.respond(format_args!("REBOOT\r\n"));
+            liatris::pac::SCB::sys_reset();
+        }
+        Command::UsbBoot => {
+            usb.respond(format_args!("BOOT\r\n"));
+            reset_to_usb_boot(0, 0);
+        }
+        Command::Status | Command::Layer | Command::Stats | Command::ClockSet(_) => {
+            return Some(cmd)
+        }
+    }
+    None
+}
+
+fn respond_config(
+    key: ConfigKey,
+    usb: &mut UsbGuard,
+    power_led: &PowerLed,
+    sleep: &SleepCountdown,
+) {
+    let value = match key {
+        ConfigKey::Output => ConfigValue::Bool(*usb.output),
+        ConfigKey::Led => ConfigValue::Bool(power_led.is_on()),
+        ConfigKey::Sleep => {
+            ConfigValue::Seconds(u32::try_from(sleep.timeout_seconds()).unwrap_or(u32::MAX))
+        }
+    };
+    usb.respond(format_args!("{}={value}\r\n", key.name()));
+}
+
+/// Suspicious switches, or every one the debouncer had to reject something from
+fn respond_chatter(usb: &mut UsbGuard, all: bool) {
+    let mut listed = 0;
+    for index in 0..chatter::NUM_KEYS {
+        let stats = chatter::read(index);
+        let bounced = all && stats.rejected() > 0;
+        if !bounced && !stats.is_suspicious() {
+            continue;
+        }
+        let (row, col) = matrix_ind_to_row_col(index);
+        usb.respond(format_args!(
+            "R{row}, C{col}: rejected={} changes={} min={}us{}\r\n",
+            stats.rejected(),
+            stats.changes,
+            stats.min_interval_micros.unwrap_or(0),
+            if stats.is_suspicious() {
+                " SUSPICIOUS"
+            } else {
+                ""
+            },
+        ));
+        listed += 1;
+    }
+    if listed == 0 {
+        usb.respond(format_args!("no chatter\r\n"));
+    }
+}
+
+/// One line per event, `seq micros event`, a gap in `seq` is an event lost while dumping
+fn respond_trace(usb: &mut UsbGuard) {
+    let recorded = trace::recorded();
+    for entry in trace::entries() {
+        usb.respond(format_args!("{entry}\r\n"));
+    }
+    usb.respond(format_args!("{recorded} recorded\r\n"));
+}
+
+/// Every profile's Mhz, for picking one with `clock <mhz>`
+pub struct ProfileList;
+
+impl core::fmt::Display for ProfileList {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        for (i, profile) in clock::PROFILES.iter().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            write!(f, "{}", profile.freq.to_MHz())?;
+        }
+        Ok(())
+    }
+}
+
+struct ResponseWriter<'a, 'b>(&'a mut UsbGuard<'b>);
+
+impl core::fmt::Write for ResponseWriter<'_, '_> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.0.respond(format_args!("{s}"));
+        Ok(())
+    }
+}
@@ -0,0 +1,129 @@
+use crate::keyboard::Side;
+use crate::layer::KeymapLayer;
+use crate::runtime::shared::trace;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use rp2040_kbd_lib::histogram::LatencySummary;
+use rp2040_kbd_lib::matrix::FrameStats;
+use rp2040_kbd_lib::queue::{
+    new_atomic_producer_consumer, AtomicQueueConsumer, AtomicQueueProducer,
+};
+#[cfg(feature = "serial")]
+use rp2040_kbd_lib::report::ReportStats;
+use rp2040_kbd_lib::trace::TraceEvent;
+
+#[derive(Debug, Copy, Clone)]
+pub enum KeycoreToAdminMessage {
+    // Notify on any user action
+    TouchLeft,
+    TouchRight,
+    // Micros from a key's detection to the host picking up its report, or with no host
+    // to deliver to, to the report being built
+    Delivered(Side, u32),
+    // Scan loop latencies over the last window of loops
+    Loop(LatencySummary),
+    // Output which layer is active
+    LayerChange(KeymapLayer),
+    // Output bytes received over UART
+    Rx(u16),
+    // Frames received from the slave, and the ones that were lost
+    Link(FrameStats),
+    // The slave started or stopped talking
+    LinkUp(bool),
+    // Reports that were coalesced or dropped on the way to the host
+    #[cfg(feature = "serial")]
+    Reports(ReportStats),
+    // Write a boot message then trigger usb-boot
+    Reboot,
+}
+const QUEUE_CAPACITY: usize = 8;
+
+pub type Producer = AtomicQueueProducer<'static, KeycoreToAdminMessage, QUEUE_CAPACITY>;
+
+pub type Consumer = AtomicQueueConsumer<'static, KeycoreToAdminMessage, QUEUE_CAPACITY>;
+static mut ATOMIC_QUEUE_MEM_AREA: [KeycoreToAdminMessage; QUEUE_CAPACITY] =
+    [KeycoreToAdminMessage::Reboot; QUEUE_CAPACITY];
+static mut ATOMIC_QUEUE_HEAD: AtomicUsize = AtomicUsize::new(0);
+static mut ATOMIC_QUEUE_TAIL: AtomicUsize = AtomicUsize::new(0);
+pub fn new_shared_queue() -> (Producer, Consumer) {
+    #[expect(static_mut_refs)]
+    unsafe {
+        new_atomic_producer_consumer(
+            &mut ATOMIC_QUEUE_MEM_AREA,
+            &mut ATOMIC_QUEUE_HEAD,
+            &mut ATOMIC_QUEUE_TAIL,
+        )
+    }
+}
+
+/// Set from the first push that didn't fit until one does, most pushes are retried every loop
+static QUEUE_FULL: AtomicBool = AtomicBool::new(false);
+
+/// A full queue is traced once, the caller decides whether to try again
+#[inline]
+fn push(atomic_queue_producer: &Producer, msg: KeycoreToAdminMessage) -> bool {
+    let pushed = atomic_queue_producer.push_back(msg);
+    if pushed {
+        QUEUE_FULL.store(false, Ordering::Relaxed);
+    } else if !QUEUE_FULL.load(Ordering::Relaxed) {
+        QUEUE_FULL.store(true, Ordering::Relaxed);
+        trace::record(TraceEvent::QueueFull);
+    }
+    pushed
+}
+
+pub fn push_touch_left_to_admin(atomic_queue_producer: &Producer) -> bool {
+    push(atomic_queue_producer, KeycoreToAdminMessage::TouchLeft)
+}
+
+pub fn push_touch_right_to_admin(atomic_queue_producer: &Producer) -> bool {
+    push(atomic_queue_producer, KeycoreToAdminMessage::TouchRight)
+}
+
+pub fn push_delivered(atomic_queue_producer: &Producer, side: Side, micros: u32) -> bool {
+    push(
+        atomic_queue_producer,
+        KeycoreToAdminMessage::Delivered(side, micros),
+    )
+}
+
+pub fn push_loop_to_admin(atomic_queue_producer: &Producer, scan: LatencySummary) -> bool {
+    push(atomic_queue_producer, KeycoreToAdminMessage::Loop(scan))
+}
+
+pub fn push_layer_change(atomic_queue_producer: &Producer, new_layer: KeymapLayer) -> bool {
+    trace::record(TraceEvent::Layer(new_layer.index()));
+    push(
+        atomic_queue_producer,
+        KeycoreToAdminMessage::LayerChange(new_layer),
+    )
+}
+
+pub fn push_rx_change(atomic_queue_producer: &Producer, received: u16) -> bool {
+    push(atomic_queue_producer, KeycoreToAdminMessage::Rx(received))
+}
+
+pub fn push_link_stats(atomic_queue_producer: &Producer, stats: FrameStats) -> bool {
+    push(atomic_queue_producer, KeycoreToAdminMessage::Link(stats))
+}
+
+pub fn push_link_up(atomic_queue_producer: &Producer, up: bool) -> bool {
+    push(atomic_queue_producer, KeycoreToAdminMessage::LinkUp(up))
+}
+
+#[cfg(feature = "serial")]
+pub fn push_report_stats(atomic_queue_producer: &Producer, stats: ReportStats) -> bool {
+    push(atomic_queue_producer, KeycoreToAdminMessage::Reports(stats))
+}
+
+#[inline(never)]
+pub fn push_reboot_and_halt(atomic_queue_producer: &Producer) -> ! {
+    while !atomic_queue_producer.push_back(KeycoreToAdminMessage::Reboot) {}
+    // Not a panic, that would reset the chip before the admin core gets to the bootloader
+    loop {
+        core::sync::atomic::compiler_fence(Ordering::SeqCst);
+    }
+}
+
+pub fn pop_message(atomic_queue_consumer: &Consumer) -> Option<KeycoreToAdminMessage> {
+    atomic_queue_consumer.pop_front()
+}
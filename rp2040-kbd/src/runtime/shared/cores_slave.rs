@@ -0,0 +1,92 @@
+use crate::runtime::shared::trace;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use rp2040_kbd_lib::histogram::LatencySummary;
+use rp2040_kbd_lib::queue::{
+    new_atomic_producer_consumer, AtomicQueueConsumer, AtomicQueueProducer,
+};
+use rp2040_kbd_lib::split::MasterToSlave;
+use rp2040_kbd_lib::trace::TraceEvent;
+
+#[derive(Debug, Copy, Clone)]
+pub enum KeycoreToAdminMessage {
+    /// Scan loop latencies over the last window of loops, and every poll's round trip so far
+    Loop {
+        scan: LatencySummary,
+        link: LatencySummary,
+    },
+    /// Changes sent, and the longest one of them took from detection to the wire in micros
+    Touch {
+        tx_bytes: u16,
+        forwarded: Option<u32>,
+    },
+    /// Something changed on the master side
+    Master(MasterToSlave),
+    Reboot,
+}
+
+const QUEUE_CAPACITY: usize = 8;
+
+pub type Producer = AtomicQueueProducer<'static, KeycoreToAdminMessage, QUEUE_CAPACITY>;
+
+pub type Consumer = AtomicQueueConsumer<'static, KeycoreToAdminMessage, QUEUE_CAPACITY>;
+static mut ATOMIC_QUEUE_MEM_AREA: [KeycoreToAdminMessage; QUEUE_CAPACITY] =
+    [KeycoreToAdminMessage::Reboot; QUEUE_CAPACITY];
+static mut ATOMIC_QUEUE_HEAD: AtomicUsize = AtomicUsize::new(0);
+static mut ATOMIC_QUEUE_TAIL: AtomicUsize = AtomicUsize::new(0);
+pub fn new_shared_queue() -> (Producer, Consumer) {
+    #[expect(static_mut_refs)]
+    unsafe {
+        new_atomic_producer_consumer(
+            &mut ATOMIC_QUEUE_MEM_AREA,
+            &mut ATOMIC_QUEUE_HEAD,
+            &mut ATOMIC_QUEUE_TAIL,
+        )
+    }
+}
+
+/// Set from the first push that didn't fit until one does, most pushes are retried every loop
+static QUEUE_FULL: AtomicBool = AtomicBool::new(false);
+
+/// A full queue is traced once, the caller decides whether to try again
+#[inline]
+fn push(producer: &Producer, msg: KeycoreToAdminMessage) -> bool {
+    let pushed = producer.push_back(msg);
+    if pushed {
+        QUEUE_FULL.store(false, Ordering::Relaxed);
+    } else if !QUEUE_FULL.load(Ordering::Relaxed) {
+        QUEUE_FULL.store(true, Ordering::Relaxed);
+        trace::record(TraceEvent::QueueFull);
+    }
+    pushed
+}
+
+pub fn push_loop_to_admin(producer: &Producer, scan: LatencySummary, link: LatencySummary) -> bool {
+    push(producer, KeycoreToAdminMessage::Loop { scan, link })
+}
+
+pub fn try_push_touch(producer: &Producer, transmitted: u16, forwarded: Option<u32>) -> bool {
+    push(
+        producer,
+        KeycoreToAdminMessage::Touch {
+            tx_bytes: transmitted,
+            forwarded,
+        },
+    )
+}
+
+pub fn push_master_change(producer: &Producer, msg: MasterToSlave) -> bool {
+    push(producer, KeycoreToAdminMessage::Master(msg))
+}
+
+#[inline(never)]
+pub fn push_reboot_and_halt(producer: &Producer) -> ! {
+    while !producer.push_back(KeycoreToAdminMessage::Reboot) {}
+    // Not a panic, that would reset the chip before the admin core gets to the bootloader
+    loop {
+        core::sync::atomic::compiler_fence(Ordering::SeqCst);
+    }
+}
+
+pub fn pop_message(consumer: &Consumer) -> Option<KeycoreToAdminMessage> {
+    consumer.pop_front()
+}
@@ -1,58 +1,41 @@
-use rp2040_hal::fugit::MicrosDuration;
-use rp2040_hal::rom_data::float_funcs::{fdiv, uint64_to_float, uint_to_float};
+use rp2040_hal::fugit::MicrosDurationU64;
 use rp2040_hal::timer::Instant;
+use rp2040_kbd_lib::histogram::{Histogram, LatencySummary};
 
-#[derive(Debug, Copy, Clone)]
-pub struct LoopCount {
-    pub duration: MicrosDuration<u64>,
-    pub count: u32,
-}
-
-impl LoopCount {
-    pub fn as_micros_fraction(&self) -> f32 {
-        let count = uint_to_float(self.count);
-        let micros = uint64_to_float(self.duration.to_micros());
-        let res = fdiv(micros, count);
-        if res <= 999.9 {
-            res
-        } else {
-            999.9
-        }
-    }
+/// Saturates at `u32::MAX`, a bit over 71 minutes
+#[inline]
+pub fn micros(duration: MicrosDurationU64) -> u32 {
+    u32::try_from(duration.to_micros()).unwrap_or(u32::MAX)
 }
 
+/// Scan loop durations over a window of `N` loops, so a slow stretch doesn't get buried
+/// under everything before it
 pub struct LoopCounter<const N: u32> {
-    start: Instant,
-    count: u32,
+    histogram: Histogram,
 }
 
 impl<const N: u32> LoopCounter<N> {
-    pub const fn new(instant: Instant) -> Self {
+    pub const fn new() -> Self {
         Self {
-            start: instant,
-            count: 0,
+            histogram: Histogram::new(),
         }
     }
 
+    /// Returns true once the window is full
     #[inline]
-    pub fn increment(&mut self) -> bool {
-        self.count += 1;
-        self.count >= N
+    pub fn record(&mut self, loop_start: Instant, now: Instant) -> bool {
+        let duration = now.checked_duration_since(loop_start).map_or(0, micros);
+        self.histogram.record(duration);
+        self.histogram.count() >= N
     }
 
     #[inline]
-    pub fn value(&self, now: Instant) -> LoopCount {
-        LoopCount {
-            duration: now
-                .checked_duration_since(self.start)
-                .unwrap_or(MicrosDuration::<u64>::micros(100)),
-            count: self.count,
-        }
+    pub fn value(&self) -> LatencySummary {
+        self.histogram.summary()
     }
 
     #[inline]
-    pub fn reset(&mut self, start: Instant) {
-        self.start = start;
-        self.count = 0;
+    pub fn reset(&mut self) {
+        self.histogram.reset();
     }
 }
@@ -1,10 +1,55 @@
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
 use rp2040_hal::timer::Instant;
 
 const SLEEP_AFTER_SECONDS: u64 = 60;
 
+/// How long the key core parks at a time while idle or suspended. A press or an encoder
+/// wakes it right away, the other half is only heard from this often.
+pub const PARKED_SCAN_INTERVAL_MICROS: u32 = 10_000;
+
+/// After a change the key core scans at full speed for this long before it parks again,
+/// the admin core has to get around to waking up first
+pub const PARK_AFTER_CHANGE_MICROS: u64 = 1_000_000;
+
+/// Stands in for no wake measured yet
+const NO_WAKE_LATENCY: u32 = u32::MAX;
+
+/// From the interrupt that woke the parked key core to the first change it scanned
+static WAKE_LATENCY_MICROS: AtomicU32 = AtomicU32::new(NO_WAKE_LATENCY);
+
+/// Mirrors the admin core's sleep state, for the key core to tell the other half
+static ADMIN_AWAKE: AtomicBool = AtomicBool::new(true);
+
+#[inline]
+pub fn admin_awake() -> bool {
+    ADMIN_AWAKE.load(Ordering::Relaxed)
+}
+
+/// Called by the key core when a change comes out of the matrix right after a wake,
+/// `woke_at` is the low half of the timer from [`crate::keyboard::wake::take_woke_at`]
+#[inline]
+pub fn publish_wake_latency(woke_at: u32, now: u32) {
+    WAKE_LATENCY_MICROS.store(now.wrapping_sub(woke_at), Ordering::Relaxed);
+}
+
+/// Shows the last wake latency for the console, `-` before the first
+#[cfg(feature = "serial")]
+pub struct WakeLatency;
+
+#[cfg(feature = "serial")]
+impl core::fmt::Display for WakeLatency {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match WAKE_LATENCY_MICROS.load(Ordering::Relaxed) {
+            NO_WAKE_LATENCY => f.write_str("-"),
+            micros => write!(f, "{micros}us"),
+        }
+    }
+}
+
 pub struct SleepCountdown {
     is_asleep: bool,
     touched_last: Instant,
+    sleep_after_seconds: u64,
 }
 
 impl SleepCountdown {
@@ -12,6 +57,7 @@ impl SleepCountdown {
         Self {
             is_asleep: false,
             touched_last: Instant::from_ticks(0),
+            sleep_after_seconds: SLEEP_AFTER_SECONDS,
         }
     }
 
@@ -19,6 +65,7 @@ impl SleepCountdown {
     pub fn touch(&mut self, now: Instant) {
         self.touched_last = now;
         self.is_asleep = false;
+        ADMIN_AWAKE.store(true, Ordering::Relaxed);
     }
 
     #[inline]
@@ -26,16 +73,59 @@ impl SleepCountdown {
         !self.is_asleep
             && now
                 .checked_duration_since(self.touched_last)
-                .is_some_and(|dur| dur.to_secs() > SLEEP_AFTER_SECONDS)
+                .is_some_and(|dur| dur.to_secs() > self.sleep_after_seconds)
     }
 
     #[inline]
     pub fn set_sleeping(&mut self) {
         self.is_asleep = true;
+        ADMIN_AWAKE.store(false, Ordering::Relaxed);
     }
 
     #[inline]
     pub fn is_awake(&self) -> bool {
         !self.is_asleep
     }
+
+    #[cfg(feature = "serial")]
+    pub fn timeout_seconds(&self) -> u64 {
+        self.sleep_after_seconds
+    }
+
+    #[cfg(feature = "serial")]
+    pub fn set_timeout_seconds(&mut self, seconds: u64) {
+        self.sleep_after_seconds = seconds;
+    }
+}
+
+/// Follows the usb suspend state published by whoever polls the usb device,
+/// so that the admin core only reacts to changes.
+/// Without usb it's never suspended.
+pub struct UsbSuspendWatch {
+    suspended: bool,
+}
+
+impl UsbSuspendWatch {
+    pub const fn new() -> Self {
+        Self { suspended: false }
+    }
+
+    /// Returns the new state if it changed since last check
+    #[inline]
+    pub fn check(&mut self) -> Option<bool> {
+        #[cfg(any(feature = "hiddev", feature = "serial"))]
+        let suspended = crate::runtime::shared::usb::usb_suspended();
+        #[cfg(not(any(feature = "hiddev", feature = "serial")))]
+        let suspended = false;
+        if suspended == self.suspended {
+            return None;
+        }
+        self.suspended = suspended;
+        Some(suspended)
+    }
+
+    #[inline]
+    pub fn is_suspended(&self) -> bool {
+        self.suspended
+    }
 }
@@ -0,0 +1,31 @@
+//! This half's event trace, see [`rp2040_kbd_lib::trace`]. Recorded on the key core and its
+//! interrupts, dumped by the console. Without a console it's still there for a debugger.
+use rp2040_kbd_lib::trace::{TraceEvent, TraceRing};
+
+/// A bit over a second of typing at full speed, at 8 bytes an entry
+const TRACE_LEN: usize = 256;
+
+static TRACE: TraceRing<TRACE_LEN> = TraceRing::new();
+
+/// Timestamped with the low half of the timer's micros
+#[inline]
+pub fn record(event: TraceEvent) {
+    // The usb interrupt records too, it can't be let in halfway through an entry
+    critical_section::with(|_cs| {
+        // Safety: Reading the raw timer has no side effects
+        let at = unsafe { (*liatris::pac::TIMER::ptr()).timerawl().read().bits() };
+        TRACE.record(at, event);
+    });
+}
+
+/// Oldest first, entries overwritten while reading are skipped
+#[cfg(feature = "serial")]
+pub fn entries() -> impl Iterator<Item = rp2040_kbd_lib::trace::TraceEntry> {
+    TRACE.entries()
+}
+
+#[cfg(feature = "serial")]
+#[inline]
+pub fn recorded() -> u32 {
+    TRACE.recorded()
+}
@@ -1,3 +1,5 @@
+use core::sync::atomic::{AtomicBool, Ordering};
+
 pub struct SyncBus(
     core::cell::OnceCell<usb_device::bus::UsbBusAllocator<liatris::hal::usb::UsbBus>>,
 );
@@ -40,11 +42,57 @@ static USB_SERIAL: SyncUnsafeOnce<crate::keyboard::usb_serial::UsbSerial> = Sync
 #[cfg(feature = "serial")]
 static USB_OUTPUT: SyncUnsafeOnce<bool> = SyncUnsafeOnce::new();
 
+/// Only taken inside a critical section, those lock out the other core too. The admin core
+/// pushes reports while the key core's usb interrupt may still be polling.
 #[cfg(feature = "hiddev")]
 static USB_HIDDEV: SyncUnsafeOnce<crate::hid::usb_hiddev::UsbHiddev> = SyncUnsafeOnce::new();
 
 static mut USB_CONTROL_BUFFER: [u8; 256] = [0u8; 256];
 
+/// Set by whichever core polls the usb device
+static USB_SUSPENDED: AtomicBool = AtomicBool::new(false);
+
+#[inline]
+pub fn usb_suspended() -> bool {
+    USB_SUSPENDED.load(Ordering::Relaxed)
+}
+
+/// Lock leds as last set by the host, see [`rp2040_kbd_lib::split::CAPS_LOCK`]
+#[cfg(feature = "hiddev")]
+static HOST_LEDS: core::sync::atomic::AtomicU8 = core::sync::atomic::AtomicU8::new(0);
+
+#[cfg(feature = "hiddev")]
+#[inline]
+pub fn host_leds() -> u8 {
+    HOST_LEDS.load(Ordering::Relaxed)
+}
+
+/// A report was handed to the hid class, waiting for its in transfer to complete.
+/// Only touched inside a critical section or the usb interrupt.
+#[cfg(feature = "hiddev")]
+static REPORT_IN_FLIGHT: AtomicBool = AtomicBool::new(false);
+/// Low half of the timer when the host picked the last report up, once set
+#[cfg(feature = "hiddev")]
+static REPORT_POLLED: AtomicBool = AtomicBool::new(false);
+#[cfg(feature = "hiddev")]
+static REPORT_POLLED_AT: core::sync::atomic::AtomicU32 = core::sync::atomic::AtomicU32::new(0);
+
+/// When the host picked up the last accepted report, once per report
+#[cfg(feature = "hiddev")]
+pub fn take_report_polled_at() -> Option<u32> {
+    critical_section::with(|_cs| {
+        REPORT_POLLED.load(Ordering::Relaxed).then(|| {
+            REPORT_POLLED.store(false, Ordering::Relaxed);
+            REPORT_POLLED_AT.load(Ordering::Relaxed)
+        })
+    })
+}
+
+#[inline]
+pub(crate) fn set_usb_suspended(suspended: bool) {
+    USB_SUSPENDED.store(suspended, Ordering::Relaxed);
+}
+
 #[cfg(feature = "serial")]
 #[expect(static_mut_refs)]
 pub unsafe fn init_usb(allocator: usb_device::bus::UsbBusAllocator<liatris::hal::usb::UsbBus>) {
@@ -81,6 +129,16 @@ pub struct UsbGuard<'a> {
     _pd: core::marker::PhantomData<&'a ()>,
 }
 
+#[cfg(feature = "serial")]
+impl UsbGuard<'_> {
+    /// Write a console response, unlike the `Write` impl this doesn't care about `output`
+    pub fn respond(&mut self, args: core::fmt::Arguments) {
+        if let Some(serial) = self.serial.as_mut() {
+            let _ = core::fmt::Write::write_fmt(&mut **serial, args);
+        }
+    }
+}
+
 #[cfg(feature = "serial")]
 impl core::fmt::Write for UsbGuard<'_> {
     fn write_str(&mut self, s: &str) -> core::fmt::Result {
@@ -111,15 +169,106 @@ pub unsafe fn init_usb_hiddev(
 #[cfg(feature = "hiddev")]
 pub unsafe fn try_push_report(keyboard_report: &usbd_hid::descriptor::KeyboardReport) -> bool {
     critical_section::with(|_cs| {
-        USB_HIDDEV
+        let accepted = USB_HIDDEV
             .as_mut()
-            .is_some_and(|hid| hid.try_submit_report(keyboard_report))
+            .is_some_and(|hid| hid.try_submit_report(keyboard_report));
+        if accepted {
+            REPORT_IN_FLIGHT.store(true, Ordering::Relaxed);
+        }
+        accepted
     })
 }
 
+/// Hands the host an empty report before a reset, from the admin core while the key core is
+/// stuck. Gives up after `timeout_micros`, a key core stuck inside a critical section blocks
+/// this until the watchdog resets the chip instead.
+#[cfg(feature = "hiddev")]
+pub unsafe fn release_all_keys(timer: rp2040_hal::Timer, timeout_micros: u64) -> bool {
+    let release = usbd_hid::descriptor::KeyboardReport {
+        modifier: 0,
+        reserved: 0,
+        leds: 0,
+        keycodes: [0; 6],
+    };
+    let start = timer.get_counter();
+    let in_time = || {
+        timer
+            .get_counter()
+            .checked_duration_since(start)
+            .is_some_and(|dur| dur.to_micros() < timeout_micros)
+    };
+    // Whatever the key core sent last may not have been picked up
+    let _ = take_report_polled_at();
+    while !try_push_report(&release) {
+        if !in_time() {
+            return false;
+        }
+    }
+    // The usb interrupt is the key core's, with that one stuck too the report still goes
+    // out when the host asks, it just won't be seen
+    while in_time() {
+        if take_report_polled_at().is_some() {
+            return true;
+        }
+    }
+    false
+}
+
 #[cfg(feature = "hiddev")]
 pub unsafe fn hiddev_interrupt_poll() {
-    if let Some(hid) = USB_HIDDEV.as_mut() {
-        hid.poll();
+    critical_section::with(|_cs| {
+        let Some(hid) = USB_HIDDEV.as_mut() else {
+            return;
+        };
+        let sent = hid.poll();
+        // Other interrupts, sof, setup or suspend, don't mean the host has the report
+        let picked_up = sent && REPORT_IN_FLIGHT.load(Ordering::Relaxed);
+        if picked_up {
+            REPORT_IN_FLIGHT.store(false, Ordering::Relaxed);
+            REPORT_POLLED_AT.store(
+                (*liatris::pac::TIMER::ptr()).timerawl().read().bits(),
+                Ordering::Relaxed,
+            );
+            REPORT_POLLED.store(true, Ordering::Relaxed);
+        }
+        crate::runtime::shared::trace::record(rp2040_kbd_lib::trace::TraceEvent::UsbPoll {
+            picked_up,
+        });
+        set_usb_suspended(hid.is_suspended());
+        HOST_LEDS.store(hid.host_leds(), Ordering::Relaxed);
+    });
+}
+
+/// Poll the device outside of the interrupt, for finding out if a host is attached
+/// before the key core takes over
+#[cfg(feature = "hiddev")]
+pub unsafe fn poll_host_configured() -> bool {
+    critical_section::with(|_cs| {
+        USB_HIDDEV.as_mut().is_some_and(|hid| {
+            let _ = hid.poll();
+            hid.is_configured()
+        })
+    })
+}
+
+/// Vbus detection is forced on, so enumeration is what tells if a host is attached,
+/// whichever half the host configures becomes the master.
+#[cfg(feature = "hiddev")]
+pub unsafe fn wait_for_host(timer: rp2040_hal::Timer, timeout_micros: u64) -> bool {
+    let start = timer.get_counter();
+    while timer
+        .get_counter()
+        .checked_duration_since(start)
+        .is_some_and(|dur| dur.to_micros() < timeout_micros)
+    {
+        if poll_host_configured() {
+            return true;
+        }
     }
+    false
+}
+
+#[cfg(feature = "hiddev")]
+pub unsafe fn request_remote_wakeup() -> bool {
+    critical_section::with(|_cs| USB_HIDDEV.as_mut().is_some_and(|hid| hid.remote_wakeup()))
 }
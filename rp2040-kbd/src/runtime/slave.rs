@@ -0,0 +1,447 @@
+use crate::clock::{self, IdleClock};
+use crate::keyboard::oled::slave::SlaveOledDrawer;
+use crate::keyboard::oled::OledHandle;
+use crate::keyboard::power_led::PowerLed;
+use crate::keyboard::split_serial::message_serializer::MessageSerializer;
+use crate::keyboard::split_serial::SplitLink;
+use crate::keyboard::{wake, LocalMatrix, MatrixSink, Side};
+use crate::layer::{layer_to_string, KeymapLayer};
+#[cfg(feature = "serial")]
+use crate::runtime::shared::console::{execute_shared, Console};
+use crate::runtime::shared::cores_slave::{
+    new_shared_queue, pop_message, push_loop_to_admin, push_master_change, push_reboot_and_halt,
+    try_push_touch, Consumer, KeycoreToAdminMessage, Producer,
+};
+use crate::runtime::shared::loop_counter::{micros, LoopCounter};
+#[cfg(feature = "serial")]
+use crate::runtime::shared::sleep::WakeLatency;
+use crate::runtime::shared::sleep::{
+    publish_wake_latency, SleepCountdown, UsbSuspendWatch, PARKED_SCAN_INTERVAL_MICROS,
+    PARK_AFTER_CHANGE_MICROS,
+};
+use crate::runtime::shared::trace;
+use crate::watchdog::{key_core_alive, Supervisor};
+#[cfg(feature = "serial")]
+use core::fmt::Write;
+use rp2040_hal::multicore::{Multicore, Stack};
+use rp2040_hal::rom_data::reset_to_usb_boot;
+use rp2040_hal::{Timer, Watchdog};
+#[cfg(feature = "serial")]
+use rp2040_kbd_lib::cli::Command;
+use rp2040_kbd_lib::histogram::Histogram;
+use rp2040_kbd_lib::matrix::{FramePayload, MatrixUpdate};
+use rp2040_kbd_lib::split::{
+    LinkMonitor, MasterToSlave, SlaveState, HEARTBEAT_INTERVAL_MICROS, LINK_TIMEOUT_MICROS,
+    SNAPSHOT_INTERVAL_MICROS,
+};
+use rp2040_kbd_lib::trace::TraceEvent;
+
+static CORE_1_STACK_AREA: Stack<2048> = Stack::new();
+
+/// Forwards this half's keys to the master, and polls it for what to show
+#[inline(never)]
+pub fn run_slave<'a, B: LocalMatrix + Send + 'static>(
+    mc: &'a mut Multicore<'a>,
+    #[cfg(feature = "serial")] usb_bus: usb_device::bus::UsbBusAllocator<rp2040_hal::usb::UsbBus>,
+    mut oled_handle: OledHandle,
+    uart_driver: SplitLink,
+    buttons: B,
+    power_led_pin: PowerLed,
+    timer: Timer,
+    watchdog: Watchdog,
+) -> ! {
+    #[cfg(feature = "serial")]
+    unsafe {
+        crate::runtime::shared::usb::init_usb(usb_bus);
+    };
+    let cores = mc.cores();
+    let c1 = &mut cores[1];
+    let serializer = MessageSerializer::new(uart_driver, timer);
+    let (producer, consumer) = new_shared_queue();
+    if let Err(_e) = c1.spawn(CORE_1_STACK_AREA.take().unwrap(), move || {
+        run_key_core(serializer, buttons, timer, producer)
+    }) {
+        oled_handle.clear();
+        oled_handle.write(0, "ERROR");
+        oled_handle.write(9, "SPAWN");
+        oled_handle.write(18, "CORE1");
+        oled_handle.write(27, "FAIL");
+        oled_handle.write(36, "BOOT");
+        reset_to_usb_boot(0, 0);
+    }
+    run_admin_core(
+        B::SIDE,
+        oled_handle,
+        consumer,
+        timer,
+        power_led_pin,
+        watchdog,
+    )
+}
+#[expect(clippy::needless_pass_by_value, clippy::too_many_lines)]
+fn run_admin_core(
+    side: Side,
+    oled_handle: OledHandle,
+    consumer: Consumer,
+    timer: Timer,
+    mut power_led_pin: PowerLed,
+    watchdog: Watchdog,
+) -> ! {
+    let mut oled = SlaveOledDrawer::new(oled_handle, side);
+    #[cfg(feature = "serial")]
+    let mut console = Console::new();
+    #[cfg(feature = "serial")]
+    let mut output_all = false;
+    #[cfg(feature = "serial")]
+    let mut has_dumped = false;
+    #[cfg(feature = "serial")]
+    let mut scan_loop = rp2040_kbd_lib::histogram::LatencySummary::default();
+    #[cfg(feature = "serial")]
+    let mut link_latency = rp2040_kbd_lib::histogram::LatencySummary::default();
+    #[cfg(feature = "serial")]
+    let mut total_tx: u32 = 0;
+    let mut sleep = SleepCountdown::new();
+    let mut suspend = UsbSuspendWatch::new();
+    // Detection to the wire, since boot
+    let mut press_latency = Histogram::new();
+    let mut tx: u16 = 0;
+    let mut last_avail = 0;
+    #[cfg(feature = "serial")]
+    let mut layer: Option<KeymapLayer> = None;
+    let mut clock_profile = clock::running();
+    oled.set_clock(clock::system_freq());
+    let mut supervisor = Supervisor::start(watchdog, timer.get_counter());
+    loop {
+        let now = timer.get_counter();
+        // The master releases whatever we had pressed once the link times out
+        supervisor.check(now, || {});
+        let avail = consumer.available();
+        match pop_message(&consumer) {
+            Some(KeycoreToAdminMessage::Loop { scan, link }) if sleep.is_awake() => {
+                #[cfg(feature = "serial")]
+                {
+                    scan_loop = scan;
+                    link_latency = link;
+                }
+                #[cfg(not(feature = "serial"))]
+                let _ = link;
+                oled.update_scan_loop(scan);
+            }
+            Some(KeycoreToAdminMessage::Touch {
+                tx_bytes,
+                forwarded,
+            }) => {
+                #[cfg(feature = "serial")]
+                {
+                    total_tx = total_tx.wrapping_add(u32::from(tx_bytes));
+                }
+                sleep.touch(now);
+                tx += tx_bytes;
+                if tx > 9999 {
+                    tx = tx_bytes;
+                }
+                if let Some(forwarded) = forwarded {
+                    press_latency.record(forwarded);
+                }
+                oled.update_touch(tx, press_latency.summary());
+                if !suspend.is_suspended() {
+                    oled.show();
+                    power_led_pin.turn_on();
+                }
+            }
+            Some(KeycoreToAdminMessage::Master(MasterToSlave::Layer(index))) => {
+                let new_layer = KeymapLayer::from_index(index);
+                #[cfg(feature = "serial")]
+                {
+                    layer = new_layer;
+                }
+                if let Some(new_layer) = new_layer {
+                    oled.update_layer(layer_to_string(new_layer));
+                }
+            }
+            Some(KeycoreToAdminMessage::Master(MasterToSlave::HostLeds(leds))) => {
+                oled.update_locks(leds);
+            }
+            Some(KeycoreToAdminMessage::Master(MasterToSlave::Awake(false))) => {
+                sleep.set_sleeping();
+                oled.hide();
+                power_led_pin.turn_off();
+            }
+            Some(KeycoreToAdminMessage::Master(MasterToSlave::Awake(true))) => {
+                sleep.touch(now);
+                if !suspend.is_suspended() {
+                    oled.show();
+                    power_led_pin.turn_on();
+                }
+            }
+            Some(KeycoreToAdminMessage::Reboot) => {
+                oled.render_boot_msg();
+                reset_to_usb_boot(0, 0);
+            }
+            _ => {}
+        }
+        if avail != last_avail {
+            oled.update_queue(avail);
+            last_avail = avail;
+        }
+        if clock::running() != clock_profile {
+            clock_profile = clock::running();
+            oled.set_clock(clock::system_freq());
+        }
+        match suspend.check() {
+            Some(true) => {
+                sleep.set_sleeping();
+                oled.hide();
+                power_led_pin.turn_off();
+            }
+            Some(false) => {
+                sleep.touch(now);
+                oled.show();
+                power_led_pin.turn_on();
+            }
+            None => {}
+        }
+        if sleep.should_sleep(now) {
+            sleep.set_sleeping();
+            oled.hide();
+            power_led_pin.turn_off();
+        }
+        oled.render();
+        // The host showed up after boot, come back up as the master
+        #[cfg(feature = "hiddev")]
+        if unsafe { crate::runtime::shared::usb::poll_host_configured() } {
+            liatris::hal::pac::SCB::sys_reset();
+        }
+        #[cfg(feature = "serial")]
+        {
+            console.poll(|cmd, usb| {
+                match execute_shared(cmd, usb, &mut power_led_pin, &mut sleep) {
+                    Some(Command::Status) => usb.respond(format_args!(
+                        "side={} role=slave uptime={}s clock={}MHz awake={} suspended={} queue={avail} reset={:?}\r\n",
+                        side.name(),
+                        now.duration_since_epoch().to_secs(),
+                        clock::system_freq().to_MHz(),
+                        sleep.is_awake(),
+                        suspend.is_suspended(),
+                        crate::watchdog::boot_reason(),
+                    )),
+                    Some(Command::Layer) => match layer {
+                        Some(layer) => usb.respond(format_args!(
+                            "layer={layer:?} ({}), from the master\r\n",
+                            layer_to_string(layer)
+                        )),
+                        None => usb.respond(format_args!(
+                            "layer=unknown, nothing heard from the master\r\n"
+                        )),
+                    },
+                    Some(Command::Stats) => usb.respond(format_args!(
+                        "min/p50/p99/max scan={scan_loop} press={} link={link_latency}\r\nwake={} tx={total_tx}\r\n",
+                        press_latency.summary(),
+                        WakeLatency,
+                    )),
+                    Some(Command::ClockSet(_)) => usb.respond(format_args!(
+                        "error: the master switches the clock for both halves, ask it\r\n"
+                    )),
+                    _ => {}
+                }
+                output_all = *usb.output;
+            });
+            if output_all && !has_dumped {
+                let _ = crate::runtime::shared::usb::acquire_usb()
+                    .write_fmt(format_args!("{} side running as slave\r\n", side.name()));
+                has_dumped = true;
+            }
+        }
+    }
+}
+
+/// Sends this half's keys over the link, the master runs them through the keymap
+struct ForwardKeys<'a> {
+    side: Side,
+    serializer: &'a mut MessageSerializer,
+    producer: &'a Producer,
+    reboot: Option<MatrixUpdate>,
+    timer: Timer,
+    /// Longest a change took from detection to being on the wire
+    forwarded: Option<u32>,
+}
+
+impl MatrixSink for ForwardKeys<'_> {
+    #[inline]
+    fn matrix_update(&mut self, update: MatrixUpdate, detected_at: u32) {
+        trace::record(TraceEvent::Matrix {
+            left: self.side == Side::Left,
+            update,
+        });
+        let age = self.timer.get_counter_low().wrapping_sub(detected_at);
+        self.serializer.serialize_matrix_state(update, age);
+        let forwarded = self.timer.get_counter_low().wrapping_sub(detected_at);
+        self.forwarded = Some(self.forwarded.map_or(forwarded, |f| f.max(forwarded)));
+        if self
+            .reboot
+            .is_some_and(|reboot| update.byte() == reboot.byte())
+        {
+            push_reboot_and_halt(self.producer);
+        }
+    }
+}
+
+/// Only for replies that did something, most repeat what the master already said
+#[inline]
+fn trace_reply(msg: MasterToSlave) {
+    trace::record(TraceEvent::LinkReceived(msg.to_byte()));
+}
+
+#[expect(clippy::needless_pass_by_value, clippy::too_many_lines)]
+fn run_key_core<B: LocalMatrix>(
+    mut serializer: MessageSerializer,
+    mut buttons: B,
+    timer: Timer,
+    producer: Producer,
+) -> ! {
+    let mut loop_count: LoopCounter<10_000> = LoopCounter::new();
+    // Poll to answer, since boot
+    let mut link_latency = Histogram::new();
+    buttons.scan_encoder(
+        timer,
+        &mut ForwardKeys {
+            side: B::SIDE,
+            serializer: &mut serializer,
+            producer: &producer,
+            reboot: B::REBOOT_KEY,
+            timer,
+            forwarded: None,
+        },
+    );
+    let mut tx = 0;
+    let mut slave_state = SlaveState::new();
+    let mut idle_clock = IdleClock::new();
+    let mut link = LinkMonitor::new(LINK_TIMEOUT_MICROS);
+    let mut last_poll = timer.get_counter();
+    // Whatever the master thinks is pressed after our reboot, this corrects it
+    serializer.send_snapshot();
+    let mut last_snapshot = timer.get_counter();
+    let mut alarm = {
+        let mut timer = timer;
+        timer.alarm_0().unwrap()
+    };
+    unsafe {
+        liatris::hal::pac::NVIC::unmask(liatris::pac::Interrupt::TIMER_IRQ_0);
+        liatris::hal::pac::NVIC::unmask(liatris::pac::Interrupt::IO_IRQ_BANK0);
+    }
+    let mut last_change = timer.get_counter();
+    let mut woke_at = None;
+    // Kept until the admin core got it, like `tx`
+    let mut forwarded = None;
+    loop {
+        key_core_alive();
+        // The master only talks when polled, park until the next heartbeat at the latest
+        let now = timer.get_counter();
+        if slave_state.awake == Some(false)
+            && now
+                .checked_duration_since(last_change)
+                .is_some_and(|dur| dur.to_micros() >= PARK_AFTER_CHANGE_MICROS)
+        {
+            let until_poll = now.checked_duration_since(last_poll).map_or(0, |dur| {
+                HEARTBEAT_INTERVAL_MICROS.saturating_sub(dur.to_micros())
+            });
+            let micros = u32::try_from(until_poll)
+                .unwrap_or(PARKED_SCAN_INTERVAL_MICROS)
+                .min(PARKED_SCAN_INTERVAL_MICROS);
+            if micros > 0 {
+                wake::reset();
+                buttons.park(&mut alarm, micros, || false);
+                woke_at = wake::take_woke_at();
+            }
+        }
+        let loop_timer = timer.get_counter();
+        // Polls double as heartbeats, the master releases our keys if they stop
+        if loop_timer
+            .checked_duration_since(last_poll)
+            .is_some_and(|dur| dur.to_micros() >= HEARTBEAT_INTERVAL_MICROS)
+        {
+            last_poll = loop_timer;
+            let reply = serializer.poll_master();
+            if reply.is_some() {
+                link.heard(loop_timer.ticks());
+                // Both frames and however long the master took to get to the poll
+                if let Some(dur) = timer.get_counter().checked_duration_since(loop_timer) {
+                    link_latency.record(micros(dur));
+                }
+            }
+            match reply {
+                Some(MasterToSlave::RequestSnapshot) => {
+                    trace_reply(MasterToSlave::RequestSnapshot);
+                    serializer.send_snapshot();
+                    last_snapshot = timer.get_counter();
+                }
+                // The master switches right after sending this, whether or not we do
+                Some(MasterToSlave::Clock(profile)) => {
+                    trace_reply(MasterToSlave::Clock(profile));
+                    buttons.switch_clock(profile, timer);
+                    serializer.hold_off();
+                }
+                Some(msg) => {
+                    if let MasterToSlave::Awake(awake) = msg {
+                        if let Some(profile) = idle_clock.awake(awake) {
+                            buttons.switch_clock(profile, timer);
+                            serializer.hold_off();
+                        }
+                    }
+                    // Only keep the change if the admin core got it, the master repeats
+                    let mut next = slave_state;
+                    if next.apply(msg) {
+                        trace_reply(msg);
+                        if push_master_change(&producer, msg) {
+                            slave_state = next;
+                        }
+                    }
+                }
+                None => {}
+            }
+        }
+        // Whatever the master runs at now, it falls back to the default clock too
+        if link.check(timer.get_counter().ticks()) {
+            buttons.switch_clock(clock::DEFAULT_PROFILE, timer);
+            idle_clock.forget();
+        }
+        if loop_timer
+            .checked_duration_since(last_snapshot)
+            .is_some_and(|dur| dur.to_micros() >= SNAPSHOT_INTERVAL_MICROS)
+        {
+            serializer.send_snapshot();
+            last_snapshot = loop_timer;
+        }
+        let mut forward = ForwardKeys {
+            side: B::SIDE,
+            serializer: &mut serializer,
+            producer: &producer,
+            reboot: B::REBOOT_KEY,
+            timer,
+            forwarded,
+        };
+        let mut changes = buttons.scan_matrix(timer, &mut forward);
+        if buttons.scan_encoder(timer, &mut forward) {
+            changes += 1;
+        }
+        forwarded = forward.forwarded;
+        if changes > 0 {
+            tx += changes;
+            last_change = loop_timer;
+            if let Some(woke_at) = woke_at.take() {
+                publish_wake_latency(woke_at, timer.get_counter_low());
+            }
+        }
+
+        let now = timer.get_counter();
+        if loop_count.record(loop_timer, now)
+            && push_loop_to_admin(&producer, loop_count.value(), link_latency.summary())
+        {
+            loop_count.reset();
+        }
+        if tx > 0 && try_push_touch(&producer, tx, forwarded) {
+            tx = 0;
+            forwarded = None;
+        }
+    }
+}
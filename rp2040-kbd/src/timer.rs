@@ -1,3 +1,4 @@
+use liatris::pac::interrupt;
 use rp2040_hal::Timer;
 
 pub fn wait_nanos(timer: Timer, nanos: u64) {
@@ -11,3 +12,40 @@ pub fn wait_nanos(timer: Timer, nanos: u64) {
         }
     }
 }
+
+/// Parks the calling core until `micros` have passed, or until `wake_early` says otherwise.
+/// `TIMER_IRQ_0` has to be unmasked on the calling core, or the core will never wake up.
+pub fn park_micros(
+    alarm: &mut rp2040_hal::timer::Alarm0,
+    micros: u32,
+    wake_early: impl Fn() -> bool,
+) {
+    use rp2040_hal::timer::Alarm;
+    if alarm
+        .schedule(rp2040_hal::fugit::MicrosDurationU32::micros(micros))
+        .is_err()
+    {
+        return;
+    }
+    alarm.enable_interrupt();
+    // Any taken interrupt sets the event register, if the alarm fires before we get to `wfe`
+    // it returns immediately instead of hanging
+    while !alarm.finished() && !wake_early() {
+        rp2040_hal::arch::wfe();
+    }
+    alarm.disable_interrupt();
+    let _ = alarm.cancel();
+}
+
+/// Todo: Change to 'expect' after [this PR](https://github.com/rust-embedded/cortex-m/pull/557)
+/// Only acknowledges the alarm used for parking the key core
+#[interrupt]
+#[allow(clippy::allow_attributes, non_snake_case)]
+fn TIMER_IRQ_0() {
+    // Safety: Write-clear register, only touching alarm 0 which is only used by `park_micros`
+    unsafe {
+        (*rp2040_hal::pac::TIMER::ptr())
+            .intr()
+            .write_with_zero(|w| w.alarm_0().clear_bit_by_one());
+    }
+}
@@ -0,0 +1,118 @@
+//! Both cores are supervised through the watchdog. The key core counts its loops, the admin
+//! core feeds the watchdog only while that count moves. A stalled key core gets a reset from
+//! the admin core, a stalled admin core stops feeding and the watchdog resets the chip.
+use core::sync::atomic::{AtomicU32, AtomicU8, Ordering};
+use liatris::pac;
+use rp2040_hal::fugit::{ExtU32, Instant};
+use rp2040_hal::watchdog::ScratchRegister;
+use rp2040_hal::Watchdog;
+use rp2040_kbd_lib::reset::ResetReason;
+
+/// Longest the admin core can go without feeding, it blocks on the oled and the console
+const WATCHDOG_PERIOD_MICROS: u32 = 2_000_000;
+
+/// The key core parks for at most 10ms, anything this long is stuck
+const KEY_CORE_STALL_MICROS: u64 = 500_000;
+
+/// Scratch 4 to 7 are the bootrom's
+const REASON_SCRATCH: ScratchRegister = ScratchRegister::Scratch0;
+
+/// Only the key core writes
+static KEY_CORE_LOOPS: AtomicU32 = AtomicU32::new(0);
+
+/// [`ResetReason`] by its scratch code, 0 for the ones without one
+static BOOT_REASON_SCRATCH: AtomicU32 = AtomicU32::new(0);
+/// Timed out, forced, as read at boot
+static BOOT_REASON_BITS: AtomicU8 = AtomicU8::new(0);
+
+/// Once per key core loop
+#[inline]
+pub fn key_core_alive() {
+    let loops = KEY_CORE_LOOPS.load(Ordering::Relaxed);
+    KEY_CORE_LOOPS.store(loops.wrapping_add(1), Ordering::Relaxed);
+}
+
+/// Stops a watchdog left running from before a reset that didn't go through it, and reads
+/// why the chip reset. Has to be called early at boot, before anything takes long.
+pub fn boot(watchdog: &mut Watchdog) -> ResetReason {
+    watchdog.disable();
+    // Safety: Read only, the watchdog driver has no accessor for it
+    let reason = unsafe { (*pac::WATCHDOG::ptr()).reason().read() };
+    let (timed_out, forced) = (reason.timer().bit_is_set(), reason.force().bit_is_set());
+    let scratch = watchdog.read_scratch(REASON_SCRATCH);
+    watchdog.write_scratch(REASON_SCRATCH, 0);
+    BOOT_REASON_SCRATCH.store(scratch, Ordering::Relaxed);
+    BOOT_REASON_BITS.store(
+        u8::from(timed_out) | u8::from(forced) << 1,
+        Ordering::Relaxed,
+    );
+    ResetReason::from_watchdog(timed_out, forced, scratch)
+}
+
+/// Why the chip last reset, as read by [`boot`]
+#[cfg(feature = "serial")]
+pub fn boot_reason() -> ResetReason {
+    let bits = BOOT_REASON_BITS.load(Ordering::Relaxed);
+    ResetReason::from_watchdog(
+        bits & 1 != 0,
+        bits & 2 != 0,
+        BOOT_REASON_SCRATCH.load(Ordering::Relaxed),
+    )
+}
+
+/// Resets everything but the oscillators, like `Watchdog::start` sets it up, ram and the
+/// reason are kept for the next boot
+pub fn reset(reason: ResetReason) -> ! {
+    // Safety: Nothing else runs after this, the scratch register is only written at boot
+    unsafe {
+        let watchdog = &*pac::WATCHDOG::ptr();
+        if let Some(scratch) = reason.scratch() {
+            watchdog.scratch0().write(|w| w.bits(scratch));
+        }
+        (*pac::PSM::ptr()).wdsel().write_with_zero(|w| {
+            w.bits(0x0001_ffff);
+            w.xosc().clear_bit();
+            w.rosc().clear_bit();
+            w
+        });
+        watchdog.ctrl().modify(|_, w| w.trigger().set_bit());
+    }
+    loop {
+        core::sync::atomic::compiler_fence(Ordering::SeqCst);
+    }
+}
+
+/// Lives on the admin core
+pub struct Supervisor {
+    watchdog: Watchdog,
+    loops: u32,
+    alive_at: Instant<u64, 1, 1_000_000>,
+}
+
+impl Supervisor {
+    pub fn start(mut watchdog: Watchdog, now: Instant<u64, 1, 1_000_000>) -> Self {
+        watchdog.start(WATCHDOG_PERIOD_MICROS.micros());
+        Self {
+            watchdog,
+            loops: KEY_CORE_LOOPS.load(Ordering::Relaxed),
+            alive_at: now,
+        }
+    }
+
+    /// Once per admin loop, feeds the watchdog if the key core moved since the last call.
+    /// A stalled key core gets `release_keys` called, then a reset.
+    pub fn check(&mut self, now: Instant<u64, 1, 1_000_000>, release_keys: impl FnOnce()) {
+        let loops = KEY_CORE_LOOPS.load(Ordering::Relaxed);
+        if loops != self.loops {
+            self.loops = loops;
+            self.alive_at = now;
+            self.watchdog.feed();
+        } else if now
+            .checked_duration_since(self.alive_at)
+            .is_some_and(|dur| dur.to_micros() >= KEY_CORE_STALL_MICROS)
+        {
+            release_keys();
+            reset(ResetReason::KeyCoreStalled);
+        }
+    }
+}